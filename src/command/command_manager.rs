@@ -1,17 +1,257 @@
-use crate::command::commands::Command;
+use std::error::Error;
+use std::time::{Duration, Instant};
+use crate::command::commands::{AffectedLines, Command};
 use crate::document::text_document::TextDocument;
 
+/// How long typing may pause before the next keystroke starts a new undo step.
+const COALESCE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Kinds of single-character edits that are undone together when they follow
+/// each other, so undo takes back a word rather than a keystroke.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Coalesce {
+    /// Typed characters, up to the end of a word.
+    Typing,
+    /// The Backspace key, removing the character before the caret.
+    Backspace,
+    /// The Delete key, removing the character after the caret.
+    Delete,
+}
+
+/// Commands undone and redone together, in the order they were executed.
+struct UndoStep {
+    commands: Vec<Box<dyn Command>>,
+    /// The kind of single-character edits the step holds, if it holds such edits.
+    kind: Option<Coalesce>,
+}
+
 pub struct CommandManager {
-    //TODO: add undo/redo stacks
+    undo_stack: Vec<UndoStep>,
+    redo_stack: Vec<UndoStep>,
+    /// The kind of edits the newest undo step holds while more can join it,
+    /// and when it last grew.
+    open_step: Option<(Coalesce, Instant)>,
 }
 
 impl CommandManager {
     pub fn new() -> Self {
-        CommandManager {}
+        CommandManager {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            open_step: None,
+        }
+    }
+
+    /// Executes `command` on the document as a new undo step and returns the lines it changed.
+    pub fn execute(&mut self, command: Box<dyn Command>, data: &mut TextDocument) -> Result<AffectedLines, Box<dyn Error>> {
+        self.open_step = None;
+        self.push(command, data, true, None)
+    }
+
+    /// Executes `command` as part of the newest undo step, for edits made of
+    /// several commands such as replacing a selection.
+    pub fn execute_in_last_step(&mut self, command: Box<dyn Command>, data: &mut TextDocument) -> Result<AffectedLines, Box<dyn Error>> {
+        self.open_step = None;
+        let new_step = self.undo_stack.is_empty();
+        self.push(command, data, new_step, None)
+    }
+
+    /// Executes a single-character edit of the given kind. It joins the newest
+    /// undo step if that holds edits of the same kind made shortly before and
+    /// the step wasn't closed in between.
+    pub fn execute_coalesced(&mut self, command: Box<dyn Command>, kind: Coalesce, data: &mut TextDocument) -> Result<AffectedLines, Box<dyn Error>> {
+        let joins = matches!(self.open_step, Some((open_kind, last_edit)) if open_kind == kind && last_edit.elapsed() < COALESCE_TIMEOUT);
+        let affected = self.push(command, data, !joins || self.undo_stack.is_empty(), Some(kind))?;
+        self.open_step = Some((kind, Instant::now()));
+        Ok(affected)
+    }
+
+    /// Keeps further edits out of the newest undo step, e.g. after the caret
+    /// was moved or a word was finished.
+    pub fn close_step(&mut self) {
+        self.open_step = None;
+    }
+
+    /// Executes `command` and adds it to the newest undo step, or starts a new
+    /// step of the given kind with it.
+    fn push(&mut self, mut command: Box<dyn Command>, data: &mut TextDocument, new_step: bool, kind: Option<Coalesce>) -> Result<AffectedLines, Box<dyn Error>> {
+        let affected = command.execute(data)?;
+        self.add(command, new_step, kind);
+        Ok(affected)
     }
 
-    pub fn execute(&mut self, mut command: Box<dyn Command>, data: &mut TextDocument) {
-        command.execute(data);
+    /// Adds an executed command to the newest undo step, or starts a new step
+    /// of the given kind with it.
+    fn add(&mut self, command: Box<dyn Command>, new_step: bool, kind: Option<Coalesce>) {
+        // A new edit makes the undone steps unreachable
+        self.redo_stack.clear();
+        match self.undo_stack.last_mut() {
+            Some(step) if !new_step => step.commands.push(command),
+            _ => self.undo_stack.push(UndoStep { commands: vec![command], kind }),
+        }
+    }
+
+    /// Executes the commands of the newest undo step again as a new step, placed
+    /// at `caret` the way they were placed at the caret they were first made at:
+    /// typing is typed again, Backspace removes as many characters before the
+    /// caret, other deletions remove as many after it. Returns the lines that
+    /// changed and where the caret belongs, or None if there is nothing to
+    /// repeat or the step doesn't fit at `caret`: it would start before the
+    /// document, or there is nothing left to delete.
+    pub fn repeat_last_step(&mut self, caret: usize, data: &mut TextDocument) -> Result<Option<(AffectedLines, usize)>, Box<dyn Error>> {
+        self.open_step = None;
+        let Some(step) = self.undo_stack.last() else {
+            return Ok(None);
+        };
+        let Some(first) = step.commands.first() else {
+            return Ok(None);
+        };
+        let mut delta = caret as isize - first.pos() as isize;
+        let mut commands = Vec::new();
+        for command in &step.commands {
+            let Some(mut repeated) = command.moved(delta) else {
+                break;
+            };
+            // Keep what was repeated up to a command that doesn't fit, e.g. a
+            // deletion past the end of the document
+            let Ok(affected) = repeated.execute(data) else {
+                break;
+            };
+            // The next command follows on from where this one left the caret,
+            // which moves by less for a deletion of fewer bytes
+            delta = repeated.caret_after_execute() as isize - command.caret_after_execute() as isize;
+            commands.push((repeated, affected));
+        }
+        let kind = step.kind;
 
+        let mut result: Option<(AffectedLines, usize)> = None;
+        for (i, (command, affected)) in commands.into_iter().enumerate() {
+            let affected = result.map_or(affected, |(lines, _)| lines.union(affected));
+            result = Some((affected, command.caret_after_execute()));
+            self.add(command, i == 0, kind);
+        }
+        Ok(result)
     }
-}
\ No newline at end of file
+
+    /// Undoes the newest undo step. Returns the lines that changed and where
+    /// the caret belongs, or None if there is nothing to undo.
+    pub fn undo(&mut self, data: &mut TextDocument) -> Result<Option<(AffectedLines, usize)>, Box<dyn Error>> {
+        self.open_step = None;
+        let Some(mut step) = self.undo_stack.pop() else {
+            return Ok(None);
+        };
+        let mut result: Option<(AffectedLines, usize)> = None;
+        for command in step.commands.iter_mut().rev() {
+            let affected = command.undo(data)?;
+            let affected = result.map_or(affected, |(lines, _)| lines.union(affected));
+            result = Some((affected, command.caret_after_undo()));
+        }
+        self.redo_stack.push(step);
+        Ok(result)
+    }
+
+    /// Executes the most recently undone step again. Returns the lines that
+    /// changed and where the caret belongs, or None if there is nothing to redo.
+    pub fn redo(&mut self, data: &mut TextDocument) -> Result<Option<(AffectedLines, usize)>, Box<dyn Error>> {
+        self.open_step = None;
+        let Some(mut step) = self.redo_stack.pop() else {
+            return Ok(None);
+        };
+        let mut result: Option<(AffectedLines, usize)> = None;
+        for command in step.commands.iter_mut() {
+            let affected = command.execute(data)?;
+            let affected = result.map_or(affected, |(lines, _)| lines.union(affected));
+            result = Some((affected, command.caret_after_execute()));
+        }
+        self.undo_stack.push(step);
+        Ok(result)
+    }
+
+    /// Forgets all undo and redo steps, for when the document was replaced.
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.open_step = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::commands::{DeleteCommand, InsertCommand};
+
+    #[test]
+    fn repeats_typing_at_caret() {
+        let mut document = TextDocument::from_text("xyz");
+        let mut manager = CommandManager::new();
+        for (pos, ch) in [(0, "a"), (1, "b")] {
+            let command = Box::new(InsertCommand::new(pos, ch.to_string()));
+            manager.execute_coalesced(command, Coalesce::Typing, &mut document).unwrap();
+        }
+        let (_, caret) = manager.repeat_last_step(5, &mut document).unwrap().unwrap();
+        assert_eq!(document.get_content(), "abxyzab");
+        assert_eq!(caret, 7);
+
+        // The repeated typing is undone as one step
+        manager.undo(&mut document).unwrap();
+        assert_eq!(document.get_content(), "abxyz");
+    }
+
+    #[test]
+    fn repeats_backspace_run_by_characters() {
+        let mut document = TextDocument::from_text("ééxy");
+        let mut manager = CommandManager::new();
+        for pos in [5, 4] {
+            let command = Box::new(DeleteCommand::backward(pos, 1));
+            manager.execute_coalesced(command, Coalesce::Backspace, &mut document).unwrap();
+        }
+        assert_eq!(document.get_content(), "éé");
+        let (_, caret) = manager.repeat_last_step(4, &mut document).unwrap().unwrap();
+        assert_eq!(document.get_content(), "");
+        assert_eq!(caret, 0);
+    }
+
+    #[test]
+    fn repeated_backspace_over_wide_character_removes_one_character() {
+        let mut document = TextDocument::from_text("abcé");
+        let mut manager = CommandManager::new();
+        manager.execute_coalesced(Box::new(DeleteCommand::backward(3, 2)), Coalesce::Backspace, &mut document).unwrap();
+        let (_, caret) = manager.repeat_last_step(2, &mut document).unwrap().unwrap();
+        assert_eq!(document.get_content(), "ac");
+        assert_eq!(caret, 1);
+    }
+
+    #[test]
+    fn repeats_delete_run_by_characters() {
+        let mut document = TextDocument::from_text("abééc");
+        let mut manager = CommandManager::new();
+        for _ in 0..2 {
+            let command = Box::new(DeleteCommand::new(0, 1));
+            manager.execute_coalesced(command, Coalesce::Delete, &mut document).unwrap();
+        }
+        assert_eq!(document.get_content(), "ééc");
+        let (_, caret) = manager.repeat_last_step(0, &mut document).unwrap().unwrap();
+        assert_eq!(document.get_content(), "c");
+        assert_eq!(caret, 0);
+
+        // A run longer than the rest of the document removes what is there
+        manager.repeat_last_step(0, &mut document).unwrap();
+        assert_eq!(document.get_content(), "");
+        assert!(manager.repeat_last_step(0, &mut document).unwrap().is_none());
+    }
+
+    #[test]
+    fn repeats_replaced_selection() {
+        let mut document = TextDocument::from_text("one ééé");
+        let mut manager = CommandManager::new();
+        manager.execute(Box::new(DeleteCommand::new(0, 3)), &mut document).unwrap();
+        manager.execute_in_last_step(Box::new(InsertCommand::new(0, "1".to_string())), &mut document).unwrap();
+        assert_eq!(document.get_content(), "1 ééé");
+
+        let (_, caret) = manager.repeat_last_step(2, &mut document).unwrap().unwrap();
+        assert_eq!(document.get_content(), "1 1");
+        assert_eq!(caret, 3);
+        manager.undo(&mut document).unwrap();
+        assert_eq!(document.get_content(), "1 ééé");
+    }
+}
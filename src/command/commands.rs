@@ -1,9 +1,64 @@
+use std::error::Error;
+use std::ops::Range;
 use crate::document::text_document::TextDocument;
 
+/// Lines changed by a command, numbered as they are after the change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AffectedLines {
+    pub first: usize,
+    pub last: usize,
+    /// Set when lines were added or removed, so every line after `last` moved too.
+    pub lines_moved: bool,
+}
+
+impl AffectedLines {
+    /// Returns lines covering both `self` and `other`, for several commands applied in a row.
+    pub fn union(self, other: AffectedLines) -> AffectedLines {
+        AffectedLines {
+            first: self.first.min(other.first),
+            last: self.last.max(other.last),
+            lines_moved: self.lines_moved || other.lines_moved,
+        }
+    }
+}
+
 pub trait Command {
-    pub fn execute(&self, data: &mut TextDocument);
+    /// Applies the command to the document and returns the lines it changed.
+    fn execute(&mut self, data: &mut TextDocument) -> Result<AffectedLines, Box<dyn Error>>;
+
+    /// Reverts the command, which must be the last one applied to the
+    /// document, and returns the lines that changed.
+    fn undo(&mut self, data: &mut TextDocument) -> Result<AffectedLines, Box<dyn Error>>;
+
+    /// Returns where the caret belongs after the command was executed.
+    fn caret_after_execute(&self) -> usize;
+
+    /// Returns where the caret belongs after the command was undone.
+    fn caret_after_undo(&self) -> usize;
+
+    /// Returns the offset the command was made from: where the text was
+    /// inserted or removed, or the end of the text removed with Backspace.
+    fn pos(&self) -> usize;
+
+    /// Returns an unexecuted copy of the command that is made from `delta`
+    /// bytes further on, for repeating it elsewhere, or None if that is before
+    /// the start of the document. A deletion's copy removes as many characters
+    /// as the deletion did, whatever their length in bytes.
+    fn moved(&self, delta: isize) -> Option<Box<dyn Command>>;
+}
 
-    //TODO: add undo method for all commands
+/// Returns the lines of `text` after it was inserted at `pos`.
+fn inserted_lines(data: &TextDocument, pos: usize, text: &str) -> AffectedLines {
+    let first = data.line_from_offset(pos);
+    let last = data.line_from_offset(pos + text.len());
+    AffectedLines { first, last, lines_moved: last > first }
+}
+
+/// Returns the line that remains after `text` was removed at `pos`.
+fn removed_lines(data: &TextDocument, pos: usize, text: &str) -> AffectedLines {
+    // The lines the removed text spanned are joined into one
+    let line = data.line_from_offset(pos);
+    AffectedLines { first: line, last: line, lines_moved: text.contains('\n') }
 }
 
 pub struct InsertCommand {
@@ -18,23 +73,115 @@ impl InsertCommand {
 }
 
 impl Command for InsertCommand {
-    fn execute(&self, data: &mut TextDocument) {
-        todo!();
+    fn execute(&mut self, data: &mut TextDocument) -> Result<AffectedLines, Box<dyn Error>> {
+        data.insert(self.pos, &self.text)?;
+        Ok(inserted_lines(data, self.pos, &self.text))
+    }
+
+    fn undo(&mut self, data: &mut TextDocument) -> Result<AffectedLines, Box<dyn Error>> {
+        data.delete(self.pos, self.text.len())?;
+        Ok(removed_lines(data, self.pos, &self.text))
+    }
+
+    fn caret_after_execute(&self) -> usize {
+        self.pos + self.text.len()
+    }
+
+    fn caret_after_undo(&self) -> usize {
+        self.pos
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn moved(&self, delta: isize) -> Option<Box<dyn Command>> {
+        let pos = self.pos.checked_add_signed(delta)?;
+        Some(Box::new(InsertCommand::new(pos, self.text.clone())))
     }
 }
 
 pub struct DeleteCommand {
     pub pos: usize,
     pub len: usize,
+    /// Set for the Backspace key, which removes the text before the caret.
+    backward: bool,
+    /// Set for a copy repeating a deletion elsewhere: the number of characters
+    /// to remove from `pos`, or up to it if `backward` is set. The byte range
+    /// is worked out when the copy is first executed.
+    chars: Option<usize>,
+    /// The text removed by the last execution, put back by undo.
+    removed: String,
 }
 
 impl DeleteCommand {
     pub fn new(pos: usize, len: usize) -> Self {
-        DeleteCommand { pos, len }
+        DeleteCommand { pos, len, backward: false, chars: None, removed: String::new() }
+    }
+
+    /// Creates the deletion of the `len` bytes at `pos` made with Backspace,
+    /// with the caret after them.
+    pub fn backward(pos: usize, len: usize) -> Self {
+        DeleteCommand { backward: true, ..DeleteCommand::new(pos, len) }
+    }
+}
+
+/// Returns the byte range of up to `chars` characters of `text` that start at
+/// `pos`, or end there if `backward` is set. An offset inside a character
+/// counts as the start of that character.
+fn char_range(text: &str, pos: usize, chars: usize, backward: bool) -> Range<usize> {
+    let mut pos = pos.min(text.len());
+    while !text.is_char_boundary(pos) {
+        pos -= 1;
+    }
+    if backward {
+        let start = text[..pos].char_indices().rev().take(chars).last().map_or(pos, |(i, _)| i);
+        start..pos
+    } else {
+        let end = text[pos..].char_indices().nth(chars).map_or(text.len(), |(i, _)| pos + i);
+        pos..end
     }
 }
+
 impl Command for DeleteCommand {
-    fn execute(&self, data: &mut TextDocument) {
-        todo!();
+    fn execute(&mut self, data: &mut TextDocument) -> Result<AffectedLines, Box<dyn Error>> {
+        if let Some(chars) = self.chars.take() {
+            let range = char_range(data.get_content(), self.pos, chars, self.backward);
+            if range.is_empty() {
+                return Err(format!("Nothing to delete at {}", self.pos).into());
+            }
+            self.pos = range.start;
+            self.len = range.len();
+        }
+        self.removed = data.delete(self.pos, self.len)?;
+        Ok(removed_lines(data, self.pos, &self.removed))
+    }
+
+    fn undo(&mut self, data: &mut TextDocument) -> Result<AffectedLines, Box<dyn Error>> {
+        data.insert(self.pos, &self.removed)?;
+        Ok(inserted_lines(data, self.pos, &self.removed))
+    }
+
+    fn caret_after_execute(&self) -> usize {
+        self.pos
+    }
+
+    fn caret_after_undo(&self) -> usize {
+        self.pos + self.removed.len()
     }
-}
\ No newline at end of file
+
+    fn pos(&self) -> usize {
+        if self.backward { self.pos + self.removed.len() } else { self.pos }
+    }
+
+    fn moved(&self, delta: isize) -> Option<Box<dyn Command>> {
+        let pos = self.pos().checked_add_signed(delta)?;
+        Some(Box::new(DeleteCommand {
+            pos,
+            len: 0,
+            backward: self.backward,
+            chars: Some(self.removed.chars().count()),
+            removed: String::new(),
+        }))
+    }
+}
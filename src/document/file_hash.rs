@@ -0,0 +1,83 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use windows::Win32::Security::Cryptography::{
+    BCryptCreateHash, BCryptDestroyHash, BCryptFinishHash, BCryptHashData, BCRYPT_ALG_HANDLE,
+    BCRYPT_HASH_HANDLE, BCRYPT_MD5_ALG_HANDLE, BCRYPT_SHA1_ALG_HANDLE, BCRYPT_SHA256_ALG_HANDLE,
+};
+
+/// Size of the blocks the file is read and hashed in.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Hex-encoded digests of a file's on-disk content.
+pub struct FileHashes {
+    pub md5: String,
+    pub sha1: String,
+    pub sha256: String,
+}
+
+/// A running CNG hash computation.
+struct Hasher {
+    handle: BCRYPT_HASH_HANDLE,
+    digest_len: usize,
+}
+
+impl Hasher {
+    /// Uses the algorithm pseudo-handles, so no provider has to be opened or closed.
+    fn new(algorithm: BCRYPT_ALG_HANDLE, digest_len: usize) -> Result<Self, Box<dyn Error>> {
+        let mut handle = BCRYPT_HASH_HANDLE(std::ptr::null_mut());
+        unsafe { BCryptCreateHash(algorithm, &mut handle, None, None, 0) }.ok()?;
+        Ok(Hasher { handle, digest_len })
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        unsafe { BCryptHashData(self.handle, data, 0) }.ok()?;
+        Ok(())
+    }
+
+    /// Returns the digest as lowercase hex.
+    fn finish(self) -> Result<String, Box<dyn Error>> {
+        let mut digest = vec![0u8; self.digest_len];
+        unsafe { BCryptFinishHash(self.handle, &mut digest, 0) }.ok()?;
+        Ok(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+}
+
+impl Drop for Hasher {
+    fn drop(&mut self) {
+        let _ = unsafe { BCryptDestroyHash(self.handle) };
+    }
+}
+
+/// Computes the MD5, SHA-1 and SHA-256 digests of a file in a single pass.
+/// `progress` is called after every chunk with the bytes hashed so far and the
+/// file size; it is meant for hashing large files on a background thread.
+pub fn hash_file(path: &Path, mut progress: impl FnMut(u64, u64)) -> Result<FileHashes, Box<dyn Error>> {
+    let mut file = File::open(path)?;
+    let total = file.metadata()?.len();
+
+    let mut md5 = Hasher::new(BCRYPT_MD5_ALG_HANDLE, 16)?;
+    let mut sha1 = Hasher::new(BCRYPT_SHA1_ALG_HANDLE, 20)?;
+    let mut sha256 = Hasher::new(BCRYPT_SHA256_ALG_HANDLE, 32)?;
+
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut done = 0u64;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        md5.update(&buffer[..read])?;
+        sha1.update(&buffer[..read])?;
+        sha256.update(&buffer[..read])?;
+        done += read as u64;
+        progress(done, total);
+    }
+
+    Ok(FileHashes {
+        md5: md5.finish()?,
+        sha1: sha1.finish()?,
+        sha256: sha256.finish()?,
+    })
+}
@@ -1,26 +1,291 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs;
 use std::io::Read;
 use std::error::Error;
+use std::os::windows::io::AsRawHandle;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::os::windows::ffi::OsStrExt;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::Storage::FileSystem::{
+    GetFileAttributesW, FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS, FILE_ATTRIBUTE_RECALL_ON_OPEN,
+    INVALID_FILE_ATTRIBUTES,
+};
+use windows::Win32::System::IO::CancelSynchronousIo;
+use crate::document::local_history;
 use crate::document::text_document::TextDocument;
 
+/// How long a file operation may take before it is given up on, e.g. when a
+/// network share stops responding.
+const IO_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The saves of one path. Its writes are made one at a time, in the order the
+/// saves were started, so a slow write can't land after a newer one.
+struct SaveQueue {
+    path: PathBuf,
+    /// Number of the latest save started for the path.
+    latest: AtomicU64,
+    /// Held while the file is written.
+    writing: Mutex<()>,
+}
+
+/// Save queues of the paths with saves in progress.
+static SAVE_QUEUES: Mutex<Vec<Arc<SaveQueue>>> = Mutex::new(Vec::new());
+
+/// Returns the save queue of `path`, dropping the queues no save uses anymore.
+fn save_queue(path: &Path) -> Arc<SaveQueue> {
+    let mut queues = SAVE_QUEUES.lock().unwrap_or_else(PoisonError::into_inner);
+    queues.retain(|queue| Arc::strong_count(queue) > 1);
+    if let Some(queue) = queues.iter().find(|queue| queue.path == path) {
+        return queue.clone();
+    }
+    let queue = Arc::new(SaveQueue {
+        path: path.to_path_buf(),
+        latest: AtomicU64::new(0),
+        writing: Mutex::new(()),
+    });
+    queues.push(queue.clone());
+    queue
+}
+
+/// Runs a file operation on a worker thread and waits at most `timeout` for it.
+/// An operation that doesn't finish in time (typically on an unreachable UNC
+/// path or mapped drive) is reported as an error and cancelled: the flag passed
+/// to it is set and its pending I/O is cancelled, so the UI thread is never
+/// blocked indefinitely. Errors cross the thread boundary as strings.
+fn run_with_timeout<T: Send + 'static>(
+    timeout: Duration,
+    description: String,
+    operation: impl FnOnce(&AtomicBool) -> Result<T, String> + Send + 'static,
+) -> Result<T, Box<dyn Error>> {
+    let (sender, receiver) = mpsc::channel();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let worker_cancelled = cancelled.clone();
+    let worker = thread::spawn(move || {
+        // The receiver is gone if the wait already timed out
+        let _ = sender.send(operation(&worker_cancelled));
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result.map_err(|e| e.into()),
+        Err(_) => {
+            cancelled.store(true, Ordering::SeqCst);
+            // Fails if the worker isn't waiting for I/O right now; the flag covers that
+            let _ = unsafe { CancelSynchronousIo(HANDLE(worker.as_raw_handle())) };
+            Err(format!("Timed out after {} seconds while {}", timeout.as_secs(), description).into())
+        }
+    }
+}
+
 /// Loads the content of a file into a string using OpenOptions.
 /// Creates the file if it doesn't exist.
-pub fn load(path: &Path) -> Result<String, Box<dyn Error>> {
+///
+/// Invalid UTF-8 byte sequences don't fail the load: each one is replaced with
+/// U+FFFD and its byte offset in the returned string is reported, so the damage
+/// can be shown to the user.
+///
+/// The file is read on a worker thread with a timeout (see run_with_timeout).
+/// Cloud placeholders may take longer to download; use `read_in_background` for those.
+pub fn load(path: &Path) -> Result<(String, Vec<usize>), Box<dyn Error>> {
+    let path = path.to_path_buf();
+    let description = format!("reading {}", path.display());
+    run_with_timeout(IO_TIMEOUT, description, move |_| read_file(&path).map_err(|e| e.to_string()))
+}
+
+/// A file being read by `read_in_background`.
+pub struct BackgroundRead {
+    cancelled: Arc<AtomicBool>,
+    worker: JoinHandle<()>,
+}
+
+impl BackgroundRead {
+    /// Gives up on the read: its pending I/O is cancelled and its result dropped.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        // Fails if the worker isn't waiting for I/O right now; the flag covers that
+        let _ = unsafe { CancelSynchronousIo(HANDLE(self.worker.as_raw_handle())) };
+    }
+}
+
+/// Reads a file like `load` on a worker thread, without a timeout, e.g. a
+/// cloud placeholder that is downloaded first. `on_done` is called on the
+/// worker thread with the result, unless the read was cancelled.
+pub fn read_in_background(
+    path: PathBuf,
+    on_done: impl FnOnce(Result<(String, Vec<usize>), String>) + Send + 'static,
+) -> BackgroundRead {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let worker_cancelled = cancelled.clone();
+    let worker = thread::spawn(move || {
+        let result = read_file(&path).map_err(|e| e.to_string());
+        if !worker_cancelled.load(Ordering::SeqCst) {
+            on_done(result);
+        }
+    });
+    BackgroundRead { cancelled, worker }
+}
+
+fn read_file(path: &Path) -> Result<(String, Vec<usize>), Box<dyn Error>> {
     let mut file = fs::OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
         .open(path)?;
 
-    let mut content = String::new();
-    file.read_to_string(&mut content)?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+
+    Ok(decode_utf8_lossy(&bytes))
+}
+
+/// Returns true for cloud files (e.g. OneDrive "online-only" files) whose content
+/// isn't stored locally and is downloaded when the file is read. Only the
+/// attributes are queried, which doesn't trigger the download, on a worker
+/// thread with a timeout (see run_with_timeout).
+pub fn is_cloud_placeholder(path: &Path) -> Result<bool, Box<dyn Error>> {
+    let path_wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let description = format!("querying the attributes of {}", path.display());
+    run_with_timeout(IO_TIMEOUT, description, move |_| {
+        let attributes = unsafe { GetFileAttributesW(PCWSTR(path_wide.as_ptr())) };
+        Ok(attributes != INVALID_FILE_ATTRIBUTES
+            && attributes & (FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS.0 | FILE_ATTRIBUTE_RECALL_ON_OPEN.0) != 0)
+    })
+}
+
+/// Decodes the content of a file that wasn't read by `load`, e.g. a dropped
+/// virtual file: UTF-16 if it starts with a UTF-16 byte order mark, UTF-8
+/// otherwise. Returns the text and the offsets of its replacement characters
+/// like `load`. The byte order mark is kept as the first character.
+pub fn decode(bytes: &[u8]) -> (String, Vec<usize>) {
+    let from_bytes: fn([u8; 2]) -> u16 = match bytes {
+        [0xFF, 0xFE, ..] => u16::from_le_bytes,
+        [0xFE, 0xFF, ..] => u16::from_be_bytes,
+        _ => return decode_utf8_lossy(bytes),
+    };
+    let mut content = String::with_capacity(bytes.len() / 2);
+    let mut replacements = Vec::new();
+    let pairs = bytes.chunks_exact(2);
+    let odd_byte = !pairs.remainder().is_empty();
+    for decoded in char::decode_utf16(pairs.map(|pair| from_bytes([pair[0], pair[1]]))) {
+        if decoded.is_err() {
+            replacements.push(content.len());
+        }
+        content.push(decoded.unwrap_or(char::REPLACEMENT_CHARACTER));
+    }
+    // An odd trailing byte is half of a code unit
+    if odd_byte {
+        replacements.push(content.len());
+        content.push(char::REPLACEMENT_CHARACTER);
+    }
+    (content, replacements)
+}
+
+/// Decodes UTF-8 like `String::from_utf8_lossy`, additionally returning the
+/// offsets of the inserted replacement characters.
+fn decode_utf8_lossy(bytes: &[u8]) -> (String, Vec<usize>) {
+    let mut content = String::with_capacity(bytes.len());
+    let mut replacements = Vec::new();
+
+    for chunk in bytes.utf8_chunks() {
+        content.push_str(chunk.valid());
+        if !chunk.invalid().is_empty() {
+            replacements.push(content.len());
+            content.push(char::REPLACEMENT_CHARACTER);
+        }
+    }
+    (content, replacements)
+}
+
+/// The byte order mark, kept as the first character of documents loaded with one.
+pub const BYTE_ORDER_MARK: char = '\u{FEFF}';
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    CrLf,
+    Lf,
+}
+
+/// Returns `text` with every line break converted to `line_ending`.
+pub fn convert_line_endings(text: &str, line_ending: LineEnding) -> String {
+    let lf_text = text.replace("\r\n", "\n");
+    match line_ending {
+        LineEnding::Lf => lf_text,
+        LineEnding::CrLf => lf_text.replace('\n', "\r\n"),
+    }
+}
 
-    Ok(content)
+/// Splits a path to an NTFS alternate data stream (`notes.txt:summary`, or
+/// `notes.txt:summary:$DATA`) into the path of the file and the stream name.
+/// Plain paths are returned unchanged with no stream name.
+///
+/// Streams are opened with the same std::fs calls as files, which also add the
+/// `\\?\` prefix to paths longer than MAX_PATH. Only code that looks at the
+/// file name, such as file type detection, needs the split.
+pub fn split_data_stream(path: &Path) -> (PathBuf, Option<String>) {
+    // ':' can't appear in a file name, and a drive ("C:") isn't part of it
+    let Some(file_name) = path.file_name().map(|name| name.to_string_lossy()) else {
+        return (path.to_path_buf(), None);
+    };
+    match file_name.split_once(':') {
+        Some((base_name, stream)) => {
+            let stream_name = stream.strip_suffix(":$DATA").unwrap_or(stream);
+            (path.with_file_name(base_name), Some(stream_name.to_string()))
+        }
+        None => (path.to_path_buf(), None),
+    }
 }
 
 /// Saves the content of the TextDocument to the specified path.
+/// A copy is also recorded in the local history; failing to do so doesn't fail the save.
+///
+/// The file is written and recorded on a worker thread from a snapshot of the
+/// document, with a timeout (see run_with_timeout). Saves of the same path are
+/// written one after another; one still waiting when a newer save of the path
+/// starts is skipped, as the newer save writes newer content.
 pub fn save(doc: &TextDocument, path: &Path) -> Result<(), Box<dyn Error>> {
-    fs::write(path, doc.get_content())?; 
-    Ok(())
-}
\ No newline at end of file
+    let snapshot = doc.snapshot();
+    let target = path.to_path_buf();
+    let description = format!("writing {}", target.display());
+    let queue = save_queue(path);
+    let number = queue.latest.fetch_add(1, Ordering::SeqCst) + 1;
+    run_with_timeout(IO_TIMEOUT, description, move |cancelled| {
+        let _writing = queue.writing.lock().unwrap_or_else(PoisonError::into_inner);
+        if cancelled.load(Ordering::SeqCst) {
+            return Err("The save was cancelled".to_string());
+        }
+        if queue.latest.load(Ordering::SeqCst) != number {
+            return Ok(());
+        }
+        fs::write(&target, snapshot.get_content()).map_err(|e| e.to_string())?;
+        if let Err(e) = local_history::record_snapshot(&target, snapshot.get_content()) {
+            eprintln!("Failed to record local history snapshot: {}", e);
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_utf16_after_its_byte_order_mark() {
+        assert_eq!(decode(&[0xFF, 0xFE, b'h', 0, b'i', 0]), ("\u{FEFF}hi".to_string(), vec![]));
+        assert_eq!(decode(&[0xFE, 0xFF, 0, b'h', 0, b'i']), ("\u{FEFF}hi".to_string(), vec![]));
+    }
+
+    #[test]
+    fn reports_replaced_utf16_code_units() {
+        // An unpaired surrogate, then half of a code unit
+        let (text, replacements) = decode(&[0xFF, 0xFE, b'a', 0, 0x00, 0xD8, b'b', 0, b'c']);
+        assert_eq!(text, "\u{FEFF}a\u{FFFD}b\u{FFFD}");
+        assert_eq!(replacements, vec![4, 8]);
+    }
+
+    #[test]
+    fn decodes_other_content_as_utf8() {
+        assert_eq!(decode(b"caf\xC3\xA9 \xFF"), ("caf\u{E9} \u{FFFD}".to_string(), vec![6]));
+    }
+}
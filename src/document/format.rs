@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+/// Spaces per nesting level the pretty-printers indent by unless told otherwise.
+pub const DEFAULT_INDENT: usize = 4;
+
+/// The widest indentation accepted, so a bad setting can't blow up the output.
+const MAX_INDENT: usize = 16;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FormatStyle {
+    /// One element per line, nested content indented.
+    Pretty,
+    /// All insignificant whitespace removed.
+    Minify,
+}
+
+/// A document formatter for one language. Output uses '\n' line breaks.
+/// Implement this to plug another formatter (built-in or external) into the registry.
+pub trait Formatter {
+    fn format(&self, text: &str, style: FormatStyle) -> Result<String, Box<dyn Error>>;
+}
+
+/// Maps file extensions to formatters.
+pub struct FormatterRegistry {
+    by_extension: HashMap<String, Box<dyn Formatter>>,
+}
+
+impl FormatterRegistry {
+    /// Creates a registry with the built-in JSON and XML formatters, which
+    /// indent by `indent` spaces per level.
+    pub fn with_builtin(indent: usize) -> Self {
+        let mut registry = FormatterRegistry { by_extension: HashMap::new() };
+        registry.register("json", Box::new(JsonFormatter::new(indent)));
+        for extension in ["xml", "xsd", "xsl", "xslt", "svg", "csproj", "props", "targets", "config"] {
+            registry.register(extension, Box::new(XmlFormatter::new(indent)));
+        }
+        registry
+    }
+
+    /// Registers (or replaces) the formatter used for files with `extension`.
+    pub fn register(&mut self, extension: &str, formatter: Box<dyn Formatter>) {
+        self.by_extension.insert(extension.to_ascii_lowercase(), formatter);
+    }
+
+    /// Picks a formatter from the file extension, or for untitled documents
+    /// by sniffing the first non-whitespace character of the text.
+    pub fn for_document(&self, path: Option<&Path>, text: &str) -> Option<&dyn Formatter> {
+        let extension = match path.and_then(|path| path.extension()) {
+            Some(extension) => extension.to_string_lossy().to_ascii_lowercase(),
+            None => match text.trim_start().chars().next() {
+                Some('{') | Some('[') => "json".to_string(),
+                Some('<') => "xml".to_string(),
+                _ => return None,
+            },
+        };
+        self.by_extension.get(&extension).map(|formatter| formatter.as_ref())
+    }
+}
+
+fn push_newline_indent(out: &mut String, depth: usize, indent: usize) {
+    out.push('\n');
+    out.extend(std::iter::repeat_n(' ', depth * indent));
+}
+
+/// Built-in JSON formatter. It checks string termination and bracket nesting,
+/// but otherwise re-spaces the tokens as they are.
+pub struct JsonFormatter {
+    /// Spaces per nesting level.
+    indent: usize,
+}
+
+impl JsonFormatter {
+    pub fn new(indent: usize) -> Self {
+        JsonFormatter { indent: indent.min(MAX_INDENT) }
+    }
+}
+
+impl Formatter for JsonFormatter {
+    fn format(&self, text: &str, style: FormatStyle) -> Result<String, Box<dyn Error>> {
+        let mut out = String::with_capacity(text.len());
+        let mut stack: Vec<char> = Vec::new();
+        let mut chars = text.chars().peekable();
+
+        while let Some(ch) = chars.next() {
+            match ch {
+                '"' => {
+                    out.push('"');
+                    let mut terminated = false;
+                    while let Some(ch) = chars.next() {
+                        out.push(ch);
+                        match ch {
+                            '\\' => {
+                                let escaped = chars.next().ok_or("Unterminated string")?;
+                                out.push(escaped);
+                            }
+                            '"' => {
+                                terminated = true;
+                                break;
+                            }
+                            _ => {}
+                        }
+                    }
+                    if !terminated {
+                        return Err("Unterminated string".into());
+                    }
+                }
+                '{' | '[' => {
+                    out.push(ch);
+                    stack.push(if ch == '{' { '}' } else { ']' });
+                    // Keep empty objects and arrays on one line
+                    while chars.peek().is_some_and(|next| next.is_whitespace()) {
+                        chars.next();
+                    }
+                    if chars.peek() == stack.last() {
+                        out.push(chars.next().unwrap());
+                        stack.pop();
+                    } else if style == FormatStyle::Pretty {
+                        push_newline_indent(&mut out, stack.len(), self.indent);
+                    }
+                }
+                '}' | ']' => {
+                    if stack.pop() != Some(ch) {
+                        return Err(format!("Unexpected '{}'", ch).into());
+                    }
+                    if style == FormatStyle::Pretty {
+                        push_newline_indent(&mut out, stack.len(), self.indent);
+                    }
+                    out.push(ch);
+                }
+                ',' => {
+                    out.push(',');
+                    if style == FormatStyle::Pretty {
+                        push_newline_indent(&mut out, stack.len(), self.indent);
+                    }
+                }
+                ':' => {
+                    out.push(':');
+                    if style == FormatStyle::Pretty {
+                        out.push(' ');
+                    }
+                }
+                ch if ch.is_whitespace() => {} // Insignificant outside strings
+                ch => out.push(ch),
+            }
+        }
+
+        if let Some(missing) = stack.pop() {
+            return Err(format!("Missing '{}'", missing).into());
+        }
+        if style == FormatStyle::Pretty {
+            out.push('\n');
+        }
+        Ok(out)
+    }
+}
+
+enum XmlToken<'a> {
+    /// `<name ...>`
+    Open(&'a str),
+    /// `</name>`
+    Close(&'a str),
+    /// Self-closing tags, comments, CDATA, processing instructions and declarations.
+    Standalone(&'a str),
+    Text(&'a str),
+}
+
+impl<'a> XmlToken<'a> {
+    /// Returns the token as it is written in the document.
+    fn source(&self) -> &'a str {
+        match self {
+            XmlToken::Open(text) | XmlToken::Close(text) | XmlToken::Standalone(text) | XmlToken::Text(text) => text,
+        }
+    }
+}
+
+/// Splits XML into tags and text, keeping quoted attribute values intact.
+fn tokenize_xml(text: &str) -> Result<Vec<XmlToken<'_>>, Box<dyn Error>> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            tokens.push(XmlToken::Text(&rest[..end]));
+            rest = &rest[end..];
+            continue;
+        }
+
+        // Constructs with their own terminators
+        let special_end = [("<!--", "-->"), ("<![CDATA[", "]]>"), ("<?", "?>")]
+            .iter()
+            .find(|(start, _)| rest.starts_with(start))
+            .map(|(_, end)| *end);
+        let len = if let Some(end) = special_end {
+            rest.find(end).ok_or("Unterminated markup")? + end.len()
+        } else {
+            let mut quote: Option<char> = None;
+            let end = rest.char_indices().skip(1).find(|&(_, ch)| match quote {
+                Some(open) => {
+                    if ch == open {
+                        quote = None;
+                    }
+                    false
+                }
+                None => {
+                    if ch == '"' || ch == '\'' {
+                        quote = Some(ch);
+                    }
+                    ch == '>'
+                }
+            });
+            end.ok_or("Unterminated tag")?.0 + 1
+        };
+
+        let tag = &rest[..len];
+        tokens.push(if special_end.is_some() || tag.starts_with("<!") || tag.ends_with("/>") {
+            XmlToken::Standalone(tag)
+        } else if tag.starts_with("</") {
+            XmlToken::Close(tag)
+        } else {
+            XmlToken::Open(tag)
+        });
+        rest = &rest[len..];
+    }
+    Ok(tokens)
+}
+
+/// Returns the element name of an opening or closing tag.
+fn xml_tag_name(tag: &str) -> &str {
+    tag.trim_start_matches("</")
+        .trim_start_matches('<')
+        .split(|ch: char| ch.is_whitespace() || ch == '>' || ch == '/')
+        .next()
+        .unwrap_or("")
+}
+
+/// Returns whether the element opened by `tag` asks for its whitespace to be
+/// kept, with `xml:space="preserve"` or by being XHTML's `<pre>`.
+fn xml_preserves_space(tag: &str) -> bool {
+    xml_tag_name(tag) == "pre"
+        || ["xml:space=\"preserve\"", "xml:space='preserve'"].iter().any(|attribute| tag.contains(attribute))
+}
+
+/// Where an element ends, and whether it holds text or CDATA of its own.
+/// Whitespace in such mixed content is part of the text.
+#[derive(Clone, Copy)]
+struct XmlElement {
+    close: usize,
+    has_text: bool,
+}
+
+/// Matches the opening and closing tags. Returns the element opened at the
+/// index of each opening tag.
+fn xml_elements(tokens: &[XmlToken]) -> Result<Vec<Option<XmlElement>>, Box<dyn Error>> {
+    let mut elements = vec![None; tokens.len()];
+    // Index, name and whether text was found, of the elements not closed yet
+    let mut open_elements: Vec<(usize, &str, bool)> = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        let is_text = match token {
+            XmlToken::Open(tag) => {
+                open_elements.push((index, xml_tag_name(tag), false));
+                false
+            }
+            XmlToken::Close(tag) => {
+                match open_elements.pop() {
+                    Some((open, name, has_text)) if name == xml_tag_name(tag) => {
+                        elements[open] = Some(XmlElement { close: index, has_text });
+                    }
+                    _ => return Err(format!("Unexpected closing tag {}", tag).into()),
+                }
+                false
+            }
+            XmlToken::Text(text) => !text.trim().is_empty(),
+            XmlToken::Standalone(tag) => tag.starts_with("<![CDATA["),
+        };
+        if let (true, Some(element)) = (is_text, open_elements.last_mut()) {
+            element.2 = true;
+        }
+    }
+    if let Some((_, name, _)) = open_elements.pop() {
+        return Err(format!("Element <{}> is not closed", name).into());
+    }
+    Ok(elements)
+}
+
+/// Built-in XML formatter. Text that is only whitespace between tags is
+/// dropped. Elements holding text, and elements asking for their whitespace
+/// to be kept, are written as they are.
+pub struct XmlFormatter {
+    /// Spaces per nesting level.
+    indent: usize,
+}
+
+impl XmlFormatter {
+    pub fn new(indent: usize) -> Self {
+        XmlFormatter { indent: indent.min(MAX_INDENT) }
+    }
+}
+
+impl Formatter for XmlFormatter {
+    fn format(&self, text: &str, style: FormatStyle) -> Result<String, Box<dyn Error>> {
+        let tokens = tokenize_xml(text)?;
+        let elements = xml_elements(&tokens)?;
+        let mut out = String::with_capacity(text.len());
+        let mut depth = 0;
+        let mut i = 0;
+
+        while i < tokens.len() {
+            match &tokens[i] {
+                XmlToken::Text(text) => {
+                    // Whitespace between tags is insignificant. Other text
+                    // here lies outside of any element and is kept.
+                    if !text.trim().is_empty() {
+                        if style == FormatStyle::Pretty {
+                            push_newline_indent(&mut out, depth, self.indent);
+                        }
+                        out.push_str(text);
+                    }
+                }
+                XmlToken::Open(tag) => {
+                    if style == FormatStyle::Pretty {
+                        push_newline_indent(&mut out, depth, self.indent);
+                    }
+                    let kept = elements[i].filter(|element| element.has_text || xml_preserves_space(tag));
+                    if let Some(element) = kept {
+                        for token in &tokens[i..=element.close] {
+                            out.push_str(token.source());
+                        }
+                        i = element.close + 1;
+                        continue;
+                    }
+                    out.push_str(tag);
+                    depth += 1;
+                }
+                XmlToken::Close(tag) => {
+                    depth -= 1;
+                    if style == FormatStyle::Pretty {
+                        push_newline_indent(&mut out, depth, self.indent);
+                    }
+                    out.push_str(tag);
+                }
+                XmlToken::Standalone(tag) => {
+                    if style == FormatStyle::Pretty {
+                        push_newline_indent(&mut out, depth, self.indent);
+                    }
+                    out.push_str(tag);
+                }
+            }
+            i += 1;
+        }
+
+        // The pretty-printer starts every token on a new line; drop the leading one
+        let mut out = out.trim_start_matches('\n').to_string();
+        if style == FormatStyle::Pretty {
+            out.push('\n');
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(text: &str, style: FormatStyle) -> String {
+        JsonFormatter::new(DEFAULT_INDENT).format(text, style).unwrap()
+    }
+
+    fn xml(text: &str, style: FormatStyle) -> String {
+        XmlFormatter::new(DEFAULT_INDENT).format(text, style).unwrap()
+    }
+
+    #[test]
+    fn json_pretty_prints() {
+        let expected = "{\n    \"a\": 1,\n    \"b\": [\n        1,\n        \"x, y\"\n    ]\n}\n";
+        assert_eq!(json(r#"{"a":1,"b":[1,"x, y"]}"#, FormatStyle::Pretty), expected);
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let text = r#"{ "name": "a \"quoted\" {value}", "list": [1, 2, {"nested": [true, null]}] }"#;
+        let pretty = json(text, FormatStyle::Pretty);
+        let minified = json(text, FormatStyle::Minify);
+        assert_eq!(json(&pretty, FormatStyle::Minify), minified);
+        assert_eq!(json(&minified, FormatStyle::Pretty), pretty);
+        assert_eq!(json(&pretty, FormatStyle::Pretty), pretty);
+    }
+
+    #[test]
+    fn json_keeps_empty_objects_and_arrays_on_one_line() {
+        assert_eq!(json("{ }", FormatStyle::Pretty), "{}\n");
+        assert_eq!(json("[\n]", FormatStyle::Pretty), "[]\n");
+        assert_eq!(json(r#"{"a": {}, "b": [ ]}"#, FormatStyle::Pretty), "{\n    \"a\": {},\n    \"b\": []\n}\n");
+        assert_eq!(json(r#"{"a": {}, "b": [ ]}"#, FormatStyle::Minify), r#"{"a":{},"b":[]}"#);
+    }
+
+    #[test]
+    fn json_reports_errors() {
+        let formatter = JsonFormatter::new(DEFAULT_INDENT);
+        for text in [r#"{"a": "b}"#, r#"["a\"#, r#"{"a": 1"#, r#"{"a": 1]"#] {
+            assert!(formatter.format(text, FormatStyle::Pretty).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn json_uses_the_indent_given() {
+        let formatter = JsonFormatter::new(2);
+        assert_eq!(formatter.format("[[1]]", FormatStyle::Pretty).unwrap(), "[\n  [\n    1\n  ]\n]\n");
+    }
+
+    #[test]
+    fn json_accepts_crlf_input() {
+        let text = "{\r\n\t\"a\": [1,\r\n 2]\r\n}\r\n";
+        assert_eq!(json(text, FormatStyle::Pretty), json(&text.replace("\r\n", "\n"), FormatStyle::Pretty));
+    }
+
+    #[test]
+    fn xml_pretty_prints() {
+        let expected = "<?xml version=\"1.0\"?>\n<root>\n    <a x=\"1 > 0\">text</a>\n    <b/>\n    <!-- note -->\n</root>\n";
+        assert_eq!(xml(r#"<?xml version="1.0"?><root><a x="1 > 0">text</a><b/><!-- note --></root>"#, FormatStyle::Pretty), expected);
+    }
+
+    #[test]
+    fn xml_round_trips() {
+        let text = "<root>\n  <item id='1'>one two</item>\n  <group><item/></group>\n</root>";
+        let pretty = xml(text, FormatStyle::Pretty);
+        let minified = xml(text, FormatStyle::Minify);
+        assert_eq!(minified, "<root><item id='1'>one two</item><group><item/></group></root>");
+        assert_eq!(xml(&pretty, FormatStyle::Minify), minified);
+        assert_eq!(xml(&minified, FormatStyle::Pretty), pretty);
+    }
+
+    #[test]
+    fn xml_reports_errors() {
+        let formatter = XmlFormatter::new(DEFAULT_INDENT);
+        for text in ["<a><b></a>", "<a>", "<a x=\"1>", "<!-- open"] {
+            assert!(formatter.format(text, FormatStyle::Pretty).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn xml_keeps_mixed_content_as_it_is() {
+        let text = "<doc>\n  <p>Hello <b>big</b> <i>world</i>!</p>\n  <name>  padded  </name>\n</doc>";
+        assert_eq!(
+            xml(text, FormatStyle::Pretty),
+            "<doc>\n    <p>Hello <b>big</b> <i>world</i>!</p>\n    <name>  padded  </name>\n</doc>\n"
+        );
+        assert_eq!(xml(text, FormatStyle::Minify), "<doc><p>Hello <b>big</b> <i>world</i>!</p><name>  padded  </name></doc>");
+    }
+
+    #[test]
+    fn xml_keeps_preserved_whitespace() {
+        let text = "<doc><code xml:space=\"preserve\">\n  <line/>\n</code><pre>\n  <b/>\n</pre><data><![CDATA[ x ]]>\n</data></doc>";
+        assert_eq!(
+            xml(text, FormatStyle::Minify),
+            "<doc><code xml:space=\"preserve\">\n  <line/>\n</code><pre>\n  <b/>\n</pre><data><![CDATA[ x ]]>\n</data></doc>"
+        );
+    }
+
+    #[test]
+    fn xml_accepts_crlf_input() {
+        let text = "<root>\r\n  <a>x</a>\r\n</root>\r\n";
+        assert_eq!(xml(text, FormatStyle::Pretty), "<root>\n    <a>x</a>\n</root>\n");
+    }
+}
@@ -0,0 +1,150 @@
+/// Largest number of line pairs compared to find the longest common
+/// subsequence of the lines that differ. Larger changes are shown as every
+/// old line removed and every new line added.
+const MAX_LCS_CELLS: usize = 4_000_000;
+
+/// A line of a line-by-line comparison of two texts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Compares `old` and `new` line by line. Lines shared at the start and end
+/// are matched directly, the ones between them by their longest common
+/// subsequence.
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut diff: Vec<DiffLine> = old[..prefix].iter().map(|line| DiffLine::Same(line)).collect();
+    if old_middle.len().saturating_mul(new_middle.len()) <= MAX_LCS_CELLS {
+        diff.extend(lcs_diff(old_middle, new_middle));
+    } else {
+        diff.extend(old_middle.iter().map(|line| DiffLine::Removed(line)));
+        diff.extend(new_middle.iter().map(|line| DiffLine::Added(line)));
+    }
+    diff.extend(old[old.len() - suffix..].iter().map(|line| DiffLine::Same(line)));
+    diff
+}
+
+/// Compares two runs of lines through a table of the lengths of their longest
+/// common subsequences.
+fn lcs_diff<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<DiffLine<'a>> {
+    // lengths[i * width + j] is the length for old[i..] and new[j..]
+    let width = new.len() + 1;
+    let mut lengths = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i * width + j] = if old[i] == new[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::with_capacity(old.len() + new.len());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            diff.push(DiffLine::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            diff.push(DiffLine::Removed(old[i]));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+    diff.extend(old[i..].iter().map(|line| DiffLine::Removed(line)));
+    diff.extend(new[j..].iter().map(|line| DiffLine::Added(line)));
+    diff
+}
+
+/// Lays out the changes of `diff` for reading: removed lines start with `-`,
+/// added ones with `+`, and up to `context` unchanged lines around them with
+/// spaces. `...` marks unchanged lines left out. Returns an empty string if
+/// nothing changed.
+pub fn preview(diff: &[DiffLine], context: usize) -> String {
+    let mut shown = vec![false; diff.len()];
+    for (index, line) in diff.iter().enumerate() {
+        if !matches!(line, DiffLine::Same(_)) {
+            let end = (index + context + 1).min(diff.len());
+            shown[index.saturating_sub(context)..end].fill(true);
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut skipped = false;
+    for (line, &shown) in diff.iter().zip(&shown) {
+        if !shown {
+            skipped = true;
+            continue;
+        }
+        if skipped {
+            lines.push("...".to_string());
+            skipped = false;
+        }
+        lines.push(match line {
+            DiffLine::Same(text) => format!("  {}", text),
+            DiffLine::Removed(text) => format!("- {}", text),
+            DiffLine::Added(text) => format!("+ {}", text),
+        });
+    }
+    if skipped && !lines.is_empty() {
+        lines.push("...".to_string());
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DiffLine::*;
+
+    #[test]
+    fn unchanged_text_has_no_changes() {
+        let diff = diff_lines("a\nb\r\nc", "a\nb\nc\n");
+        assert_eq!(diff, vec![Same("a"), Same("b"), Same("c")]);
+        assert_eq!(preview(&diff, 2), "");
+    }
+
+    #[test]
+    fn changed_lines_are_removed_and_added() {
+        let diff = diff_lines("a\nb\nc\nd\n", "a\nx\nc\nd\ne\n");
+        assert_eq!(diff, vec![Same("a"), Removed("b"), Added("x"), Same("c"), Same("d"), Added("e")]);
+    }
+
+    #[test]
+    fn moved_line_keeps_the_longest_common_run() {
+        let diff = diff_lines("1\n2\n3\n4", "2\n3\n4\n1");
+        assert_eq!(diff, vec![Removed("1"), Same("2"), Same("3"), Same("4"), Added("1")]);
+    }
+
+    #[test]
+    fn empty_texts_are_all_added_or_removed() {
+        assert_eq!(diff_lines("", "a\nb"), vec![Added("a"), Added("b")]);
+        assert_eq!(diff_lines("a", ""), vec![Removed("a")]);
+    }
+
+    #[test]
+    fn preview_shows_context_and_marks_left_out_lines() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9";
+        assert_eq!(preview(&diff_lines(old, new), 1), "...\n  4\n- 5\n+ five\n  6\n...");
+        assert_eq!(preview(&diff_lines(old, new), 10), "  1\n  2\n  3\n  4\n- 5\n+ five\n  6\n  7\n  8\n  9");
+    }
+}
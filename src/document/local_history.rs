@@ -0,0 +1,250 @@
+use std::error::Error;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::document::file_io;
+
+/// Maximum number of snapshots kept for a single file.
+const MAX_SNAPSHOTS_PER_FILE: usize = 50;
+
+/// Highest index of a snapshot among those taken within the same millisecond.
+const MAX_SNAPSHOT_INDEX: u32 = 9999;
+
+/// Snapshots older than this are pruned the next time the file is saved.
+const MAX_SNAPSHOT_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Returns the root of the local history store (`%LOCALAPPDATA%\Jedit\History`).
+fn history_root() -> Option<PathBuf> {
+    std::env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("Jedit").join("History"))
+}
+
+/// 64-bit FNV-1a hash. Used instead of `DefaultHasher` because the directory
+/// names must stay stable across builds.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Returns the directory holding the snapshots of the file at `path`.
+/// The directory is named after the file name plus a hash of the full path,
+/// so two files with the same name in different folders don't collide.
+pub fn history_dir(path: &Path) -> Option<PathBuf> {
+    let root = history_root()?;
+    let full_path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    // Windows paths are case-insensitive
+    let key = full_path.to_string_lossy().to_lowercase();
+    // Alternate data streams ("notes.txt:summary") get their own history, but
+    // ':' isn't valid in the directory name
+    let file_name = path.file_name()?.to_string_lossy().replace(':', "_");
+    Some(root.join(format!("{}-{:016x}", file_name, fnv1a(key.as_bytes()))))
+}
+
+/// Converts days since the Unix epoch into a (year, month, day) civil date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Builds a sortable snapshot name from a point in time, e.g. `20261016-140322.047Z`.
+/// Milliseconds keep two saves within the same second apart.
+fn snapshot_stem(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let secs_of_day = secs.rem_euclid(86400);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}.{:03}Z",
+        year, month, day,
+        secs_of_day / 3600, (secs_of_day / 60) % 60, secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Builds the file name of a snapshot, e.g. `20261016-140322.047Z-0000.txt`.
+/// `index` tells apart snapshots taken within the same millisecond; it always
+/// has four digits so that the names still sort chronologically.
+fn snapshot_name(time: SystemTime, index: u32, extension: &str) -> String {
+    format!("{}-{:04}{}", snapshot_stem(time), index, extension)
+}
+
+/// Writes `content` to a new snapshot in `dir`, named after `time`.
+fn write_snapshot(dir: &Path, time: SystemTime, extension: &str, content: &str) -> Result<PathBuf, Box<dyn Error>> {
+    for index in 0..=MAX_SNAPSHOT_INDEX {
+        let snapshot_path = dir.join(snapshot_name(time, index, extension));
+        match fs::OpenOptions::new().write(true).create_new(true).open(&snapshot_path) {
+            Ok(mut file) => {
+                file.write_all(content.as_bytes())?;
+                return Ok(snapshot_path);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Err("Too many snapshots within the same millisecond".into())
+}
+
+/// Stores a timestamped copy of `content` in the local history of `path`,
+/// then prunes old snapshots.
+pub fn record_snapshot(path: &Path, content: &str) -> Result<(), Box<dyn Error>> {
+    let dir = history_dir(path).ok_or("Local history directory is unavailable")?;
+    fs::create_dir_all(&dir)?;
+
+    // Keep the original extension so the snapshot opens like the original file
+    let (file_path, _) = file_io::split_data_stream(path);
+    let extension = file_path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    write_snapshot(&dir, SystemTime::now(), &extension, content)?;
+
+    prune(&dir)?;
+    Ok(())
+}
+
+/// Returns the snapshots recorded for `path`, newest first.
+pub fn list_snapshots(path: &Path) -> Vec<PathBuf> {
+    let Some(dir) = history_dir(path) else {
+        return Vec::new();
+    };
+    let mut snapshots = snapshots_in(&dir);
+    snapshots.reverse();
+    snapshots
+}
+
+/// Returns when the snapshot at `path` was taken, as the Local History pane
+/// shows it, e.g. `2026-10-16 14:03:22 UTC`. Files not named like snapshots
+/// are shown by their name.
+pub fn snapshot_label(path: &Path) -> String {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let bytes = name.as_bytes();
+    let is_snapshot = bytes.len() >= 15
+        && bytes[..8].iter().all(u8::is_ascii_digit)
+        && bytes[8] == b'-'
+        && bytes[9..15].iter().all(u8::is_ascii_digit);
+    if !is_snapshot {
+        return name;
+    }
+    format!(
+        "{}-{}-{} {}:{}:{} UTC",
+        &name[0..4], &name[4..6], &name[6..8], &name[9..11], &name[11..13], &name[13..15]
+    )
+}
+
+/// Returns the snapshot files in `dir`, oldest first (names sort chronologically).
+fn snapshots_in(dir: &Path) -> Vec<PathBuf> {
+    let mut snapshots: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    snapshots.sort();
+    snapshots
+}
+
+/// Applies the pruning policies: drops snapshots older than `MAX_SNAPSHOT_AGE`
+/// and keeps at most `MAX_SNAPSHOTS_PER_FILE` of the newest ones.
+fn prune(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let now = SystemTime::now();
+    let mut snapshots = snapshots_in(dir);
+
+    snapshots.retain(|snapshot| {
+        let expired = fs::metadata(snapshot)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > MAX_SNAPSHOT_AGE);
+        if expired {
+            let _ = fs::remove_file(snapshot);
+        }
+        !expired
+    });
+
+    if snapshots.len() > MAX_SNAPSHOTS_PER_FILE {
+        let excess = snapshots.len() - MAX_SNAPSHOTS_PER_FILE;
+        for snapshot in &snapshots[..excess] {
+            fs::remove_file(snapshot)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2026-10-16 14:03:22.047 UTC
+    fn sample_time() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(1_792_159_402_047)
+    }
+
+    /// Returns an empty directory of its own for the test called `name`.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("jedit-local-history-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn file_names(snapshots: &[PathBuf]) -> Vec<String> {
+        snapshots
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn names_hold_time_and_fixed_width_index() {
+        assert_eq!(snapshot_stem(sample_time()), "20261016-140322.047Z");
+        assert_eq!(snapshot_name(sample_time(), 0, ".txt"), "20261016-140322.047Z-0000.txt");
+        assert_eq!(snapshot_name(sample_time(), 12, ""), "20261016-140322.047Z-0012");
+        assert_eq!(snapshot_stem(UNIX_EPOCH), "19700101-000000.000Z");
+    }
+
+    #[test]
+    fn labels_show_when_snapshots_were_taken() {
+        let dir = Path::new("History");
+        assert_eq!(snapshot_label(&dir.join("20261016-140322.047Z-0003.txt")), "2026-10-16 14:03:22 UTC");
+        assert_eq!(snapshot_label(&dir.join("notes.txt")), "notes.txt");
+    }
+
+    #[test]
+    fn snapshots_sort_in_the_order_they_were_taken() {
+        let dir = test_dir("order");
+        let earlier = sample_time() - Duration::from_millis(1);
+        let mut written = vec![write_snapshot(&dir, earlier, ".txt", "first").unwrap()];
+        // Clashes past the tenth must not sort before the second
+        for n in 0..12 {
+            written.push(write_snapshot(&dir, sample_time(), ".txt", &n.to_string()).unwrap());
+        }
+        assert_eq!(file_names(&snapshots_in(&dir)), file_names(&written));
+        assert_eq!(fs::read_to_string(&written[12]).unwrap(), "11");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prune_removes_the_oldest_snapshots() {
+        let dir = test_dir("prune");
+        let written: Vec<PathBuf> = (0..MAX_SNAPSHOTS_PER_FILE + 2)
+            .map(|n| write_snapshot(&dir, sample_time(), ".txt", &n.to_string()).unwrap())
+            .collect();
+        prune(&dir).unwrap();
+        assert_eq!(file_names(&snapshots_in(&dir)), file_names(&written[2..]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,2 +1,7 @@
 pub mod text_document;
-pub mod file_io;
\ No newline at end of file
+pub mod file_io;
+pub mod local_history;
+pub mod line_diff;
+pub mod format;
+pub mod file_hash;
+pub mod templates;
@@ -0,0 +1,46 @@
+use std::fs;
+use std::path::PathBuf;
+use windows::Win32::System::SystemInformation::GetLocalTime;
+
+/// Returns the directory users keep their templates in (`%APPDATA%\Jedit\Templates`).
+pub fn templates_dir() -> Option<PathBuf> {
+    std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("Jedit").join("Templates"))
+}
+
+/// Creates the templates directory if needed and returns whether it contains any files.
+pub fn ensure_templates_dir() -> Option<(PathBuf, bool)> {
+    let dir = templates_dir()?;
+    if let Err(e) = fs::create_dir_all(&dir) {
+        eprintln!("Failed to create templates directory: {}", e);
+        return None;
+    }
+    let has_templates = fs::read_dir(&dir)
+        .map(|entries| entries.flatten().any(|entry| entry.path().is_file()))
+        .unwrap_or(false);
+    Some((dir, has_templates))
+}
+
+/// Expands the placeholder variables of a template:
+///
+/// - `{{date}}`: the current local date, e.g. 2026-10-16
+/// - `{{time}}`: the current local time, e.g. 14:03
+/// - `{{author}}`: the user name of the current account
+/// - `{{filename}}`: the name of the new document, i.e. "Untitled"
+///
+/// Unknown placeholders are left as they are.
+pub fn expand(template: &str) -> String {
+    let now = unsafe { GetLocalTime() };
+    let date = format!("{:04}-{:02}-{:02}", now.wYear, now.wMonth, now.wDay);
+    let time = format!("{:02}:{:02}", now.wHour, now.wMinute);
+    let author = std::env::var("USERNAME").unwrap_or_default();
+
+    let variables = [
+        ("{{date}}", date.as_str()),
+        ("{{time}}", time.as_str()),
+        ("{{author}}", author.as_str()),
+        ("{{filename}}", "Untitled"),
+    ];
+    variables
+        .iter()
+        .fold(template.to_string(), |text, (placeholder, value)| text.replace(placeholder, value))
+}
@@ -1,9 +1,17 @@
-use std::{error::Error, path::Path};
+use std::{error::Error, path::Path, sync::Arc};
 use crate::document::file_io;
 
+/// The text and line index are held behind `Arc`s so snapshots can share them;
+/// a mutation only copies a buffer while a snapshot still references it.
 pub struct TextDocument {
-    line_offsets: Vec<usize>,
-    text_buffer: String,
+    line_offsets: Arc<Vec<usize>>,
+    text_buffer: Arc<String>,
+    /// Byte offsets of U+FFFD characters that replaced invalid byte sequences on load.
+    replacement_offsets: Vec<usize>,
+    /// Incremented on every change to the content, so views can tell stale data apart.
+    version: u64,
+    /// Set when the content changed since it was loaded or last saved.
+    modified: bool,
 }
 
 impl TextDocument {
@@ -11,90 +19,410 @@ impl TextDocument {
     /// Creates a new, empty TextDocument.
     pub fn new() -> Self {
         TextDocument {
-            line_offsets: vec![0],
-            text_buffer: String::new(),
+            line_offsets: Arc::new(vec![0]),
+            text_buffer: Arc::new(String::new()),
+            replacement_offsets: Vec::new(),
+            version: 0,
+            modified: false,
         }
     }
 
+    /// Creates a document holding `text`, for tests.
+    #[cfg(test)]
+    pub fn from_text(text: &str) -> Self {
+        let mut document = TextDocument::new();
+        document.set_content(text.to_string());
+        document
+    }
+
     /// Recalculates line offsets based on the current text_buffer.
     /// Assumes line_offsets starts with `vec![0]`.
     fn init_line_offsets(&mut self) -> Result<(), Box<dyn Error>> {
-        self.line_offsets.truncate(1);
+        let line_offsets = Arc::make_mut(&mut self.line_offsets);
+        line_offsets.truncate(1);
 
-        for (i, ch) in self.text_buffer.char_indices() {
-            if ch == '\n' {
-                // Record the offset *after* the newline character
-                self.line_offsets.push(i + 1);
-            }
-        }
+        // '\n' is never part of a multi-byte UTF-8 sequence, so scanning the raw
+        // bytes with memchr (SIMD accelerated) finds exactly the line breaks.
+        // Record the offset *after* each newline character.
+        line_offsets
+            .extend(memchr::memchr_iter(b'\n', self.text_buffer.as_bytes()).map(|i| i + 1));
         // Note: Doesn't explicitly handle files without trailing newline,
         // but getline logic correctly handles the last line.
         Ok(())
     }
 
     /// Initializes the document by loading content from a file path.
-    /// Clears existing content before loading.
+    /// Replaces the existing content only once the file was read, so a failed
+    /// read leaves the document as it was.
     pub fn init(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let (text, replacement_offsets) = file_io::load(path)?;
+        self.init_loaded(text, replacement_offsets)
+    }
+
+    /// Initializes the document with the content of a file that was already
+    /// read, with the offsets of its replacement characters (see file_io::load).
+    pub fn init_loaded(&mut self, text: String, replacement_offsets: Vec<usize>) -> Result<(), Box<dyn Error>> {
         self.clear();
-        self.text_buffer = file_io::load(path)?;
+        self.text_buffer = Arc::new(text);
+        self.replacement_offsets = replacement_offsets;
         self.init_line_offsets()?;
+        self.version += 1;
+        self.modified = false;
+        Ok(())
+    }
+
+    /// Inserts `text` at the byte offset `pos`, which must be on a character boundary.
+    /// The line index is updated in place rather than rebuilt.
+    pub fn insert(&mut self, pos: usize, text: &str) -> Result<(), Box<dyn Error>> {
+        if !self.text_buffer.is_char_boundary(pos) {
+            return Err(format!("Invalid insert position {}", pos).into());
+        }
+        let line = self.line_from_offset(pos);
+        Arc::make_mut(&mut self.text_buffer).insert_str(pos, text);
+
+        // Later lines move by the inserted length; line breaks in the text start new lines
+        let line_offsets = Arc::make_mut(&mut self.line_offsets);
+        for start in &mut line_offsets[line + 1..] {
+            *start += text.len();
+        }
+        let new_starts = memchr::memchr_iter(b'\n', text.as_bytes()).map(|i| pos + i + 1);
+        line_offsets.splice(line + 1..line + 1, new_starts);
+
+        // Replacement characters after the insertion point move with the text
+        for offset in self.replacement_offsets.iter_mut().filter(|offset| **offset >= pos) {
+            *offset += text.len();
+        }
+        self.version += 1;
+        self.modified = true;
         Ok(())
     }
 
+    /// Removes `len` bytes starting at the byte offset `pos` and returns them.
+    /// Both ends must be on character boundaries. The line index is updated in
+    /// place rather than rebuilt.
+    pub fn delete(&mut self, pos: usize, len: usize) -> Result<String, Box<dyn Error>> {
+        let end = pos.checked_add(len).filter(|&end| end <= self.text_buffer.len());
+        let end = match end {
+            Some(end) if self.text_buffer.is_char_boundary(pos) && self.text_buffer.is_char_boundary(end) => end,
+            _ => return Err(format!("Invalid delete range {}..{}", pos, pos.saturating_add(len)).into()),
+        };
+        let removed: String = Arc::make_mut(&mut self.text_buffer).drain(pos..end).collect();
+
+        // Lines starting after a deleted line break are gone; later lines move back
+        let line_offsets = Arc::make_mut(&mut self.line_offsets);
+        let first_removed = line_offsets.partition_point(|&start| start <= pos);
+        let first_kept = line_offsets.partition_point(|&start| start <= end);
+        line_offsets.drain(first_removed..first_kept);
+        for start in &mut line_offsets[first_removed..] {
+            *start -= len;
+        }
+
+        // Replacement characters in the deleted text are gone, later ones move back
+        self.replacement_offsets.retain(|offset| !(pos..end).contains(offset));
+        for offset in self.replacement_offsets.iter_mut().filter(|offset| **offset >= end) {
+            *offset -= len;
+        }
+        self.version += 1;
+        self.modified = true;
+        Ok(removed)
+    }
+
+    /// Replaces the whole content of the document with `text`.
+    pub fn set_content(&mut self, text: String) {
+        self.text_buffer = Arc::new(text);
+        // Offsets of replacement characters no longer match the new text
+        self.replacement_offsets.clear();
+        let _ = self.init_line_offsets();
+        self.version += 1;
+        self.modified = true;
+    }
+
     /// Clears the document content and resets state to empty.
     pub fn clear(&mut self) {
-        self.line_offsets = vec![0];
-        self.text_buffer.clear();
+        // Fresh allocations rather than clearing in place, which would copy
+        // buffers still shared with a snapshot
+        self.line_offsets = Arc::new(vec![0]);
+        self.text_buffer = Arc::new(String::new());
+        self.replacement_offsets.clear();
+        self.version += 1;
+        self.modified = false;
     }
     
     /// Given a 0-based line number, returns a string slice of that line's text,
     /// excluding the trailing newline character(s).
     pub fn getline(&self, lineno: usize) -> Option<&str> {
-        let num_lines = self.line_offsets.len();
+        line_in(&self.text_buffer, &self.line_offsets, lineno)
+    }
 
-        if lineno >= num_lines {
-            return None;
+    /// Returns the number of lines in the document.
+    pub fn line_count(&self) -> usize {
+        self.line_offsets.len()
+    }
+
+    /// Returns the length in bytes of the longest line, excluding line breaks.
+    pub fn longest_line_len(&self) -> usize {
+        (0..self.line_count()).map(|lineno| self.getline(lineno).map_or(0, str::len)).max().unwrap_or(0)
+    }
+
+    /// Returns the byte offset at which the given 0-based line starts.
+    pub fn line_start(&self, lineno: usize) -> Option<usize> {
+        self.line_offsets.get(lineno).copied()
+    }
+
+    /// Returns the 0-based line containing the given byte offset.
+    pub fn line_from_offset(&self, offset: usize) -> usize {
+        // line_offsets starts with 0, so at least one line start is <= offset
+        self.line_offsets.partition_point(|&start| start <= offset) - 1
+    }
+
+    /// Converts a byte offset into a 0-based line and the byte offset within that line.
+    pub fn offset_to_position(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_from_offset(offset);
+        (line, offset - self.line_offsets[line])
+    }
+
+    /// Converts a 0-based line and a byte offset within it into a byte offset into
+    /// the document. Lines past the end clamp to the last line, and columns past
+    /// the end of the line's text clamp to it, before the line break. A column
+    /// within a character moves to the start of that character.
+    pub fn position_to_offset(&self, line: usize, column: usize) -> usize {
+        let line = line.min(self.line_count() - 1);
+        let text = self.getline(line).unwrap_or("");
+        let mut column = column.min(text.len());
+        while !text.is_char_boundary(column) {
+            column -= 1;
         }
+        self.line_offsets[line] + column
+    }
 
+    /// Returns the total length of the text buffer in bytes.
+    pub fn len(&self) -> usize {
+        self.text_buffer.len()
+    }
+
+    /// Returns the number of replacement characters inserted for invalid byte
+    /// sequences when the document was loaded.
+    pub fn encoding_error_count(&self) -> usize {
+        self.replacement_offsets.len()
+    }
+
+    /// Forgets the recorded replacement characters, e.g. once they have been
+    /// written to disk and became part of the file.
+    pub fn clear_encoding_errors(&mut self) {
+        self.replacement_offsets.clear();
+    }
+
+    /// Returns the byte offsets, relative to the start of the line, of the
+    /// replacement characters inserted on load within the given 0-based line.
+    pub fn encoding_errors_in_line(&self, lineno: usize) -> Vec<usize> {
+        let num_lines = self.line_offsets.len();
+        if lineno >= num_lines {
+            return Vec::new();
+        }
         let start_offset = self.line_offsets[lineno];
         let end_offset = if lineno + 1 < num_lines {
             self.line_offsets[lineno + 1]
         } else {
-            self.text_buffer.len() // End of the buffer for the last line
+            self.text_buffer.len()
         };
 
-        // Basic sanity check
-        if start_offset > end_offset || end_offset > self.text_buffer.len() {
-             eprintln!("getline error: Invalid offsets {}..{}", start_offset, end_offset); 
-             return None; // Indicates an internal error
-        }
+        // replacement_offsets is sorted, so only the slice inside the line is visited
+        let first = self.replacement_offsets.partition_point(|&offset| offset < start_offset);
+        self.replacement_offsets[first..]
+            .iter()
+            .take_while(|&&offset| offset < end_offset)
+            .map(|&offset| offset - start_offset)
+            .collect()
+    }
 
-        let mut line_slice = &self.text_buffer[start_offset..end_offset];
+    /// Returns whether the content changed since it was loaded or last saved.
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
 
-        // Trim trailing newline characters (\n or \r\n)
-        // Check for \n first, then \r
-        if line_slice.ends_with('\n') {
-            line_slice = &line_slice[..line_slice.len() - 1];
-        }
-        if line_slice.ends_with('\r') {
-            line_slice = &line_slice[..line_slice.len() - 1];
+    /// Marks the content as changed, or as matching the file, e.g. once it was saved.
+    pub fn set_modified(&mut self, modified: bool) {
+        self.modified = modified;
+    }
+
+    /// Returns the document version, which changes whenever the content does.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Returns a reference to the entire text buffer.
+    pub fn get_content(&self) -> &str {
+        &self.text_buffer
+    }
+
+    /// Takes a cheap, immutable snapshot of the current content. Background tasks
+    /// can read the snapshot while the document keeps being edited.
+    pub fn snapshot(&self) -> DocumentSnapshot {
+        DocumentSnapshot {
+            line_offsets: Arc::clone(&self.line_offsets),
+            text_buffer: Arc::clone(&self.text_buffer),
+            version: self.version,
         }
+    }
+}
+
+/// An immutable view of a TextDocument at a given version, sharing its buffers.
+/// Snapshots are `Send + Sync`, so they can be handed to worker threads.
+#[derive(Clone)]
+pub struct DocumentSnapshot {
+    line_offsets: Arc<Vec<usize>>,
+    text_buffer: Arc<String>,
+    version: u64,
+}
 
-        Some(line_slice)
+impl DocumentSnapshot {
+    /// Given a 0-based line number, returns that line's text without the newline.
+    pub fn getline(&self, lineno: usize) -> Option<&str> {
+        line_in(&self.text_buffer, &self.line_offsets, lineno)
     }
 
-    /// Returns the number of lines in the document.
+    /// Returns the number of lines in the snapshot.
     pub fn line_count(&self) -> usize {
         self.line_offsets.len()
     }
 
-    /// Returns the total length of the text buffer in bytes.
-    pub fn len(&self) -> usize {
-        self.text_buffer.len()
+    /// Returns the document version the snapshot was taken at.
+    pub fn version(&self) -> u64 {
+        self.version
     }
 
-    /// Returns a reference to the entire text buffer.
+    /// Returns the entire text of the snapshot.
     pub fn get_content(&self) -> &str {
         &self.text_buffer
     }
-}
\ No newline at end of file
+}
+
+/// Given a 0-based line number, returns a string slice of that line's text,
+/// excluding the trailing newline character(s).
+fn line_in<'a>(text_buffer: &'a str, line_offsets: &[usize], lineno: usize) -> Option<&'a str> {
+    let num_lines = line_offsets.len();
+
+    if lineno >= num_lines {
+        return None;
+    }
+
+    let start_offset = line_offsets[lineno];
+    let end_offset = if lineno + 1 < num_lines {
+        line_offsets[lineno + 1]
+    } else {
+        text_buffer.len() // End of the buffer for the last line
+    };
+
+    // Basic sanity check
+    if start_offset > end_offset || end_offset > text_buffer.len() {
+         eprintln!("getline error: Invalid offsets {}..{}", start_offset, end_offset); 
+         return None; // Indicates an internal error
+    }
+
+    let mut line_slice = &text_buffer[start_offset..end_offset];
+
+    // Trim trailing newline characters (\n or \r\n)
+    // Check for \n first, then \r
+    if line_slice.ends_with('\n') {
+        line_slice = &line_slice[..line_slice.len() - 1];
+    }
+    if line_slice.ends_with('\r') {
+        line_slice = &line_slice[..line_slice.len() - 1];
+    }
+
+    Some(line_slice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asserts that the line offsets kept up to date by insert and delete are
+    /// the ones a full rebuild computes.
+    fn assert_matches_rebuild(document: &mut TextDocument) {
+        let incremental = document.line_offsets.to_vec();
+        document.init_line_offsets().unwrap();
+        assert_eq!(incremental, *document.line_offsets, "line offsets of {:?}", document.get_content());
+    }
+
+    #[test]
+    fn insert_updates_line_offsets() {
+        let cases: &[(&str, usize, &str)] = &[
+            ("", 0, "abc"),
+            ("", 0, "\n"),
+            ("", 0, "a\nb\n"),
+            ("one\ntwo\nthree", 0, "zero\n"),
+            ("one\ntwo\nthree", 2, "x"),
+            ("one\ntwo\nthree", 3, "\n"),
+            ("one\ntwo\nthree", 4, "\n\n"),
+            ("one\ntwo\nthree", 6, "a\r\nb"),
+            ("one\ntwo\nthree", 13, "\n"),
+            ("one\ntwo\n", 8, "three"),
+            ("one\r\ntwo", 4, "x\n"),
+            ("äö\nü", 2, "\nß\n"),
+        ];
+        for &(text, pos, inserted) in cases {
+            let mut document = TextDocument::from_text(text);
+            document.insert(pos, inserted).unwrap();
+            assert_matches_rebuild(&mut document);
+        }
+    }
+
+    #[test]
+    fn delete_updates_line_offsets() {
+        let cases: &[(&str, usize, usize)] = &[
+            ("abc", 0, 3),
+            ("\n", 0, 1),
+            ("one\ntwo\nthree", 0, 4),
+            ("one\ntwo\nthree", 1, 1),
+            ("one\ntwo\nthree", 3, 1),
+            ("one\ntwo\nthree", 2, 7),
+            ("one\ntwo\nthree", 4, 4),
+            ("one\ntwo\nthree", 0, 13),
+            ("one\ntwo\n", 7, 1),
+            ("one\n\n\ntwo", 4, 2),
+            ("one\r\ntwo", 3, 2),
+            ("äö\nü\n", 2, 5),
+        ];
+        for &(text, pos, len) in cases {
+            let mut document = TextDocument::from_text(text);
+            document.delete(pos, len).unwrap();
+            assert_matches_rebuild(&mut document);
+        }
+    }
+
+    #[test]
+    fn failed_edits_keep_line_offsets() {
+        let mut document = TextDocument::from_text("ä\nb");
+        assert!(document.insert(1, "x").is_err());
+        assert!(document.delete(0, 1).is_err());
+        assert!(document.delete(2, 5).is_err());
+        assert_eq!(document.get_content(), "ä\nb");
+        assert_matches_rebuild(&mut document);
+    }
+
+    #[test]
+    fn edit_sequence_matches_rebuild() {
+        // A fixed pseudo-random sequence of edits, so failures are reproducible
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % bound as u64) as usize
+        };
+        let pieces = ["a", "bc", "\n", "\r\n", "x\ny", "\n\n", "é", "line\n"];
+        let mut document = TextDocument::from_text("first\nsecond\nthird");
+        for _ in 0..500 {
+            let len = document.len();
+            let pos = document.position_to_offset(next(document.line_count() + 1), next(8));
+            if len > 0 && next(3) == 0 {
+                let end = document.position_to_offset(next(document.line_count() + 1), next(8)).max(pos);
+                document.delete(pos, end - pos).unwrap();
+            } else {
+                document.insert(pos, pieces[next(pieces.len())]).unwrap();
+            }
+            assert_matches_rebuild(&mut document);
+        }
+    }
+}
@@ -0,0 +1,8 @@
+//! Jedit's editor as a library. Other Win32 applications can embed the editor
+//! control with [`ui::control::JeditControl`].
+
+pub mod command;
+pub mod document;
+pub mod ui;
+
+pub use ui::control::JeditControl;
@@ -1,24 +1,47 @@
-mod ui;
-mod document;
-
+use std::path::PathBuf;
 use windows::{
     core::{Result, HSTRING},
     Win32::{
         Foundation::E_FAIL,
+        System::Ole::OleInitialize,
         UI::WindowsAndMessaging::{DispatchMessageW, GetMessageW, TranslateMessage, MSG},
     },
 };
 
-use crate::ui::editor_view::*; 
-use crate::ui::main_window::*; 
+use jedit::ui::editor_view::*;
+use jedit::ui::main_window::*;
 
 fn main() -> Result<()> { // Revert return type to windows::core::Result<()>
+    // A file given on the command line opens in the running instance, unless --new-window is given
+    let file_arg = std::env::args_os()
+        .skip(1)
+        .find(|arg| !arg.to_string_lossy().starts_with("--"))
+        .map(PathBuf::from);
+    let new_window = std::env::args().skip(1).any(|arg| arg == "--new-window");
+    // Held until the main window exists, so an instance started at the same
+    // time finds that window rather than opening a second one
+    let startup_lock = StartupLock::acquire();
+    if let Some(file_path) = &file_arg {
+        // The running instance tells the user if the file couldn't be opened
+        if !new_window && open_in_running_instance(file_path).is_some() {
+            return Ok(());
+        }
+    }
+
+    // The shell file dialogs are COM objects and need a single-threaded apartment;
+    // OleInitialize sets one up and also enables drag and drop
+    unsafe { OleInitialize(None) }?;
+
     // Initialize window classes
     init_main_window()?;
     init_editor_view().map_err(|e| windows::core::Error::new(E_FAIL, format!("init_editor_view failed: {}", e)))?;
 
     // Create the main window
-    let _hwnd_main = create_main_window().map_err(|e| windows::core::Error::new(E_FAIL, format!("create_main_window failed: {}", e)))?;
+    let hwnd_main = create_main_window().map_err(|e| windows::core::Error::new(E_FAIL, format!("create_main_window failed: {}", e)))?;
+    drop(startup_lock);
+    if let Some(file_path) = &file_arg {
+        open_file(hwnd_main, file_path);
+    }
 
     // Run the message loop for main window
     unsafe {
@@ -0,0 +1,35 @@
+use windows::{
+    core::BSTR,
+    Win32::{
+        Foundation::HWND,
+        UI::Accessibility::{
+            UiaClientsAreListening, UiaHostProviderFromHwnd, UiaRaiseNotificationEvent, NotificationKind_ActionCompleted,
+            NotificationProcessing_ImportantMostRecent,
+        },
+    },
+};
+
+/// Has screen readers speak `text`, for events that are otherwise only shown
+/// on screen (e.g. "File saved"). Announcements with the same `activity_id`
+/// replace each other, so only the latest of a quick series is read out.
+pub fn announce(hwnd: HWND, text: &str, activity_id: &str) {
+    unsafe {
+        // Nothing to do without an assistive technology listening
+        if !UiaClientsAreListening().as_bool() {
+            return;
+        }
+        // The window's host provider is enough to raise events from
+        let result = UiaHostProviderFromHwnd(hwnd).and_then(|provider| {
+            UiaRaiseNotificationEvent(
+                &provider,
+                NotificationKind_ActionCompleted,
+                NotificationProcessing_ImportantMostRecent,
+                &BSTR::from(text),
+                &BSTR::from(activity_id),
+            )
+        });
+        if let Err(e) = result {
+            eprintln!("Failed to announce \"{}\": {}", text, e);
+        }
+    }
+}
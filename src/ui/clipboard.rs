@@ -0,0 +1,75 @@
+use std::ptr;
+use crate::document::file_io::{self, LineEnding};
+use windows::{
+    core::{Error, Result},
+    Win32::{
+        Foundation::{GlobalFree, HANDLE, HGLOBAL, HWND},
+        System::{
+            DataExchange::{CloseClipboard, EmptyClipboard, GetClipboardData, IsClipboardFormatAvailable, OpenClipboard, SetClipboardData},
+            Memory::{GlobalAlloc, GlobalLock, GlobalSize, GlobalUnlock, GMEM_MOVEABLE},
+            Ole::CF_UNICODETEXT,
+        },
+    },
+};
+
+/// Places `text` on the clipboard as Unicode text. Line breaks become CR LF,
+/// which other Windows programs expect on the clipboard.
+pub fn set_text(hwnd: HWND, text: &str) -> Result<()> {
+    let text = file_io::convert_line_endings(text, LineEnding::CrLf);
+    let text_wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        OpenClipboard(Some(hwnd))?;
+        let result = (|| {
+            EmptyClipboard()?;
+            let hmem = GlobalAlloc(GMEM_MOVEABLE, text_wide.len() * std::mem::size_of::<u16>())?;
+            let buffer = GlobalLock(hmem) as *mut u16;
+            if buffer.is_null() {
+                let _ = GlobalFree(Some(hmem));
+                return Err(Error::from_win32());
+            }
+            ptr::copy_nonoverlapping(text_wide.as_ptr(), buffer, text_wide.len());
+            let _ = GlobalUnlock(hmem);
+            // On success the clipboard takes ownership of the memory
+            if let Err(e) = SetClipboardData(CF_UNICODETEXT.0 as u32, Some(HANDLE(hmem.0))) {
+                let _ = GlobalFree(Some(hmem));
+                return Err(e);
+            }
+            Ok(())
+        })();
+        let _ = CloseClipboard();
+        result
+    }
+}
+
+/// Returns whether the clipboard holds text that could be pasted.
+pub fn has_text() -> bool {
+    unsafe { IsClipboardFormatAvailable(CF_UNICODETEXT.0 as u32) }.is_ok()
+}
+
+/// Returns the text on the clipboard, or None if it holds no text. Line
+/// breaks are left as they are, usually CR LF.
+pub fn get_text(hwnd: HWND) -> Result<Option<String>> {
+    unsafe {
+        if !has_text() {
+            return Ok(None);
+        }
+        OpenClipboard(Some(hwnd))?;
+        let result = (|| {
+            // The clipboard keeps ownership of the memory
+            let hmem = HGLOBAL(GetClipboardData(CF_UNICODETEXT.0 as u32)?.0);
+            let buffer = GlobalLock(hmem) as *const u16;
+            if buffer.is_null() {
+                return Err(Error::from_win32());
+            }
+            // The text is null-terminated and may not fill the whole allocation
+            let capacity = GlobalSize(hmem) / std::mem::size_of::<u16>();
+            let units = std::slice::from_raw_parts(buffer, capacity);
+            let len = units.iter().position(|&unit| unit == 0).unwrap_or(capacity);
+            let text = String::from_utf16_lossy(&units[..len]);
+            let _ = GlobalUnlock(hmem);
+            Ok(Some(text))
+        })();
+        let _ = CloseClipboard();
+        result
+    }
+}
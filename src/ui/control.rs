@@ -0,0 +1,273 @@
+use std::error::Error;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use windows::Win32::{
+    Foundation::{HWND, LPARAM, WPARAM},
+    Graphics::Gdi::HFONT,
+    UI::Controls::{EM_GETMODIFY, EM_SETMODIFY},
+    UI::WindowsAndMessaging::{SendMessageW, WM_GETTEXT, WM_GETTEXTLENGTH, WM_SETFONT, WM_SETTEXT, WM_USER},
+};
+use crate::document::file_io::LineEnding;
+use crate::document::format::FormatStyle;
+use crate::ui::editor_view;
+use crate::ui::keymap::KeyBindings;
+
+// Messages understood by the editor control. JeditControl wraps them; they
+// are public for hosts that talk to the control through SendMessageW.
+
+/// Opens a file. lparam is the path as PCWSTR; returns 1 on success.
+pub const EVM_OPENFILE: u32 = WM_USER + 1;
+/// Starts a new, empty untitled document.
+pub const EVM_CLEARFILE: u32 = WM_USER + 2;
+/// Saves the document. wparam holds SAVE_* flags, lparam the path as PCWSTR
+/// or null for the current path; returns 1 on success.
+pub const EVM_SAVEFILE: u32 = WM_USER + 3;
+/// Copies the file path into a buffer: wparam is the capacity in characters,
+/// lparam the buffer. Returns the path length; a null buffer just queries it.
+pub const EVM_GETFILEPATH: u32 = WM_USER + 4;
+/// Replaces the content with a local history snapshot at the lparam PCWSTR path.
+pub const EVM_RESTOREFILE: u32 = WM_USER + 5;
+/// Returns the number of invalid byte sequences replaced when the file was loaded.
+pub const EVM_GETENCODINGERRORS: u32 = WM_USER + 6;
+/// Suspends (nonzero wparam) or resumes the timers of idle tasks, repainting
+/// and the caret blinking, e.g. while the host window is minimized.
+pub const EVM_SUSPENDTIMERS: u32 = WM_USER + 7;
+/// Pretty-prints (wparam 0) or minifies the document, indenting by lparam
+/// spaces (0 for the default); returns 1 on success.
+pub const EVM_FORMATDOCUMENT: u32 = WM_USER + 8;
+/// Turns aligned columns on (nonzero wparam) or off; returns 1 if they are shown.
+pub const EVM_SETALIGNEDVIEW: u32 = WM_USER + 9;
+/// Returns 1 while aligned columns are shown.
+pub const EVM_GETALIGNEDVIEW: u32 = WM_USER + 10;
+/// Starts an untitled document from the template at the lparam PCWSTR path.
+pub const EVM_NEWFROMTEMPLATE: u32 = WM_USER + 11;
+/// Zooms by the signed number of steps in wparam; 0 restores the default zoom.
+pub const EVM_ZOOM: u32 = WM_USER + 12;
+/// Uses one zoom level for all documents (nonzero wparam) or one per document.
+pub const EVM_SETZOOMSYNC: u32 = WM_USER + 13;
+/// Returns 1 while one zoom level is used for all documents.
+pub const EVM_GETZOOMSYNC: u32 = WM_USER + 14;
+/// Copies selections to the clipboard as they are made and pastes on
+/// middle-click (nonzero wparam), or turns that off.
+pub const EVM_SETAUTOCOPY: u32 = WM_USER + 15;
+/// Returns 1 while selections are copied as they are made.
+pub const EVM_GETAUTOCOPY: u32 = WM_USER + 16;
+/// Sets the number of lines (wparam) kept visible above and below the caret
+/// when the view scrolls to it.
+pub const EVM_SETSCROLLMARGIN: u32 = WM_USER + 17;
+/// Switches to the key binding scheme whose KeyBindings index is in wparam.
+pub const EVM_SETKEYBINDINGS: u32 = WM_USER + 18;
+/// Returns the KeyBindings index of the key binding scheme in use.
+pub const EVM_GETKEYBINDINGS: u32 = WM_USER + 19;
+/// Opens a file that was already read. lparam is the path as PCWSTR, or null
+/// for an untitled document, wparam a `*mut (String, Vec<usize>)` with the
+/// content as file_io::load returns it, which the editor takes. Returns 1 on success.
+pub const EVM_OPENLOADEDFILE: u32 = WM_USER + 20;
+/// Keeps (nonzero wparam) the first line of aligned columns at the top of the
+/// view as a header row while scrolling, or lets it scroll away.
+pub const EVM_SETFREEZEHEADER: u32 = WM_USER + 21;
+/// Returns 1 if the header row of aligned columns is kept in view.
+pub const EVM_GETFREEZEHEADER: u32 = WM_USER + 22;
+
+// Notification codes the editor view sends its parent window in the high word
+// of WM_COMMAND's wparam, with its window handle in lparam
+/// The document became modified, or unmodified, e.g. by saving it. EM_GETMODIFY tells which.
+pub const EVN_MODIFIEDCHANGE: u16 = 1;
+
+// EVM_SAVEFILE wparam flags: conversions applied to the document before saving
+pub const SAVE_LINE_ENDINGS_CRLF: usize = 0x1;
+pub const SAVE_LINE_ENDINGS_LF: usize = 0x2;
+pub const SAVE_ENCODING_UTF8: usize = 0x4;
+pub const SAVE_ENCODING_UTF8_BOM: usize = 0x8;
+
+/// Conversions applied to the document when it is saved. `None` keeps the
+/// document as it is.
+#[derive(Clone, Copy, Default)]
+pub struct SaveOptions {
+    pub line_ending: Option<LineEnding>,
+    /// Whether the file starts with a UTF-8 byte order mark.
+    pub byte_order_mark: Option<bool>,
+}
+
+impl SaveOptions {
+    /// Returns the SAVE_* flags for EVM_SAVEFILE.
+    pub fn flags(&self) -> usize {
+        let line_ending = match self.line_ending {
+            Some(LineEnding::CrLf) => SAVE_LINE_ENDINGS_CRLF,
+            Some(LineEnding::Lf) => SAVE_LINE_ENDINGS_LF,
+            None => 0,
+        };
+        let encoding = match self.byte_order_mark {
+            Some(true) => SAVE_ENCODING_UTF8_BOM,
+            Some(false) => SAVE_ENCODING_UTF8,
+            None => 0,
+        };
+        line_ending | encoding
+    }
+}
+
+/// Handle to an editor control, for embedding the editor in other Win32
+/// applications. The control is a child window owned by its parent; this
+/// handle doesn't destroy it.
+#[derive(Clone, Copy)]
+pub struct JeditControl {
+    hwnd: HWND,
+}
+
+impl JeditControl {
+    /// Creates an editor control as a child of `parent`, registering the
+    /// window class on first use. Position it with MoveWindow or SetWindowPos.
+    pub fn create(parent: HWND) -> Result<Self, Box<dyn Error>> {
+        editor_view::init_editor_view()?;
+        let hwnd = editor_view::create_editor_view(parent)?;
+        Ok(JeditControl { hwnd })
+    }
+
+    /// Wraps an existing editor control window.
+    pub fn from_hwnd(hwnd: HWND) -> Self {
+        JeditControl { hwnd }
+    }
+
+    /// Returns the control's window handle.
+    pub fn hwnd(&self) -> HWND {
+        self.hwnd
+    }
+
+    fn send(&self, msg: u32, wparam: usize, lparam: isize) -> isize {
+        unsafe { SendMessageW(self.hwnd, msg, Some(WPARAM(wparam)), Some(LPARAM(lparam))) }.0
+    }
+
+    /// Opens a file, replacing the current document.
+    pub fn open(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let path_wide = to_wide(path);
+        if self.send(EVM_OPENFILE, 0, path_wide.as_ptr() as isize) == 0 {
+            return Err(format!("Failed to open {}", path.display()).into());
+        }
+        Ok(())
+    }
+
+    /// Starts a new, empty untitled document.
+    pub fn clear(&self) {
+        self.send(EVM_CLEARFILE, 0, 0);
+    }
+
+    /// Saves the document to `path`, or to its current path for None.
+    pub fn save(&self, path: Option<&Path>, options: SaveOptions) -> Result<(), Box<dyn Error>> {
+        let path_wide = path.map(to_wide);
+        let path_ptr = path_wide.as_ref().map_or(std::ptr::null(), |path_wide| path_wide.as_ptr());
+        if self.send(EVM_SAVEFILE, options.flags(), path_ptr as isize) == 0 {
+            return Err("Failed to save the document".into());
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the document, or None if it is untitled.
+    pub fn file_path(&self) -> Option<PathBuf> {
+        let path_len = self.send(EVM_GETFILEPATH, 0, 0) as usize;
+        if path_len == 0 {
+            return None;
+        }
+        let mut buffer = vec![0u16; path_len + 1];
+        let copied = self.send(EVM_GETFILEPATH, buffer.len(), buffer.as_mut_ptr() as isize) as usize;
+        Some(PathBuf::from(std::ffi::OsString::from_wide(&buffer[..copied])))
+    }
+
+    /// Returns the whole text of the document.
+    pub fn text(&self) -> String {
+        let length = self.send(WM_GETTEXTLENGTH, 0, 0) as usize;
+        let mut buffer = vec![0u16; length + 1];
+        let copied = self.send(WM_GETTEXT, buffer.len(), buffer.as_mut_ptr() as isize) as usize;
+        String::from_utf16_lossy(&buffer[..copied])
+    }
+
+    /// Replaces the whole text of the document.
+    pub fn set_text(&self, text: &str) {
+        let text_wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+        self.send(WM_SETTEXT, 0, text_wide.as_ptr() as isize);
+    }
+
+    /// Pretty-prints or minifies the document with the formatter for its file type.
+    pub fn format(&self, style: FormatStyle) -> Result<(), Box<dyn Error>> {
+        if self.send(EVM_FORMATDOCUMENT, (style == FormatStyle::Minify) as usize, 0) == 0 {
+            return Err("No formatter could format the document".into());
+        }
+        Ok(())
+    }
+
+    /// Sets the font the text is drawn with, before zooming.
+    pub fn set_font(&self, hfont: HFONT) {
+        self.send(WM_SETFONT, hfont.0 as usize, 1);
+    }
+
+    /// Shows delimited files with aligned columns. Returns whether they are
+    /// shown, which is never the case for other files.
+    pub fn set_aligned_view(&self, enable: bool) -> bool {
+        self.send(EVM_SETALIGNEDVIEW, enable as usize, 0) != 0
+    }
+
+    pub fn aligned_view(&self) -> bool {
+        self.send(EVM_GETALIGNEDVIEW, 0, 0) != 0
+    }
+
+    /// Keeps the first line of aligned columns in view as their header row.
+    pub fn set_freeze_header(&self, freeze: bool) {
+        self.send(EVM_SETFREEZEHEADER, freeze as usize, 0);
+    }
+
+    pub fn freeze_header(&self) -> bool {
+        self.send(EVM_GETFREEZEHEADER, 0, 0) != 0
+    }
+
+    /// Zooms in (positive `steps`) or out, or restores the default zoom for 0.
+    pub fn zoom(&self, steps: i32) {
+        self.send(EVM_ZOOM, steps as isize as usize, 0);
+    }
+
+    /// Uses one zoom level for all documents instead of one per document.
+    pub fn set_zoom_sync(&self, sync_all: bool) {
+        self.send(EVM_SETZOOMSYNC, sync_all as usize, 0);
+    }
+
+    pub fn zoom_sync(&self) -> bool {
+        self.send(EVM_GETZOOMSYNC, 0, 0) != 0
+    }
+
+    /// Copies selections to the clipboard as they are made, with middle-click
+    /// pasting, as X11 applications do.
+    pub fn set_auto_copy(&self, enable: bool) {
+        self.send(EVM_SETAUTOCOPY, enable as usize, 0);
+    }
+
+    pub fn auto_copy(&self) -> bool {
+        self.send(EVM_GETAUTOCOPY, 0, 0) != 0
+    }
+
+    /// Keeps `lines` lines of context visible above and below the caret.
+    pub fn set_scroll_margin(&self, lines: usize) {
+        self.send(EVM_SETSCROLLMARGIN, lines, 0);
+    }
+
+    /// Returns whether the document changed since it was opened or last saved.
+    pub fn is_modified(&self) -> bool {
+        self.send(EM_GETMODIFY, 0, 0) != 0
+    }
+
+    /// Marks the document as changed or unchanged, e.g. after the host saved it.
+    pub fn set_modified(&self, modified: bool) {
+        self.send(EM_SETMODIFY, modified as usize, 0);
+    }
+
+    /// Switches to vi or Emacs key bindings, or back to the standard keys.
+    pub fn set_key_bindings(&self, key_bindings: KeyBindings) {
+        self.send(EVM_SETKEYBINDINGS, key_bindings.index(), 0);
+    }
+
+    pub fn key_bindings(&self) -> KeyBindings {
+        KeyBindings::from_index(self.send(EVM_GETKEYBINDINGS, 0, 0) as usize)
+    }
+}
+
+/// Converts a path to a null-terminated wide string.
+fn to_wide(path: &Path) -> Vec<u16> {
+    path.as_os_str().encode_wide().chain(std::iter::once(0)).collect()
+}
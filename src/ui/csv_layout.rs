@@ -0,0 +1,166 @@
+use std::ops::{Range, RangeInclusive};
+use std::path::Path;
+use crate::document::text_document::TextDocument;
+use crate::ui::line_layout;
+
+/// Blank cells between two aligned columns.
+const COLUMN_GAP: usize = 2;
+
+/// Returns the field delimiter for delimited text files, or None for other files.
+pub fn delimiter_for(path: &Path) -> Option<char> {
+    let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    match extension.as_str() {
+        "csv" => Some(','),
+        "tsv" | "tab" => Some('\t'),
+        _ => None,
+    }
+}
+
+/// Splits a line into the byte ranges of its fields. Delimiters inside
+/// double-quoted fields don't split; the quotes are part of the field.
+pub fn field_ranges(line: &str, delimiter: char) -> Vec<Range<usize>> {
+    let mut fields = Vec::new();
+    let mut field_start = 0;
+    let mut in_quotes = false;
+
+    for (i, ch) in line.char_indices() {
+        if ch == '"' {
+            // An escaped quote ("") toggles twice and leaves the state unchanged
+            in_quotes = !in_quotes;
+        } else if ch == delimiter && !in_quotes {
+            fields.push(field_start..i);
+            field_start = i + ch.len_utf8();
+        }
+    }
+    fields.push(field_start..line.len());
+    fields
+}
+
+/// A field of a line placed in the aligned view.
+pub struct AlignedField {
+    /// Index of the column the field belongs to.
+    pub column_index: usize,
+    /// Screen column at which the field starts.
+    pub start_column: usize,
+    /// Width of the column in cells, excluding the gap.
+    pub width: usize,
+    /// Byte range of the field within the line.
+    pub range: Range<usize>,
+}
+
+/// Display-only transform that pads the fields of delimited files into
+/// aligned columns. The document itself is never modified.
+pub struct AlignedView {
+    delimiter: char,
+    /// Set once the column widths were measured over the whole document.
+    measured: bool,
+    widths: Vec<usize>,
+    /// Document version a background rescan is measuring, and the widths it measured so far.
+    rescan: Option<(u64, Vec<usize>)>,
+}
+
+impl AlignedView {
+    pub fn new(delimiter: char) -> Self {
+        AlignedView {
+            delimiter,
+            measured: false,
+            widths: Vec::new(),
+            rescan: None,
+        }
+    }
+
+    /// Measures the widest field of every column if that wasn't done since the
+    /// view was created or reset. Edits are taken into account by `widen` and
+    /// `rescan_line` instead.
+    pub fn update(&mut self, document: &TextDocument) {
+        if self.measured {
+            return;
+        }
+        self.widths.clear();
+        for lineno in 0..document.line_count() {
+            if let Some(line) = document.getline(lineno) {
+                fit_line(&mut self.widths, line, self.delimiter);
+            }
+        }
+        self.measured = true;
+        self.rescan = None;
+    }
+
+    /// Forgets the column widths, for when the document content was replaced.
+    pub fn reset(&mut self) {
+        self.measured = false;
+        self.rescan = None;
+    }
+
+    /// Widens the columns to fit the edited `lines`. Columns never get narrower
+    /// here; a rescan finds columns whose widest field was shortened or removed.
+    pub fn widen(&mut self, document: &TextDocument, lines: RangeInclusive<usize>) {
+        if !self.measured {
+            return;
+        }
+        for lineno in lines {
+            if let Some(line) = document.getline(lineno) {
+                fit_line(&mut self.widths, line, self.delimiter);
+            }
+        }
+    }
+
+    /// Measures line `lineno` of a rescan of the whole document, which starts
+    /// over if the document changed since the rescan began. Returns the next
+    /// line to measure, or None once the rescan's widths replaced the old ones.
+    pub fn rescan_line(&mut self, document: &TextDocument, lineno: usize) -> Option<usize> {
+        let version = document.version();
+        let delimiter = self.delimiter;
+        let (lineno, widths) = match &mut self.rescan {
+            Some((rescan_version, widths)) if *rescan_version == version => (lineno, widths),
+            rescan => (0, &mut rescan.insert((version, Vec::new())).1),
+        };
+        if let Some(line) = document.getline(lineno) {
+            fit_line(widths, line, delimiter);
+        }
+        if lineno + 1 < document.line_count() {
+            return Some(lineno + 1);
+        }
+        self.widths = self.rescan.take().map(|(_, widths)| widths).unwrap_or_default();
+        self.measured = true;
+        None
+    }
+
+    /// Returns the width in cells of the widest aligned line, as measured by the last update.
+    pub fn width(&self) -> usize {
+        self.widths.iter().sum::<usize>() + self.widths.len().saturating_sub(1) * COLUMN_GAP
+    }
+
+    /// Places the fields of `line` at their column positions. Fields starting
+    /// at or beyond `max_columns` are left out.
+    pub fn layout_line(&self, line: &str, max_columns: usize) -> Vec<AlignedField> {
+        let mut fields = Vec::new();
+        let mut start_column = 0;
+        for (column_index, range) in field_ranges(line, self.delimiter).into_iter().enumerate() {
+            if start_column >= max_columns {
+                break;
+            }
+            // Fall back to the field's own width if update() hasn't measured this column
+            let width = self.widths.get(column_index).copied().unwrap_or_else(|| {
+                let field = &line[range.clone()];
+                line_layout::display_column(field, field.len())
+            });
+            fields.push(AlignedField { column_index, start_column, width, range });
+            start_column += width + COLUMN_GAP;
+        }
+        fields
+    }
+}
+
+/// Widens `widths` so that every column fits its field of `line`.
+fn fit_line(widths: &mut Vec<usize>, line: &str, delimiter: char) {
+    for (column_index, range) in field_ranges(line, delimiter).into_iter().enumerate() {
+        let field = &line[range];
+        let width = line_layout::display_column(field, field.len());
+        if column_index == widths.len() {
+            widths.push(width);
+        } else {
+            widths[column_index] = widths[column_index].max(width);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::ops::Range;
+use std::path::Path;
+
+/// What is drawn for a decorated range of a line.
+#[derive(Clone, Copy, PartialEq)]
+pub enum DecorationKind {
+    /// A swatch showing the color a token denotes.
+    ColorSwatch { red: u8, green: u8, blue: u8 },
+}
+
+/// A visual annotation attached to a byte range of a line. Decorations are
+/// drawn on top of the text and never change the document.
+pub struct Decoration {
+    pub range: Range<usize>,
+    pub kind: DecorationKind,
+}
+
+/// Finds decorations within a single line. Providers run at paint time on the
+/// visible lines only, so they should be cheap and must not hold state per line.
+pub trait DecorationProvider {
+    fn decorate_line(&self, line: &str, decorations: &mut Vec<Decoration>);
+}
+
+/// Returns the decoration providers that apply to a file.
+pub fn providers_for(path: Option<&Path>) -> Vec<Box<dyn DecorationProvider>> {
+    let mut providers: Vec<Box<dyn DecorationProvider>> = Vec::new();
+    if path.is_some_and(ColorSwatchProvider::applies_to) {
+        providers.push(Box::new(ColorSwatchProvider));
+    }
+    providers
+}
+
+/// Detects `#RRGGBB` and `rgb(r, g, b)` color codes in stylesheets and config files.
+pub struct ColorSwatchProvider;
+
+impl ColorSwatchProvider {
+    fn applies_to(path: &Path) -> bool {
+        let Some(extension) = path.extension() else {
+            return false;
+        };
+        let extension = extension.to_string_lossy().to_ascii_lowercase();
+        matches!(
+            extension.as_str(),
+            "css" | "scss" | "sass" | "less" | "html" | "htm" | "svg" | "xml" | "xaml"
+                | "json" | "ini" | "cfg" | "conf" | "config" | "toml" | "yaml" | "yml"
+        )
+    }
+}
+
+impl DecorationProvider for ColorSwatchProvider {
+    fn decorate_line(&self, line: &str, decorations: &mut Vec<Decoration>) {
+        let bytes = line.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            let token = if bytes[i] == b'#' && (i == 0 || bytes[i - 1] != b'&') {
+                // "&#123456;" is a character reference, not a color
+                parse_hex_color(line, i)
+            } else if bytes[i..].starts_with(b"rgb(") && (i == 0 || !is_word_byte(bytes[i - 1])) {
+                parse_rgb_function(line, i)
+            } else {
+                None
+            };
+            match token {
+                Some((len, kind)) => {
+                    decorations.push(Decoration { range: i..i + len, kind });
+                    i += len;
+                }
+                None => i += 1,
+            }
+        }
+    }
+}
+
+fn is_word_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-'
+}
+
+/// Parses `#RRGGBB` at `start`, returning its length and color.
+fn parse_hex_color(line: &str, start: usize) -> Option<(usize, DecorationKind)> {
+    const LEN: usize = 7;
+    let digits = line.get(start + 1..start + LEN)?;
+    if !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    // "#1234567" or "#abcdefg" are something else, such as an issue number
+    if line.as_bytes().get(start + LEN).is_some_and(|&byte| is_word_byte(byte)) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).ok();
+    Some((LEN, DecorationKind::ColorSwatch { red: channel(0)?, green: channel(2)?, blue: channel(4)? }))
+}
+
+/// Parses `rgb(r, g, b)` with decimal channels at `start`, returning its length and color.
+fn parse_rgb_function(line: &str, start: usize) -> Option<(usize, DecorationKind)> {
+    let arguments_start = start + "rgb(".len();
+    let arguments_len = line[arguments_start..].find(')')?;
+    let channels: Vec<u8> = line[arguments_start..arguments_start + arguments_len]
+        .split(',')
+        .map(|channel| channel.trim().parse::<u8>().ok())
+        .collect::<Option<_>>()?;
+    let [red, green, blue] = channels[..] else {
+        return None;
+    };
+    Some((arguments_start + arguments_len + 1 - start, DecorationKind::ColorSwatch { red, green, blue }))
+}
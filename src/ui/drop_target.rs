@@ -0,0 +1,254 @@
+use std::cell::Cell;
+use std::ffi::OsString;
+use std::os::windows::ffi::OsStringExt;
+use std::path::PathBuf;
+use windows::{
+    core::{implement, Interface, Ref, Result, HRESULT},
+    Win32::{
+        Foundation::{E_ABORT, E_FAIL, HWND, POINTL, S_OK},
+        System::{
+            Com::{IDataObject, DVASPECT_CONTENT, FORMATETC, STGMEDIUM, TYMED, TYMED_HGLOBAL, TYMED_ISTREAM},
+            DataExchange::RegisterClipboardFormatW,
+            Memory::{GlobalLock, GlobalSize, GlobalUnlock},
+            Ole::{
+                IDropTarget, IDropTarget_Impl, RegisterDragDrop, ReleaseStgMedium, RevokeDragDrop, CF_HDROP,
+                DROPEFFECT, DROPEFFECT_COPY, DROPEFFECT_NONE,
+            },
+            SystemServices::MODIFIERKEYS_FLAGS,
+        },
+        UI::Shell::{
+            DragQueryFileW, IDataObjectAsyncCapability, CFSTR_FILECONTENTS, CFSTR_FILEDESCRIPTORW, FD_FILESIZE,
+            FILEGROUPDESCRIPTORW, HDROP,
+        },
+    },
+};
+
+/// Something dropped onto a window. Only the first of several dropped files is
+/// delivered, as there is only one document to open it in.
+pub enum DroppedItem {
+    /// A file on disk, e.g. dragged from Explorer.
+    File(PathBuf),
+    /// A virtual file that only exists in the dragged data, such as an Outlook
+    /// attachment or a file inside a zip folder.
+    VirtualFile(VirtualFile),
+}
+
+/// Largest virtual file that is read; a text editor has no use for more.
+const MAX_VIRTUAL_FILE_SIZE: usize = 256 * 1024 * 1024;
+
+/// A dropped virtual file. Its content can take long to produce (e.g. to
+/// unpack or download), so it is only read after the drop, with `read`.
+pub struct VirtualFile {
+    pub name: String,
+    /// The size of the content, if the source gave one.
+    size: Option<usize>,
+    data: IDataObject,
+    /// Set if the source was told that the content is extracted after the
+    /// drop, and waits to hear when that is done.
+    operation: Option<IDataObjectAsyncCapability>,
+}
+
+impl VirtualFile {
+    /// Reads the content of the file, up to MAX_VIRTUAL_FILE_SIZE bytes.
+    pub fn read(mut self) -> std::result::Result<Vec<u8>, String> {
+        let result = self.read_content();
+        let hresult = if result.is_ok() { S_OK } else { E_FAIL };
+        self.end_operation(hresult);
+        result
+    }
+
+    fn read_content(&self) -> std::result::Result<Vec<u8>, String> {
+        if self.size.is_some_and(|size| size > MAX_VIRTUAL_FILE_SIZE) {
+            return Err(too_large());
+        }
+        let contents_format = unsafe { RegisterClipboardFormatW(CFSTR_FILECONTENTS) as u16 };
+        let tymed = TYMED(TYMED_HGLOBAL.0 | TYMED_ISTREAM.0);
+        let mut medium = unsafe { self.data.GetData(&format_etc(contents_format, 0, tymed)) }.map_err(|e| e.to_string())?;
+        let content = read_medium(&medium, self.size);
+        unsafe { ReleaseStgMedium(&mut medium) };
+        content
+    }
+
+    /// Tells a waiting source how the extraction ended.
+    fn end_operation(&mut self, hresult: HRESULT) {
+        if let Some(operation) = self.operation.take() {
+            let effect = if hresult.is_ok() { DROPEFFECT_COPY } else { DROPEFFECT_NONE };
+            let _ = unsafe { operation.EndOperation(hresult, None, effect.0) };
+        }
+    }
+}
+
+impl Drop for VirtualFile {
+    fn drop(&mut self) {
+        // The file wasn't read, e.g. as the user kept the document open
+        self.end_operation(E_ABORT);
+    }
+}
+
+/// OLE drop target accepting files and virtual files.
+#[implement(IDropTarget)]
+struct DropTarget {
+    on_drop: Box<dyn Fn(DroppedItem)>,
+    /// Whether the data being dragged over the window can be dropped.
+    accepted: Cell<bool>,
+}
+
+/// Lets files be dropped onto `hwnd` and its child windows. `on_drop` runs
+/// while the drag source waits for the drop to complete, so it should hand
+/// the item on (e.g. with PostMessageW) rather than open it right away.
+/// Requires OLE to be initialized on the thread.
+pub fn register(hwnd: HWND, on_drop: impl Fn(DroppedItem) + 'static) -> Result<()> {
+    let target: IDropTarget = DropTarget {
+        on_drop: Box::new(on_drop),
+        accepted: Cell::new(false),
+    }
+    .into();
+    unsafe { RegisterDragDrop(hwnd, &target) }
+}
+
+/// Stops accepting drops; call before `hwnd` is destroyed.
+pub fn revoke(hwnd: HWND) {
+    let _ = unsafe { RevokeDragDrop(hwnd) };
+}
+
+impl DropTarget {
+    fn drop_effect(&self) -> DROPEFFECT {
+        if self.accepted.get() { DROPEFFECT_COPY } else { DROPEFFECT_NONE }
+    }
+}
+
+impl IDropTarget_Impl for DropTarget_Impl {
+    fn DragEnter(&self, pdataobj: Ref<'_, IDataObject>, _grfkeystate: MODIFIERKEYS_FLAGS, _pt: &POINTL, pdweffect: *mut DROPEFFECT) -> Result<()> {
+        self.accepted.set(pdataobj.as_ref().is_some_and(can_drop));
+        unsafe { *pdweffect = self.drop_effect() };
+        Ok(())
+    }
+
+    fn DragOver(&self, _grfkeystate: MODIFIERKEYS_FLAGS, _pt: &POINTL, pdweffect: *mut DROPEFFECT) -> Result<()> {
+        unsafe { *pdweffect = self.drop_effect() };
+        Ok(())
+    }
+
+    fn DragLeave(&self) -> Result<()> {
+        self.accepted.set(false);
+        Ok(())
+    }
+
+    fn Drop(&self, pdataobj: Ref<'_, IDataObject>, _grfkeystate: MODIFIERKEYS_FLAGS, _pt: &POINTL, pdweffect: *mut DROPEFFECT) -> Result<()> {
+        self.accepted.set(false);
+        let item = pdataobj.as_ref().and_then(dropped_item);
+        unsafe { *pdweffect = if item.is_some() { DROPEFFECT_COPY } else { DROPEFFECT_NONE } };
+        if let Some(item) = item {
+            (self.on_drop)(item);
+        }
+        Ok(())
+    }
+}
+
+fn format_etc(format: u16, index: i32, tymed: TYMED) -> FORMATETC {
+    FORMATETC {
+        cfFormat: format,
+        ptd: std::ptr::null_mut(),
+        dwAspect: DVASPECT_CONTENT.0,
+        lindex: index,
+        tymed: tymed.0 as u32,
+    }
+}
+
+fn file_descriptor_format() -> u16 {
+    unsafe { RegisterClipboardFormatW(CFSTR_FILEDESCRIPTORW) as u16 }
+}
+
+/// Returns whether the data holds files or virtual files.
+fn can_drop(data: &IDataObject) -> bool {
+    [CF_HDROP.0, file_descriptor_format()]
+        .into_iter()
+        .any(|format| unsafe { data.QueryGetData(&format_etc(format, -1, TYMED_HGLOBAL)) } == S_OK)
+}
+
+/// Extracts the first file from dropped data, preferring files on disk.
+fn dropped_item(data: &IDataObject) -> Option<DroppedItem> {
+    dropped_file(data).or_else(|| dropped_virtual_file(data))
+}
+
+fn dropped_file(data: &IDataObject) -> Option<DroppedItem> {
+    let mut medium = unsafe { data.GetData(&format_etc(CF_HDROP.0, -1, TYMED_HGLOBAL)) }.ok()?;
+    let hdrop = HDROP(unsafe { medium.u.hGlobal }.0);
+    let path_len = unsafe { DragQueryFileW(hdrop, 0, None) } as usize;
+    let mut buffer = vec![0u16; path_len + 1];
+    let copied = unsafe { DragQueryFileW(hdrop, 0, Some(&mut buffer)) } as usize;
+    unsafe { ReleaseStgMedium(&mut medium) };
+    (copied > 0).then(|| DroppedItem::File(PathBuf::from(OsString::from_wide(&buffer[..copied]))))
+}
+
+fn dropped_virtual_file(data: &IDataObject) -> Option<DroppedItem> {
+    let mut medium = unsafe { data.GetData(&format_etc(file_descriptor_format(), -1, TYMED_HGLOBAL)) }.ok()?;
+    let descriptor = unsafe {
+        let hglobal = medium.u.hGlobal;
+        let group = GlobalLock(hglobal) as *const FILEGROUPDESCRIPTORW;
+        let descriptor = (!group.is_null() && (*group).cItems > 0).then(|| (*group).fgd[0]);
+        let _ = GlobalUnlock(hglobal);
+        ReleaseStgMedium(&mut medium);
+        descriptor
+    }?;
+    // Copied out of the packed struct to be borrowed
+    let file_name = descriptor.cFileName;
+    let name_len = file_name.iter().position(|&unit| unit == 0).unwrap_or(file_name.len());
+    let name = String::from_utf16_lossy(&file_name[..name_len]);
+    // The size is optional; an HGLOBAL may be larger than the file it holds
+    let size = (descriptor.dwFlags & FD_FILESIZE.0 as u32 != 0)
+        .then(|| ((descriptor.nFileSizeHigh as u64) << 32 | descriptor.nFileSizeLow as u64).try_into().unwrap_or(usize::MAX));
+
+    // Sources that support it keep the content available until told that
+    // it was read; others keep it while the data object is referenced
+    let operation = data
+        .cast::<IDataObjectAsyncCapability>()
+        .ok()
+        .filter(|operation| unsafe { operation.GetAsyncMode() }.is_ok_and(|is_async| is_async.as_bool()))
+        .filter(|operation| unsafe { operation.StartOperation(None) }.is_ok());
+    Some(DroppedItem::VirtualFile(VirtualFile { name, size, data: data.clone(), operation }))
+}
+
+fn too_large() -> String {
+    format!("The file is larger than {} MB.", MAX_VIRTUAL_FILE_SIZE / (1024 * 1024))
+}
+
+/// Reads the bytes of a memory or stream medium, at most `size` bytes if given.
+fn read_medium(medium: &STGMEDIUM, size: Option<usize>) -> std::result::Result<Vec<u8>, String> {
+    // Memory and streams may hold more than the file, e.g. padding
+    let limit = size.unwrap_or(usize::MAX);
+    let mut content = Vec::new();
+    match TYMED(medium.tymed as i32) {
+        TYMED_HGLOBAL => unsafe {
+            let hglobal = medium.u.hGlobal;
+            let len = limit.min(GlobalSize(hglobal));
+            if len > MAX_VIRTUAL_FILE_SIZE {
+                return Err(too_large());
+            }
+            let bytes = GlobalLock(hglobal) as *const u8;
+            if bytes.is_null() {
+                return Err("The dropped data could not be accessed.".to_string());
+            }
+            content.extend_from_slice(std::slice::from_raw_parts(bytes, len));
+            let _ = GlobalUnlock(hglobal);
+        },
+        TYMED_ISTREAM => {
+            let stream = unsafe { (*medium.u.pstm).as_ref() }.ok_or("The dropped data has no stream.")?;
+            let mut buffer = vec![0u8; 64 * 1024];
+            while content.len() < limit {
+                let wanted = (limit - content.len()).min(buffer.len());
+                let mut read = 0u32;
+                unsafe { stream.Read(buffer.as_mut_ptr() as *mut _, wanted as u32, Some(&mut read)) }.ok().map_err(|e| e.to_string())?;
+                if read == 0 {
+                    break;
+                }
+                content.extend_from_slice(&buffer[..read as usize]);
+                if content.len() > MAX_VIRTUAL_FILE_SIZE {
+                    return Err(too_large());
+                }
+            }
+        }
+        _ => return Err("The dropped data is in an unsupported format.".to_string()),
+    }
+    Ok(content)
+}
@@ -1,39 +1,159 @@
 use windows::{
     core::{w, PCWSTR},
     Win32::{
-        Foundation::{HWND, LPARAM, LRESULT, WPARAM},
+        Foundation::{COLORREF, ERROR_CLASS_ALREADY_EXISTS, HWND, LPARAM, LRESULT, POINT, RECT, WPARAM},
         Graphics::Gdi::{
             BeginPaint, EndPaint, GetDC, GetStockObject, GetTextMetricsW, InvalidateRect,
             ReleaseDC, SelectObject, TextOutW, ANSI_FIXED_FONT, HBRUSH, HDC, HFONT,
-            PAINTSTRUCT, TEXTMETRICW, FillRect, COLOR_WINDOW, GetSysColorBrush
+            PAINTSTRUCT, TEXTMETRICW, FillRect, COLOR_WINDOW, GetSysColorBrush,
+            CreateSolidBrush, DeleteObject, SetBkColor, SetTextColor, GetSysColor, COLOR_WINDOWTEXT,
+            SetBkMode, BACKGROUND_MODE, COLOR_INFOBK, COLOR_INFOTEXT, TRANSPARENT, FrameRect,
+            CreateFontIndirectW, GetObjectW, LOGFONTW, OUT_TT_PRECIS, InvertRect, ClientToScreen,
+            SetViewportOrgEx, UpdateWindow,
         },
         System::LibraryLoader::GetModuleHandleW,
+        System::SystemServices::{MK_CONTROL, MK_SHIFT},
+        UI::Controls::{
+            SetScrollInfo, EM_GETMODIFY, EM_GETSEL, EM_LINEFROMCHAR, EM_LINEINDEX, EM_REPLACESEL, EM_SETMODIFY, EM_SETSEL,
+        },
+        UI::Input::KeyboardAndMouse::{
+            GetCapture, GetKeyState, ReleaseCapture, SetCapture, SetFocus, VIRTUAL_KEY, VK_CONTROL, VK_DOWN, VK_END, VK_HOME,
+            VK_LEFT, VK_NEXT, VK_PRIOR, VK_RIGHT, VK_SHIFT, VK_UP, VK_MENU,
+        },
         UI::WindowsAndMessaging::{
-            CreateWindowExW, DefWindowProcW, GetWindowLongPtrW, LoadCursorW,
+            CreateWindowExW, DefWindowProcW, GetClientRect, GetWindowLongPtrW, LoadCursorW,
             RegisterClassW, SendMessageW, SetWindowLongPtrW, IDC_ARROW, WINDOW_EX_STYLE,
             WNDCLASSW, WS_CHILD, WS_HSCROLL, WS_VISIBLE, WS_VSCROLL, 
-            WM_NCCREATE, WM_NCDESTROY, WM_PAINT, WM_SETFONT, WM_USER, WINDOW_LONG_PTR_INDEX,
+            WM_KEYDOWN, WM_NCCREATE, WM_NCDESTROY, WM_PAINT, WM_SETFONT, WM_TIMER, WINDOW_LONG_PTR_INDEX,
+            WM_FONTCHANGE, WM_SETTINGCHANGE, WM_SYSCOLORCHANGE, WM_THEMECHANGED, WM_MOUSEWHEEL,
+            WM_GETTEXT, WM_GETTEXTLENGTH, WM_SETTEXT, WM_CHAR, WM_SETFOCUS, WM_KILLFOCUS, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MOUSEMOVE, WM_CUT, WM_COPY, WM_PASTE,
+            CreateCaret, DestroyCaret, SetCaretPos, ShowCaret, SystemParametersInfoW, SPI_GETCARETWIDTH, KillTimer, SetTimer,
+            SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS, WM_CONTEXTMENU, AppendMenuW, CreatePopupMenu, DestroyMenu,
+            TrackPopupMenu, MF_ENABLED, MF_GRAYED, MF_STRING, TPM_RETURNCMD, TPM_RIGHTBUTTON,
+            GetScrollInfo, ScrollWindowEx, SCROLLBAR_COMMAND, SCROLLBAR_CONSTANTS, SCROLLINFO, SB_BOTTOM, SB_HORZ,
+            SB_LEFT, SB_LINEDOWN, SB_LINELEFT, SB_LINERIGHT, SB_LINEUP, SB_PAGEDOWN, SB_PAGELEFT, SB_PAGERIGHT,
+            SB_PAGEUP, SB_RIGHT, SB_THUMBPOSITION, SB_THUMBTRACK, SB_TOP, SB_VERT, SIF_PAGE, SIF_POS, SIF_RANGE,
+            SIF_TRACKPOS, SW_INVALIDATE, WM_HSCROLL, WM_SIZE, WM_VSCROLL, WM_MOUSEHWHEEL, SPI_GETWHEELSCROLLCHARS,
+            SPI_GETWHEELSCROLLLINES, WHEEL_DELTA, WM_MBUTTONDOWN, MSG, PeekMessageW,
+            PM_REMOVE, WM_SYSCHAR, WM_SYSKEYDOWN, GetParent, WM_COMMAND,
         },
     },
 };
-use std::ffi::OsStr;
-use std::os::windows::ffi::OsStringExt;
-use std::{error::Error, path::Path, ptr};
-use crate::document::text_document::TextDocument;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::{cell::RefCell, error::Error, ops::{Range, RangeInclusive}, path::{Path, PathBuf}, ptr, time::Instant};
+use crate::command::command_manager::{Coalesce, CommandManager};
+use crate::command::commands::{AffectedLines, Command, DeleteCommand, InsertCommand};
+use crate::document::{file_io::{self, LineEnding}, templates, text_document::TextDocument};
+use crate::document::format::{FormatStyle, FormatterRegistry, DEFAULT_INDENT};
+use crate::ui::control::{
+    EVM_CLEARFILE, EVM_FORMATDOCUMENT, EVM_GETALIGNEDVIEW, EVM_GETENCODINGERRORS, EVM_GETFILEPATH,
+    EVM_GETZOOMSYNC, EVM_NEWFROMTEMPLATE, EVM_OPENFILE, EVM_OPENLOADEDFILE, EVM_RESTOREFILE, EVM_SAVEFILE,
+    EVM_SETALIGNEDVIEW, EVM_GETFREEZEHEADER, EVM_SETFREEZEHEADER, EVM_SETZOOMSYNC, EVM_SUSPENDTIMERS, EVM_SETAUTOCOPY, EVM_GETAUTOCOPY, EVM_SETSCROLLMARGIN, EVM_SETKEYBINDINGS, EVM_GETKEYBINDINGS, EVM_ZOOM, EVN_MODIFIEDCHANGE, SAVE_ENCODING_UTF8,
+    SAVE_ENCODING_UTF8_BOM, SAVE_LINE_ENDINGS_CRLF, SAVE_LINE_ENDINGS_LF,
+};
+use crate::ui::announce;
+use crate::ui::clipboard;
+use crate::ui::csv_layout::{self, AlignedField, AlignedView};
+use crate::ui::emacs_keys::{self, EmacsCommand, EmacsState};
+use crate::ui::decorations::{self, Decoration, DecorationKind, DecorationProvider};
+use crate::ui::keymap::{Chord, KeyBindings, Keymap, Lookup};
+use crate::ui::line_layout::{self, LineRun};
+use crate::ui::frame_pacer::{FramePacer, RENDER_TIMER_ID};
+use crate::ui::idle_scheduler::{IdleScheduler, IdleTask, IDLE_TIMER_ID};
+use crate::ui::metrics::Metrics;
+use crate::ui::render_cache::{CachedRun, RenderCache};
+use crate::ui::selection::Selection;
+use crate::ui::standard_keys::{self, Movement, StandardCommand};
+use crate::ui::usage_stats;
+use crate::ui::vi_mode::{self, Action, InsertAt, Mode, Motion, Operator, Register, Target, ViState};
+use crate::ui::zoom::{ZoomSettings, DEFAULT_ZOOM_PERCENT};
 
 const EDITOR_VIEW_CLASS: PCWSTR = w!("EditorView32");
 
-const EVM_OPENFILE: u32 = WM_USER + 1;
-const EVM_CLEARFILE: u32 = WM_USER + 2;
+// Height in pixels of the color swatch drawn under color codes
+const SWATCH_HEIGHT: i32 = 4;
+
+// Color of the marker drawn under characters that replaced invalid byte sequences
+const ENCODING_ERROR_COLOR: COLORREF = COLORREF(0x000000FF); // Red (0x00BBGGRR)
+
+// Wheel scroll setting meaning a page per notch (not defined by the windows crate)
+const WHEEL_PAGESCROLL: u32 = u32::MAX;
+
+// Items of the context menu
+const CONTEXT_MENU_CUT: u32 = 1;
+const CONTEXT_MENU_COPY: u32 = 2;
+const CONTEXT_MENU_PASTE: u32 = 3;
+
+// Most bytes a vi put inserts; a larger count puts fewer copies
+const MAX_PUT_LEN: usize = 16 * 1024 * 1024;
+
+// Timer that starts measuring the aligned columns again once editing pauses
+const COLUMN_RESCAN_TIMER_ID: usize = 3;
+const COLUMN_RESCAN_DELAY_MS: u32 = 500;
 
 pub struct EditorView {
     hwnd: HWND,
     document: TextDocument,
-    caret_pos: usize,
+    file_path: Option<PathBuf>,
+    /// The modified state of the document last reported to the parent window.
+    reported_modified: bool,
+    /// Set while the view has the keyboard focus and owns the system caret.
+    has_focus: bool,
+    /// Set while the parent window suspended the background timers. The caret
+    /// is hidden then, as the system blinks it with a timer.
+    timers_suspended: bool,
+    /// High surrogate of a character typed outside the BMP, waiting for its low surrogate.
+    pending_surrogate: Option<u16>,
+    /// The selection, as byte offsets into the document. Its active end is
+    /// where the caret is; an empty selection is just the caret.
+    selection: Selection,
+    /// Screen column Up/Down and Page Up/Down aim for while moving vertically,
+    /// so the caret returns to it after passing through shorter lines.
+    goal_column: Option<usize>,
+    /// Set when selections are copied to the clipboard as soon as they are
+    /// made, and middle-click pastes, as in X11 applications.
+    auto_copy: bool,
+    /// Lines kept visible above and below the caret when scrolling to it.
+    scroll_margin: usize,
+    /// Mode and registers of the vi key bindings, while they are on.
+    vi: Option<ViState>,
+    /// Key sequence and mark of the Emacs key bindings, while they are on.
+    emacs: Option<EmacsState>,
+    /// The keys that work in every scheme, unless Emacs key bindings take them over.
+    standard_keys: Keymap<StandardCommand>,
     font_height: i32,
     font_width: i32,
+    /// The font currently drawn with: `base_font`, or `zoomed_font` when zoomed.
     hfont: HFONT,
+    /// The font set with WM_SETFONT.
+    base_font: HFONT,
+    /// A scaled copy of `base_font` owned by the view, while the zoom isn't 100%.
+    zoomed_font: Option<HFONT>,
+    zoom: ZoomSettings,
+    command_manager: CommandManager,
     line_count: usize,
+    /// First line shown at the top of the view.
+    first_visible_line: usize,
+    /// How far the view is scrolled to the right, in pixels.
+    scroll_x: i32,
+    /// Length in bytes of the longest line, which sets the horizontal scroll
+    /// range. It only grows while editing, until the document is replaced.
+    longest_line_len: usize,
+    /// Wheel movement not yet scrolled by, vertically and horizontally, as
+    /// high-resolution wheels report fractions of a notch.
+    wheel_remainder: (i32, i32),
+    render_cache: RefCell<RenderCache>,
+    idle_scheduler: RefCell<IdleScheduler>,
+    frame_pacer: FramePacer,
+    metrics: RefCell<Metrics>,
+    show_debug_overlay: bool,
+    /// Set while delimited files are shown with aligned columns.
+    aligned_view: RefCell<Option<AlignedView>>,
+    /// Set to keep the first line of aligned columns at the top of the view
+    /// as their header row, covering the line scrolled under it.
+    freeze_header: bool,
+    /// Providers of decorations (e.g. color swatches) for the current file type.
+    decoration_providers: Vec<Box<dyn DecorationProvider>>,
 }
 
 impl EditorView {
@@ -56,11 +176,38 @@ impl EditorView {
         let mut view = Self {
             hwnd,
             document,
-            caret_pos: 0,
+            file_path: None,
+            reported_modified: false,
+            has_focus: false,
+            timers_suspended: false,
+            pending_surrogate: None,
+            selection: Selection::caret(0),
+            goal_column: None,
+            auto_copy: false,
+            scroll_margin: 0,
+            vi: None,
+            emacs: None,
+            standard_keys: standard_keys::keymap(),
             font_height: 0, // Will be set by update_font_metrics
             font_width: 0,  // Will be set by update_font_metrics
             hfont,
+            base_font: hfont,
+            zoomed_font: None,
+            zoom: ZoomSettings::new(),
+            command_manager: CommandManager::new(),
             line_count,
+            first_visible_line: 0,
+            scroll_x: 0,
+            longest_line_len: 0,
+            wheel_remainder: (0, 0),
+            render_cache: RefCell::new(RenderCache::new()),
+            idle_scheduler: RefCell::new(IdleScheduler::new()),
+            frame_pacer: FramePacer::new(),
+            metrics: RefCell::new(Metrics::new()),
+            show_debug_overlay: false,
+            freeze_header: false,
+            aligned_view: RefCell::new(None),
+            decoration_providers: Vec::new(),
         };
         // Calculate initial font metrics, log error if it fails
         if let Err(e) = view.update_font_metrics() {
@@ -103,19 +250,86 @@ impl EditorView {
             self.font_height = tm.tmHeight + tm.tmExternalLeading;
             self.font_width = tm.tmAveCharWidth;
         }
+        // The caret's height follows the font
+        if self.shows_caret() {
+            self.destroy_caret();
+            self.create_caret();
+        }
         Ok(())
     }
 
     /// Handles the WM_SETFONT message. Updates the font and recalculates metrics.
     pub fn on_set_font(&mut self, new_hfont: HFONT) -> Result<(), Box<dyn Error>> {
-        self.hfont = new_hfont;
-        self.update_font_metrics()?; // Recalculate metrics
+        self.base_font = new_hfont;
+        self.apply_zoom()?; // Rescale the font and recalculate metrics
         unsafe { InvalidateRect(Some(self.hwnd), None, true); } 
         Ok(())
     }
+    /// Selects the font for the current document's zoom level, the base font
+    /// at 100% and a scaled copy of it otherwise, and recalculates the metrics.
+    fn apply_zoom(&mut self) -> Result<(), Box<dyn Error>> {
+        let percent = self.zoom.zoom_for(self.file_path.as_deref());
+        let zoomed_font = if percent == DEFAULT_ZOOM_PERCENT {
+            None
+        } else {
+            let mut logfont = LOGFONTW::default();
+            let size = std::mem::size_of::<LOGFONTW>() as i32;
+            if unsafe { GetObjectW(self.base_font.into(), size, Some(&mut logfont as *mut LOGFONTW as *mut _)) } == 0 {
+                return Err("GetObjectW failed".into());
+            }
+            logfont.lfHeight = logfont.lfHeight * percent as i32 / 100;
+            logfont.lfWidth = logfont.lfWidth * percent as i32 / 100;
+            // Bitmap fonts such as the stock fixed font don't scale; prefer an outline font
+            logfont.lfOutPrecision = OUT_TT_PRECIS;
+            let font = unsafe { CreateFontIndirectW(&logfont) };
+            if font.is_invalid() {
+                return Err("CreateFontIndirectW failed".into());
+            }
+            Some(font)
+        };
+
+        if let Some(old_font) = std::mem::replace(&mut self.zoomed_font, zoomed_font) {
+            let _ = unsafe { DeleteObject(old_font.into()) };
+        }
+        self.hfont = self.zoomed_font.unwrap_or(self.base_font);
+        self.update_font_metrics()?;
+        self.update_scroll_bars();
+        self.frame_pacer.invalidate(self.hwnd, None);
+        Ok(())
+    }
+
+    /// Zooms the current document in (positive `steps`) or out, or back to the default for 0.
+    fn zoom(&mut self, steps: i32) {
+        let percent = self.zoom.zoom(self.file_path.as_deref(), steps);
+        if let Err(e) = self.apply_zoom() {
+            eprintln!("Failed to apply zoom: {}", e);
+        }
+        announce::announce(self.hwnd, &format!("Zoom {}%", percent), "Zoom");
+    }
+
+    /// Turns on or off using one zoom level for all documents.
+    fn set_zoom_sync(&mut self, sync_all: bool) {
+        self.zoom.set_sync_all(sync_all, self.file_path.as_deref());
+        if let Err(e) = self.apply_zoom() {
+            eprintln!("Failed to apply zoom: {}", e);
+        }
+    }
+
+    /// Handles changes of system settings (colors, theme, font smoothing, installed
+    /// fonts, locale), forwarded by the main window: recalculates the font metrics,
+    /// drops cached layouts and repaints with the new settings.
+    pub fn on_settings_change(&mut self) {
+        if let Err(e) = self.update_font_metrics() {
+            eprintln!("Failed to update font metrics after a settings change: {}", e);
+        }
+        self.render_cache.get_mut().clear();
+        self.frame_pacer.invalidate(self.hwnd, None);
+    }
+
     /// WM_PAINT handler for the text view.
     /// This method begins painting, draws the text, and ends painting.
     pub fn on_paint(&self) -> Result<(), Box<dyn Error>> {
+        let paint_start = Instant::now();
         let mut ps = PAINTSTRUCT::default();
         unsafe {
             let hdc = BeginPaint(self.hwnd, &mut ps);
@@ -127,13 +341,55 @@ impl EditorView {
 
             // Select the editor's font into the DC
             let old_font = SelectObject(hdc, self.hfont.into());
+            // Lines are drawn at their column positions, shifted left by the
+            // horizontal scroll position
+            let _ = SetViewportOrgEx(hdc, -self.scroll_x, 0, None);
 
             // Calculate the first and last line based on the paint area and font height
             let num_lines = self.document.line_count();
-            let first_line = ps.rcPaint.top / self.font_height;
-            let last_line = std::cmp::min(ps.rcPaint.bottom / self.font_height, num_lines as i32 - 1);
-            for line in first_line..=last_line {
-                self.paint_line(hdc, line)?;
+            let first_line = (self.first_visible_line as i32).saturating_add(ps.rcPaint.top / self.font_height);
+            let last_line = std::cmp::min(
+                (self.first_visible_line as i32).saturating_add(ps.rcPaint.bottom / self.font_height),
+                num_lines as i32 - 1,
+            );
+            // Only lay out as many columns as reach the right edge of the paint area
+            let max_columns = ((ps.rcPaint.right + self.scroll_x) / self.font_width.max(1) + 1) as usize;
+            let header_frozen = self.header_frozen();
+            if let Some(aligned_view) = self.aligned_view.borrow_mut().as_mut() {
+                aligned_view.update(&self.document);
+                for line in first_line..=last_line {
+                    let y = self.row_top(line as usize);
+                    // The header row is drawn in place of the line scrolled to the top
+                    let line = if header_frozen && line as usize == self.first_visible_line { 0 } else { line };
+                    self.paint_aligned_line(hdc, aligned_view, line, y, max_columns)?;
+                }
+            } else {
+                for line in first_line..=last_line {
+                    self.paint_line(hdc, line, max_columns)?;
+                }
+            }
+
+            {
+                let mut metrics = self.metrics.borrow_mut();
+                metrics.record_timing("paint time", paint_start.elapsed());
+                metrics.set_value("lines painted", (last_line - first_line + 1).max(0) as u64);
+                metrics.set_value("render cache hit %", self.render_cache.borrow().hit_rate_percent());
+            }
+            if self.show_debug_overlay {
+                // The overlay stays in the corner of the view
+                let _ = SetViewportOrgEx(hdc, 0, 0, None);
+                self.paint_debug_overlay(hdc);
+            }
+
+            // Lay out the next screenful while idle so it is ready when scrolled to
+            let next_line = (last_line + 1).max(0) as usize;
+            let screen_lines = (last_line - first_line + 1).max(0) as usize;
+            if next_line < num_lines {
+                self.idle_scheduler.borrow_mut().schedule(self.hwnd, IdleTask::PrewarmLines {
+                    next: next_line,
+                    end: std::cmp::min(next_line + screen_lines, num_lines),
+                    max_columns,
+                });
             }
 
             // Restore the original font
@@ -143,20 +399,38 @@ impl EditorView {
         Ok(())
     }
 
-    fn paint_line(&self, hdc: HDC, line_idx: i32) -> Result<(), Box<dyn Error>> {
+    fn paint_line(&self, hdc: HDC, line_idx: i32, max_columns: usize) -> Result<(), Box<dyn Error>> {
         // Safely convert line index (i32) to usize for getline
         if let Ok(line_usize) = usize::try_from(line_idx) {
             if let Some(line_text) = self.document.getline(line_usize) {
-                // Convert the Rust string to a null-terminated UTF-16 string
-                let text_wide: Vec<u16> = line_text.encode_utf16().chain(std::iter::once(0)).collect();
                 // Calculate the Y position based on the line number and font height
-                let y = line_idx * self.font_height; // Simple Y calculation
-                // Draw the text at position (0, y)
-                unsafe {
-                    if TextOutW(hdc, 0, y, &text_wide) == false { // Use bool false
-                        return Err("TextOutW failed".into());
+                let y = self.line_top(line_usize);
+                // Draw the line run by run, starting at position (0, y)
+                let cached_line = self.render_cache.borrow_mut()
+                    .get_or_layout(self.document.version(), line_usize, line_text, max_columns);
+                let mut column = 0;
+                for run in &cached_line.runs {
+                    let x = column as i32 * self.font_width;
+                    match run {
+                        CachedRun::Text(text_wide) => {
+                            unsafe {
+                                if TextOutW(hdc, x, y, text_wide) == false { // Use bool false
+                                    return Err("TextOutW failed".into());
+                                }
+                            }
+                        }
+                        CachedRun::Control(mnemonic) => self.paint_control_mnemonic(hdc, x, y, mnemonic)?,
                     }
+                    column += run.columns();
                 }
+                let visible_text = &line_text[..cached_line.visible_len];
+                // Skip offsets past the visible part of the line
+                let column_of = |offset: usize| (offset < visible_text.len()).then(|| line_layout::display_column(visible_text, offset));
+                self.paint_decorations(hdc, visible_text, y, column_of);
+                self.paint_encoding_errors(hdc, line_usize, y, column_of);
+                self.paint_selection(hdc, line_usize, line_text, y, |offset| {
+                    if offset <= visible_text.len() { line_layout::display_column(visible_text, offset) } else { max_columns }
+                });
             } else {
                 eprintln!("Warning: Invalid line index {} encountered during painting.", line_idx); // Keep commented for debugging
             }
@@ -164,130 +438,2474 @@ impl EditorView {
         Ok(())
     }
 
+    /// Paints a line of a delimited file with its fields padded into columns.
+    /// Every other column is tinted so rows are easy to follow across the screen.
+    /// Aligned lines bypass the render cache, as they depend on the column widths.
+    fn paint_aligned_line(&self, hdc: HDC, aligned_view: &AlignedView, line_idx: i32, y: i32, max_columns: usize) -> Result<(), Box<dyn Error>> {
+        let Ok(line_usize) = usize::try_from(line_idx) else {
+            return Ok(());
+        };
+        let Some(line_text) = self.document.getline(line_usize) else {
+            return Ok(());
+        };
+        let fields = aligned_view.layout_line(line_text, max_columns);
+
+        unsafe {
+            let tint_brush = CreateSolidBrush(column_tint(GetSysColor(COLOR_WINDOW)));
+            for field in &fields {
+                let x = field.start_column as i32 * self.font_width;
+                if field.column_index % 2 == 1 {
+                    let tint = RECT {
+                        left: x,
+                        top: y,
+                        right: x + field.width as i32 * self.font_width,
+                        bottom: y + self.font_height,
+                    };
+                    FillRect(hdc, &tint, tint_brush);
+                }
+
+                // Draw the text over the tint; mnemonics keep their own background
+                let old_bk_mode = SetBkMode(hdc, TRANSPARENT);
+                let mut column = field.start_column;
+                for run in line_layout::line_runs(&line_text[field.range.clone()], field.width) {
+                    let x = column as i32 * self.font_width;
+                    match run {
+                        LineRun::Text(text) => {
+                            let text_wide: Vec<u16> = text.encode_utf16().collect();
+                            let _ = TextOutW(hdc, x, y, &text_wide);
+                            column += text_wide.len();
+                        }
+                        LineRun::Control(mnemonic) => {
+                            SetBkMode(hdc, BACKGROUND_MODE(old_bk_mode as u32));
+                            self.paint_control_mnemonic(hdc, x, y, mnemonic)?;
+                            SetBkMode(hdc, TRANSPARENT);
+                            column += mnemonic.len();
+                        }
+                    }
+                }
+                SetBkMode(hdc, BACKGROUND_MODE(old_bk_mode as u32));
+            }
+            let _ = DeleteObject(tint_brush.into());
+        }
+
+        let column_of = |offset: usize| {
+            fields.iter().find(|field| field.range.contains(&offset)).map(|field| {
+                let field_text = &line_text[field.range.clone()];
+                field.start_column + line_layout::display_column(field_text, offset - field.range.start)
+            })
+        };
+        self.paint_decorations(hdc, line_text, y, column_of);
+        self.paint_encoding_errors(hdc, line_usize, y, column_of);
+        self.paint_selection(hdc, line_usize, line_text, y, |offset| {
+            aligned_column(&fields, line_text, offset).unwrap_or(max_columns)
+        });
+        Ok(())
+    }
+
+    /// Inverts the cells of the selected part of a line. `column_of` maps byte
+    /// offsets within the line, up to its end, to screen columns. A selected
+    /// line break is shown as one inverted cell after the text of the line.
+    fn paint_selection(&self, hdc: HDC, line: usize, line_text: &str, y: i32, column_of: impl Fn(usize) -> usize) {
+        let Some(line_start) = self.document.line_start(line) else {
+            return;
+        };
+        let line_end = line_start + line_text.len();
+        let selection = self.selection.range();
+        if selection.is_empty() || selection.end < line_start || selection.start > line_end {
+            return;
+        }
+        let start_column = column_of(selection.start.max(line_start) - line_start);
+        let mut end_column = column_of(selection.end.min(line_end) - line_start);
+        if selection.end > line_end {
+            end_column += 1;
+        }
+        if end_column > start_column {
+            let rect = RECT {
+                left: start_column as i32 * self.font_width,
+                top: y,
+                right: end_column as i32 * self.font_width,
+                bottom: y + self.font_height,
+            };
+            let _ = unsafe { InvertRect(hdc, &rect) };
+        }
+    }
+
+    /// Turns the aligned column view on or off. Returns whether it is now on;
+    /// it can only be turned on for delimited files such as .csv and .tsv.
+    fn set_aligned_view(&mut self, enable: bool) -> bool {
+        let delimiter = self.file_type_path().as_deref().and_then(csv_layout::delimiter_for);
+        let aligned_view = match delimiter {
+            Some(delimiter) if enable => Some(AlignedView::new(delimiter)),
+            _ => None,
+        };
+        let enabled = aligned_view.is_some();
+        *self.aligned_view.get_mut() = aligned_view;
+        self.frame_pacer.invalidate(self.hwnd, None);
+        self.update_scroll_bars();
+        self.update_caret_position();
+        enabled
+    }
+
+    /// Draws the debug overlay listing the collected metrics in the top-right corner.
+    fn paint_debug_overlay(&self, hdc: HDC) {
+        const MARGIN: i32 = 8;
+        const PADDING: i32 = 4;
+
+        let lines: Vec<Vec<u16>> = self.metrics.borrow()
+            .display_lines()
+            .iter()
+            .map(|line| line.encode_utf16().collect())
+            .collect();
+        let max_columns = lines.iter().map(|line| line.len()).max().unwrap_or(0) as i32;
+
+        unsafe {
+            let mut client = RECT::default();
+            let _ = GetClientRect(self.hwnd, &mut client);
+            let overlay = RECT {
+                left: client.right - MARGIN - max_columns * self.font_width - 2 * PADDING,
+                top: MARGIN,
+                right: client.right - MARGIN,
+                bottom: MARGIN + lines.len() as i32 * self.font_height + 2 * PADDING,
+            };
+            FillRect(hdc, &overlay, GetSysColorBrush(COLOR_INFOBK));
+
+            let old_text_color = SetTextColor(hdc, COLORREF(GetSysColor(COLOR_INFOTEXT)));
+            let old_bk_mode = SetBkMode(hdc, TRANSPARENT);
+            for (i, line) in lines.iter().enumerate() {
+                let y = overlay.top + PADDING + i as i32 * self.font_height;
+                let _ = TextOutW(hdc, overlay.left + PADDING, y, line);
+            }
+            SetBkMode(hdc, BACKGROUND_MODE(old_bk_mode as u32));
+            SetTextColor(hdc, old_text_color);
+        }
+    }
+
+    /// Shows or hides the debug overlay (hidden action: Ctrl+Shift+F12).
+    fn toggle_debug_overlay(&mut self) {
+        self.show_debug_overlay = !self.show_debug_overlay;
+        self.frame_pacer.invalidate(self.hwnd, None);
+    }
+
+    /// Handles the column rescan timer: measures the aligned columns again
+    /// while idle, now that editing paused.
+    fn start_column_rescan(&mut self) {
+        let _ = unsafe { KillTimer(Some(self.hwnd), COLUMN_RESCAN_TIMER_ID) };
+        self.idle_scheduler.get_mut().schedule(self.hwnd, IdleTask::MeasureColumns { next: 0 });
+    }
+
+    /// Handles the idle timer: runs a time slice of the queued idle tasks.
+    pub fn on_idle_timer(&mut self) {
+        let mut columns_measured = false;
+        self.idle_scheduler.get_mut().run_slice(self.hwnd, |task| match task {
+            IdleTask::PrewarmLines { next, end, max_columns } => {
+                if let Some(line_text) = self.document.getline(next) {
+                    self.render_cache.get_mut()
+                        .get_or_layout(self.document.version(), next, line_text, max_columns);
+                }
+                (next + 1 < end).then_some(IdleTask::PrewarmLines { next: next + 1, end, max_columns })
+            }
+            IdleTask::MeasureColumns { next } => {
+                let next = self.aligned_view.get_mut().as_mut()?.rescan_line(&self.document, next);
+                columns_measured |= next.is_none();
+                next.map(|next| IdleTask::MeasureColumns { next })
+            }
+        });
+        if columns_measured {
+            // Columns may have become narrower, which moves the fields of every line
+            self.update_scroll_bars();
+            self.update_caret_position();
+            self.frame_pacer.invalidate(self.hwnd, None);
+        }
+    }
+
+    /// Draws a control character mnemonic (e.g. "ESC") in reverse video so it
+    /// can't be mistaken for regular text.
+    fn paint_control_mnemonic(&self, hdc: HDC, x: i32, y: i32, mnemonic: &str) -> Result<(), Box<dyn Error>> {
+        let mnemonic_wide: Vec<u16> = mnemonic.encode_utf16().collect();
+        unsafe {
+            let old_text_color = SetTextColor(hdc, COLORREF(GetSysColor(COLOR_WINDOW)));
+            let old_bk_color = SetBkColor(hdc, COLORREF(GetSysColor(COLOR_WINDOWTEXT)));
+            let result = TextOutW(hdc, x, y, &mnemonic_wide);
+            SetTextColor(hdc, old_text_color);
+            SetBkColor(hdc, old_bk_color);
+            if result == false {
+                return Err("TextOutW failed".into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws the decorations the providers find on a line. `column_of` maps a
+    /// byte offset in the line to its screen column, as for paint_encoding_errors.
+    fn paint_decorations(&self, hdc: HDC, line_text: &str, y: i32, column_of: impl Fn(usize) -> Option<usize>) {
+        if self.decoration_providers.is_empty() {
+            return;
+        }
+        let mut line_decorations: Vec<Decoration> = Vec::new();
+        for provider in &self.decoration_providers {
+            provider.decorate_line(line_text, &mut line_decorations);
+        }
+
+        for decoration in line_decorations {
+            let Some(start_column) = column_of(decoration.range.start) else {
+                continue;
+            };
+            // A token cut off at the right edge is decorated up to the edge
+            let end_column = column_of(decoration.range.end)
+                .unwrap_or_else(|| start_column + decoration.range.len());
+            match decoration.kind {
+                DecorationKind::ColorSwatch { red, green, blue } => {
+                    // A bar under the token, framed so it shows on any background
+                    let swatch = RECT {
+                        left: start_column as i32 * self.font_width,
+                        top: y + self.font_height - SWATCH_HEIGHT,
+                        right: end_column as i32 * self.font_width,
+                        bottom: y + self.font_height,
+                    };
+                    let color = COLORREF(red as u32 | (green as u32) << 8 | (blue as u32) << 16);
+                    unsafe {
+                        let brush = CreateSolidBrush(color);
+                        FillRect(hdc, &swatch, brush);
+                        let _ = DeleteObject(brush.into());
+                        FrameRect(hdc, &swatch, GetSysColorBrush(COLOR_WINDOWTEXT));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Underlines the replacement characters that stand in for invalid byte
+    /// sequences on the given line, so damaged parts of the file stand out.
+    /// `column_of` maps a byte offset in the line to its screen column, or
+    /// None if that part of the line isn't drawn.
+    fn paint_encoding_errors(&self, hdc: HDC, line_usize: usize, y: i32, column_of: impl Fn(usize) -> Option<usize>) {
+        let error_offsets = self.document.encoding_errors_in_line(line_usize);
+        if error_offsets.is_empty() {
+            return;
+        }
+        unsafe {
+            let brush = CreateSolidBrush(ENCODING_ERROR_COLOR);
+            for offset in error_offsets {
+                let Some(column) = column_of(offset) else {
+                    continue;
+                };
+                let column = column as i32;
+                let marker = RECT {
+                    left: column * self.font_width,
+                    top: y + self.font_height - 2,
+                    right: (column + 1) * self.font_width,
+                    bottom: y + self.font_height,
+                };
+                FillRect(hdc, &marker, brush);
+            }
+            let _ = DeleteObject(brush.into());
+        }
+    }
+
     // File IO message handlers
     pub fn clear_file(&mut self) -> Result<(), Box<dyn Error>> {
         self.document.clear();
+        self.command_manager.clear();
+        self.set_caret(0);
+        self.file_path = None;
         self.line_count = self.document.line_count();
-        unsafe { InvalidateRect(Some(self.hwnd), None, true); }
+        self.reset_scroll();
+        *self.aligned_view.get_mut() = None;
+        self.decoration_providers.clear();
+        self.apply_zoom()?;
+        self.frame_pacer.invalidate(self.hwnd, None);
+        self.report_modified();
         Ok(())
     }
 
     pub fn open_file(&mut self, filename_pcwstr: PCWSTR) -> Result<(), Box<dyn Error>> {
-        self.clear_file()?;
         // Convert PCWSTR to &Path
         let path_osstr = unsafe { std::ffi::OsString::from_wide(filename_pcwstr.as_wide()) };
         let path = Path::new(&path_osstr);
 
-        self.document.init(path)?; 
+        let load_start = Instant::now();
+        let (text, replacement_offsets) = file_io::load(path)?;
+        self.metrics.borrow_mut().record_timing("document load", load_start.elapsed());
+        self.open_loaded_file(Some(path), text, replacement_offsets)
+    }
+
+    /// Opens the file at `path` with its content already read, e.g. by
+    /// file_io::read_in_background, along with the offsets of its replacement characters.
+    /// Without a path the content is opened as an untitled document.
+    pub fn open_loaded_file(&mut self, path: Option<&Path>, text: String, replacement_offsets: Vec<usize>) -> Result<(), Box<dyn Error>> {
+        let was_aligned = self.aligned_view.get_mut().is_some();
+        self.clear_file()?;
+        self.document.init_loaded(text, replacement_offsets)?;
+        self.file_path = path.map(Path::to_path_buf);
         self.line_count = self.document.line_count();
-        
+        self.reset_scroll();
+        self.decoration_providers = decorations::providers_for(self.file_type_path().as_deref());
+        self.apply_zoom()?;
+        // Keep the aligned view on when switching between delimited files
+        if was_aligned {
+            self.set_aligned_view(true);
+        }
+        self.report_modified();
         Ok(())
     }
 
-   // TODO: Additional methods handling scrolling, keyboard input, etc.
-}
+    /// Saves the document. A null `filename_pcwstr` saves to the current file path,
+    /// otherwise the document is saved to (and from now on associated with) the given path.
+    /// `flags` are SAVE_* conversions applied to the document first.
+    pub fn save_file(&mut self, filename_pcwstr: PCWSTR, flags: usize) -> Result<(), Box<dyn Error>> {
+        // The document takes a new name only once it has been saved under it
+        let new_path = (!filename_pcwstr.is_null())
+            .then(|| PathBuf::from(unsafe { std::ffi::OsString::from_wide(filename_pcwstr.as_wide()) }));
+        let path = new_path.as_deref().or(self.file_path.as_deref()).ok_or("Document has no file path")?.to_path_buf();
+        self.apply_save_conversions(flags)?;
+        let save_start = Instant::now();
+        file_io::save(&self.document, &path)?;
+        self.metrics.borrow_mut().record_timing("document save", save_start.elapsed());
+        if new_path.is_some() {
+            self.file_path = new_path;
+            // Saving under a new name can change the file type
+            self.decoration_providers = decorations::providers_for(self.file_type_path().as_deref());
+            self.frame_pacer.invalidate(self.hwnd, None);
+        }
+        // Replacement characters are now part of the file itself
+        if self.document.encoding_error_count() > 0 {
+            self.document.clear_encoding_errors();
+            self.frame_pacer.invalidate(self.hwnd, None);
+        }
+        self.document.set_modified(false);
+        self.report_modified();
+        Ok(())
+    }
 
-pub fn init_editor_view() -> Result<(), Box<dyn Error>> {
-    unsafe {
-        let hinstance = GetModuleHandleW(None)?;
+    /// Converts the document's line endings and byte order mark as requested
+    /// by SAVE_* flags, so the document shows exactly what is written to disk.
+    /// The conversion is one undo step; the selection keeps its place in the text.
+    fn apply_save_conversions(&mut self, flags: usize) -> Result<(), Box<dyn Error>> {
+        if flags == 0 {
+            return Ok(());
+        }
+        let content = self.document.get_content();
+        let converted = save_conversion(content, flags);
+        if converted == content {
+            return Ok(());
+        }
+        // The text before an offset converts into the text before its new offset
+        let map_offset = |offset: usize| {
+            let mut before = &content[..offset];
+            // An offset within a CRLF stays before it
+            if content[offset..].starts_with('\n') {
+                before = before.strip_suffix('\r').unwrap_or(before);
+            }
+            save_conversion(before, flags).len()
+        };
+        let selection = Selection::new(map_offset(self.selection.anchor), map_offset(self.selection.active));
 
-        // Load the standard arrow cursor
-        let hcursor = LoadCursorW(None, IDC_ARROW)?;
+        let len = self.document.len();
+        let affected = self.command_manager.execute(Box::new(DeleteCommand::new(0, len)), &mut self.document)?;
+        let affected = affected.union(self.command_manager.execute_in_last_step(Box::new(InsertCommand::new(0, converted)), &mut self.document)?);
+        self.update_after_edit(affected);
+        self.move_caret(selection.anchor, false);
+        self.move_caret(selection.active, true);
+        self.frame_pacer.invalidate(self.hwnd, None);
+        Ok(())
+    }
 
-        let wc = WNDCLASSW {
-            hInstance: hinstance.into(),
-            lpszClassName: EDITOR_VIEW_CLASS,
-            lpfnWndProc: Some(wndproc),
-            hCursor: hcursor,
-            hbrBackground: HBRUSH(ptr::null_mut()), // No background brush (we handle painting)
-            cbWndExtra: std::mem::size_of::<*mut EditorView>() as i32, // Reserve extra space for pointer
-            ..Default::default()
+    /// Starts a new untitled document filled with the expanded template at the given path.
+    pub fn new_from_template(&mut self, template_pcwstr: PCWSTR) -> Result<(), Box<dyn Error>> {
+        let path_osstr = unsafe { std::ffi::OsString::from_wide(template_pcwstr.as_wide()) };
+        let (template, _) = file_io::load(Path::new(&path_osstr))?;
+        self.clear_file()?;
+        self.document.set_content(templates::expand(&template));
+        self.line_count = self.document.line_count();
+        self.reset_scroll();
+        self.report_modified();
+        Ok(())
+    }
+
+    /// Replaces the document content with a local history snapshot.
+    /// The document stays associated with its original file path.
+    pub fn restore_file(&mut self, filename_pcwstr: PCWSTR) -> Result<(), Box<dyn Error>> {
+        let path_osstr = unsafe { std::ffi::OsString::from_wide(filename_pcwstr.as_wide()) };
+        self.document.init(Path::new(&path_osstr))?;
+        // The snapshot differs from the file until it is saved
+        self.document.set_modified(true);
+        self.command_manager.clear();
+        self.set_caret(0);
+        self.line_count = self.document.line_count();
+        self.reset_scroll();
+        self.frame_pacer.invalidate(self.hwnd, None);
+        self.report_modified();
+        Ok(())
+    }
+
+    /// Reformats the whole document with the formatter registered for its file
+    /// type, indenting by `indent` spaces per level. The change is one undo step.
+    pub fn format_document(&mut self, style: FormatStyle, indent: usize) -> Result<(), Box<dyn Error>> {
+        let registry = FormatterRegistry::with_builtin(indent);
+        let content = self.document.get_content();
+        let formatter = registry
+            .for_document(self.file_type_path().as_deref(), content)
+            .ok_or("No formatter is available for this file type")?;
+
+        // Formatters work with '\n' line breaks. CRLFs are turned into them first
+        // and back once at the end, so CRLFs kept in text and comments stay intact.
+        let crlf = content.contains("\r\n");
+        let mut formatted = if crlf {
+            formatter.format(&content.replace("\r\n", "\n"), style)?
+        } else {
+            formatter.format(content, style)?
         };
+        if crlf {
+            formatted = formatted.replace('\n', "\r\n");
+        }
+        if formatted == content {
+            return Ok(());
+        }
 
-        // Register the window class
-        if RegisterClassW(&wc) == 0 {
-            return Err(windows::core::Error::from_win32().into());
+        // Replaced as a whole in one undo step, keeping the earlier history
+        let len = self.document.len();
+        let affected = self.command_manager.execute(Box::new(DeleteCommand::new(0, len)), &mut self.document)?;
+        let affected = affected.union(self.command_manager.execute_in_last_step(Box::new(InsertCommand::new(0, formatted)), &mut self.document)?);
+        self.update_after_edit(affected);
+        self.set_caret(0);
+        self.reset_scroll();
+        self.frame_pacer.invalidate(self.hwnd, None);
+        Ok(())
+    }
+
+    /// Returns the path that determines the document's file type: the file
+    /// path, without the stream name for alternate data streams.
+    fn file_type_path(&self) -> Option<PathBuf> {
+        self.file_path.as_deref().map(|path| file_io::split_data_stream(path).0)
+    }
+
+    /// Copies the current file path into `buffer` as a null-terminated wide string.
+    /// Returns the path length in characters, or 0 for an untitled document.
+    fn get_file_path(&self, buffer: &mut [u16]) -> usize {
+        let Some(path) = &self.file_path else {
+            return 0;
+        };
+        let path_wide: Vec<u16> = path.as_os_str().encode_wide().collect();
+        if buffer.is_empty() {
+            return path_wide.len();
         }
+        let copy_len = std::cmp::min(path_wide.len(), buffer.len() - 1);
+        buffer[..copy_len].copy_from_slice(&path_wide[..copy_len]);
+        buffer[copy_len] = 0;
+        copy_len
     }
-    Ok(())
-}
 
-pub fn create_editor_view(hwnd_parent: HWND) -> Result<HWND, Box<dyn Error>> {
-    unsafe {
-        let hinstance = GetModuleHandleW(None)?;
-        eprintln!("hinstance: {:?}", hinstance);
+    /// Returns the selection, e.g. for clipboard and delete operations.
+    pub fn selection(&self) -> Selection {
+        self.selection
+    }
 
-        let hwnd = CreateWindowExW(
-            WINDOW_EX_STYLE::default(),                      // Optional window styles
-            EDITOR_VIEW_CLASS,                               // Window class name
-            EDITOR_VIEW_CLASS,                                          // Window title (none)
-            WS_CHILD | WS_VISIBLE | WS_VSCROLL | WS_HSCROLL, // Window styles
-            0, 0, 0, 0,                                      // Position and size (set later)
-            Some(hwnd_parent),                               // Wrap hwnd_parent in Some()
-            None,                                            // No menu or child ID
-            Some(hinstance.into()),                          // Wrap hinstance in Some() and convert
-            None,                                            // No additional application data
-        )?;
+    /// Returns the selected text, which is empty without a selection.
+    pub fn selected_text(&self) -> &str {
+        &self.document.get_content()[self.selection.range()]
+    }
 
-        eprintln!("CreateWindowExW result: {:?}", hwnd);
+    /// Moves the caret to the byte offset `offset`, leaving no selection.
+    fn set_caret(&mut self, offset: usize) {
+        self.move_caret(offset, false);
+    }
 
-        Ok(hwnd)
+    /// Moves the caret to `offset`. With `extend` the selection grows or shrinks
+    /// from its anchor, otherwise it collapses at the caret. Repaints the lines
+    /// whose selection changed.
+    fn move_caret(&mut self, offset: usize, extend: bool) {
+        self.goal_column = None;
+        let old = self.selection;
+        self.selection.move_to(offset, extend);
+        if !old.is_empty() || !self.selection.is_empty() {
+            let changed = if old.anchor == self.selection.anchor {
+                // Only the part between the old and the new caret changed
+                old.active.min(offset)..old.active.max(offset)
+            } else {
+                old.start().min(self.selection.start())..old.end().max(self.selection.end())
+            };
+            let first_line = self.document.line_from_offset(changed.start);
+            let last_line = self.document.line_from_offset(changed.end);
+            self.invalidate_lines(first_line..=last_line);
+        }
+        self.update_caret_position();
+        self.ensure_caret_visible();
     }
-}
 
-extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
-    unsafe {
+    /// Moves the caret for the arrow keys, Home/End and Page Up/Down; with Ctrl,
+    /// Home and End go to the start and end of the document. With Shift the
+    /// selection is extended. Returns false for other keys.
+    fn on_navigation_key(&mut self, key: VIRTUAL_KEY, ctrl_down: bool, shift_down: bool) -> bool {
+        // Without Shift, Left and Right first collapse a selection to its start or end
+        if !shift_down && !self.selection.is_empty() && (key == VK_LEFT || key == VK_RIGHT) {
+            let offset = if key == VK_LEFT { self.selection.start() } else { self.selection.end() };
+            self.command_manager.close_step();
+            self.set_caret(offset);
+            return true;
+        }
+        let caret = self.selection.active;
+        let (line, column) = self.document.offset_to_position(caret);
+        let line_text = self.document.getline(line).unwrap_or("");
+        let column = column.min(line_text.len());
+        let last_line = self.document.line_count() - 1;
 
-        match msg {
-            // First message received by any window:
-            WM_NCCREATE => {
-                eprintln!("WM_NCCREATE received");
-                // Create the EditorView instance when the window is created
-                let editor_view = Box::new(EditorView::new(hwnd));
-                // Store a raw pointer to the EditorView in the window's extra data (at offset 0)
-                SetWindowLongPtrW(hwnd, WINDOW_LONG_PTR_INDEX(0), Box::into_raw(editor_view) as isize); // Use WINDOW_LONG_PTR_INDEX
-                return LRESULT(1); 
-            }
-            // Last message received - clean up the EditorView instance
-            WM_NCDESTROY => {
-                // Retrieve the pointer from window extra storage
-                let ptr = GetWindowLongPtrW(hwnd, WINDOW_LONG_PTR_INDEX(0)) as *mut EditorView;
-                if !ptr.is_null() {
-                    // Convert the raw pointer back to a Box to allow Rust to drop it
-                    let _ = Box::from_raw(ptr);
-                    // Clear the pointer from window storage to prevent double-free
-                    SetWindowLongPtrW(hwnd, WINDOW_LONG_PTR_INDEX(0), 0); // Use WINDOW_LONG_PTR_INDEX
-                }
-                return LRESULT(0);
+        let mut goal_column = None;
+        let offset = match key {
+            VK_LEFT => match line_text[..column].chars().next_back() {
+                Some(ch) => self.document.position_to_offset(line, column - ch.len_utf8()),
+                // Move to the end of the previous line, skipping its line break
+                None if line > 0 => self.document.position_to_offset(line - 1, usize::MAX),
+                None => caret,
+            },
+            VK_RIGHT => match line_text[column..].chars().next() {
+                Some(ch) => self.document.position_to_offset(line, column + ch.len_utf8()),
+                None if line < last_line => self.document.position_to_offset(line + 1, 0),
+                None => caret,
+            },
+            VK_UP | VK_DOWN | VK_PRIOR | VK_NEXT => {
+                let distance = if key == VK_UP || key == VK_DOWN { 1 } else { self.page_lines() };
+                let target_line = if key == VK_UP || key == VK_PRIOR {
+                    line.saturating_sub(distance)
+                } else {
+                    (line + distance).min(last_line)
+                };
+                // Aim for the screen column the vertical movement started in, which
+                // differs from the byte column for multi-byte characters, control
+                // character mnemonics and aligned fields, even across shorter lines
+                let column = self.goal_column.unwrap_or_else(|| self.screen_position(caret).1);
+                goal_column = Some(column);
+                self.offset_at_column(target_line, column)
             }
-            WM_PAINT => {
-                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
-                    if let Err(_e) = editor_view.on_paint() {
-                        // eprintln!("on_paint error: {:?}", e); // Keep commented for debugging
-                    }
-                }
-                return LRESULT(0);
+            VK_HOME if ctrl_down => 0,
+            VK_HOME => self.document.position_to_offset(line, 0),
+            VK_END if ctrl_down => self.document.len(),
+            VK_END => self.document.position_to_offset(line, usize::MAX),
+            _ => return false,
+        };
+        self.command_manager.close_step();
+        self.move_caret(offset, shift_down);
+        self.goal_column = goal_column;
+        if shift_down {
+            self.on_selection_made();
+        }
+        true
+    }
+
+    /// Returns the number of lines Page Up and Page Down move by: the number of
+    /// lines that fit into the view, at least one.
+    fn page_lines(&self) -> usize {
+        let mut client = RECT::default();
+        let _ = unsafe { GetClientRect(self.hwnd, &mut client) };
+        ((client.bottom - client.top) / self.font_height.max(1)).max(1) as usize
+    }
+
+    /// Returns whether the first line is kept at the top of the view as the
+    /// header row of aligned columns.
+    fn header_frozen(&self) -> bool {
+        self.freeze_header && self.aligned_view.borrow().is_some()
+    }
+
+    /// Returns the client y coordinate of the top of `line`, which is negative
+    /// for lines above the view. A frozen header row is always at the top.
+    fn line_top(&self, line: usize) -> i32 {
+        if line == 0 && self.header_frozen() {
+            return 0;
+        }
+        self.row_top(line)
+    }
+
+    /// Returns the client y coordinate `line` is scrolled to, ignoring a frozen header row.
+    fn row_top(&self, line: usize) -> i32 {
+        let rows = line as i64 - self.first_visible_line as i64;
+        // Far away lines are clamped well outside the view rather than overflowing
+        (rows * self.font_height as i64).clamp(i32::MIN as i64 / 2, i32::MAX as i64 / 2) as i32
+    }
+
+    /// Returns the width in pixels of the widest line, plus a cell for the
+    /// caret after its end.
+    fn content_width(&self) -> i32 {
+        let columns = match self.aligned_view.borrow_mut().as_mut() {
+            Some(aligned_view) => {
+                aligned_view.update(&self.document);
+                aligned_view.width()
             }
-            WM_SETFONT => {
-                let hfont = HFONT(wparam.0 as _); // Cast usize directly to *mut c_void implicitly
-                let redraw = lparam != LPARAM(0);
+            None => self.longest_line_len,
+        };
+        (columns as i64 + 1).saturating_mul(self.font_width as i64).min(i32::MAX as i64) as i32
+    }
 
-                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
-                    if let Err(_e) = editor_view.on_set_font(hfont) {
-                        // eprintln!("WM_SETFONT error: {:?}", e); // Keep commented for debugging
-                    } else if redraw {
-                        InvalidateRect(Some(hwnd), None, true);
-                    }
+    /// Returns the largest first visible line and horizontal scroll position:
+    /// the view stops scrolling once the end of the document is in sight.
+    fn scroll_limits(&self) -> (usize, i32) {
+        let mut client = RECT::default();
+        let _ = unsafe { GetClientRect(self.hwnd, &mut client) };
+        let max_line = self.document.line_count().saturating_sub(self.page_lines());
+        let max_x = (self.content_width() - (client.right - client.left)).max(0);
+        (max_line, max_x)
+    }
+
+    /// Scrolls the view back to the top left and measures the document, for
+    /// when its content was replaced.
+    fn reset_scroll(&mut self) {
+        self.first_visible_line = 0;
+        self.scroll_x = 0;
+        self.longest_line_len = self.document.longest_line_len();
+        if let Some(aligned_view) = self.aligned_view.get_mut() {
+            aligned_view.reset();
+        }
+        self.update_scroll_bars();
+    }
+
+    /// Sets the scroll bar ranges for the document and view size, and moves the
+    /// view back into range if the document got shorter or the view taller.
+    fn update_scroll_bars(&mut self) {
+        let mut client = RECT::default();
+        let _ = unsafe { GetClientRect(self.hwnd, &mut client) };
+        let (max_line, max_x) = self.scroll_limits();
+        if self.first_visible_line > max_line || self.scroll_x > max_x {
+            self.first_visible_line = self.first_visible_line.min(max_line);
+            self.scroll_x = self.scroll_x.min(max_x);
+            self.frame_pacer.invalidate(self.hwnd, None);
+            self.update_caret_position();
+        }
+
+        let vertical = SCROLLINFO {
+            cbSize: std::mem::size_of::<SCROLLINFO>() as u32,
+            fMask: SIF_RANGE | SIF_PAGE | SIF_POS,
+            nMin: 0,
+            nMax: self.document.line_count().saturating_sub(1).min(i32::MAX as usize) as i32,
+            nPage: self.page_lines() as u32,
+            nPos: self.first_visible_line as i32,
+            nTrackPos: 0,
+        };
+        let horizontal = SCROLLINFO {
+            cbSize: std::mem::size_of::<SCROLLINFO>() as u32,
+            fMask: SIF_RANGE | SIF_PAGE | SIF_POS,
+            nMin: 0,
+            nMax: self.content_width() - 1,
+            nPage: (client.right - client.left).max(0) as u32,
+            nPos: self.scroll_x,
+            nTrackPos: 0,
+        };
+        unsafe {
+            SetScrollInfo(self.hwnd, SB_VERT, &vertical, true);
+            SetScrollInfo(self.hwnd, SB_HORZ, &horizontal, true);
+        }
+    }
+
+    /// Scrolls the view so `first_line` is at the top and the text is shifted
+    /// `scroll_x` pixels to the left, within the scroll ranges. What stays
+    /// visible is moved with ScrollWindowEx; only the uncovered part is repainted.
+    fn scroll_to(&mut self, first_line: usize, scroll_x: i32) {
+        let (max_line, max_x) = self.scroll_limits();
+        let first_line = first_line.min(max_line);
+        let scroll_x = scroll_x.clamp(0, max_x);
+        if first_line == self.first_visible_line && scroll_x == self.scroll_x {
+            return;
+        }
+        // Paint pending changes at their old position before the pixels move
+        self.frame_pacer.flush(self.hwnd);
+        let _ = unsafe { UpdateWindow(self.hwnd) };
+
+        let mut client = RECT::default();
+        let _ = unsafe { GetClientRect(self.hwnd, &mut client) };
+        let dy = (self.first_visible_line as i64 - first_line as i64) * self.font_height as i64;
+        let dx = self.scroll_x - scroll_x;
+        self.first_visible_line = first_line;
+        self.scroll_x = scroll_x;
+        if dy.abs() < (client.bottom - client.top) as i64 && dx.abs() < client.right - client.left {
+            if self.header_frozen() {
+                // Only the lines below the header row move up and down
+                let body = RECT { top: client.top + self.font_height, ..client };
+                unsafe { ScrollWindowEx(self.hwnd, dx, dy as i32, Some(&body), Some(&body), None, None, SW_INVALIDATE) };
+                if dx != 0 {
+                    let header = RECT { bottom: body.top, ..client };
+                    self.frame_pacer.invalidate(self.hwnd, Some(header));
                 }
-                return LRESULT(0);
+            } else {
+                unsafe { ScrollWindowEx(self.hwnd, dx, dy as i32, None, None, None, None, SW_INVALIDATE) };
             }
-            EVM_OPENFILE => {
-                let filename_pcwstr = PCWSTR(lparam.0 as *const u16); // lparam is PCWSTR
-                let mut success = false;
+        } else {
+            self.frame_pacer.invalidate(self.hwnd, None);
+        }
+        self.update_scroll_bars();
+        self.update_caret_position();
+    }
 
-                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
-                    match editor_view.open_file(filename_pcwstr) {
-                        Ok(_) => success = true,
+    /// Scrolls the view as far as needed to show the caret, with `scroll_margin`
+    /// lines of context above and below it. When the caret leaves the view
+    /// sideways, a quarter of the view width of context is shown next to it.
+    fn ensure_caret_visible(&mut self) {
+        let (line, column) = self.screen_position(self.selection.active);
+        let page = self.page_lines();
+        // A margin of more than half the view would make the view jump on every line
+        let margin = self.scroll_margin.min(page.saturating_sub(1) / 2);
+        // A frozen header row covers the line at the top, and is always in view itself
+        let header_rows = self.header_frozen() as usize;
+        let mut first_line = self.first_visible_line;
+        if line >= header_rows && line < first_line + margin + header_rows {
+            first_line = line.saturating_sub(margin + header_rows);
+        } else if line + margin >= first_line + page {
+            first_line = line + margin + 1 - page;
+        }
+
+        let mut client = RECT::default();
+        let _ = unsafe { GetClientRect(self.hwnd, &mut client) };
+        let view_width = client.right - client.left;
+        let caret_x = (column as i64 * self.font_width as i64).min(i32::MAX as i64 / 2) as i32;
+        let mut scroll_x = self.scroll_x;
+        if caret_x < scroll_x {
+            scroll_x = caret_x - view_width / 4;
+        } else if caret_x + self.font_width > scroll_x + view_width {
+            scroll_x = caret_x + self.font_width - view_width + view_width / 4;
+        }
+        self.scroll_to(first_line, scroll_x);
+    }
+
+    /// Handles WM_VSCROLL: scrolls by a line or a page, or to the thumb position.
+    fn on_vscroll(&mut self, request: SCROLLBAR_COMMAND) {
+        let first = self.first_visible_line;
+        let page = self.page_lines();
+        let first_line = match request {
+            SB_LINEUP => first.saturating_sub(1),
+            SB_LINEDOWN => first + 1,
+            SB_PAGEUP => first.saturating_sub(page),
+            SB_PAGEDOWN => first + page,
+            SB_TOP => 0,
+            SB_BOTTOM => usize::MAX,
+            SB_THUMBTRACK | SB_THUMBPOSITION => self.track_position(SB_VERT) as usize,
+            _ => return,
+        };
+        self.scroll_to(first_line, self.scroll_x);
+    }
+
+    /// Handles WM_HSCROLL: scrolls by a cell or a view width, or to the thumb position.
+    fn on_hscroll(&mut self, request: SCROLLBAR_COMMAND) {
+        let mut client = RECT::default();
+        let _ = unsafe { GetClientRect(self.hwnd, &mut client) };
+        let x = self.scroll_x;
+        let scroll_x = match request {
+            SB_LINELEFT => x - self.font_width,
+            SB_LINERIGHT => x + self.font_width,
+            SB_PAGELEFT => x - (client.right - client.left),
+            SB_PAGERIGHT => x + (client.right - client.left),
+            SB_LEFT => 0,
+            SB_RIGHT => i32::MAX,
+            SB_THUMBTRACK | SB_THUMBPOSITION => self.track_position(SB_HORZ),
+            _ => return,
+        };
+        self.scroll_to(self.first_visible_line, scroll_x);
+    }
+
+    /// Handles WM_MOUSEWHEEL and WM_MOUSEHWHEEL: scrolls by the number of lines,
+    /// or characters horizontally, that the mouse settings give per notch.
+    /// Positive `delta` is the wheel turned away from the user or tilted right.
+    fn on_mouse_wheel(&mut self, delta: i16, horizontal: bool) {
+        let setting = if horizontal { SPI_GETWHEELSCROLLCHARS } else { SPI_GETWHEELSCROLLLINES };
+        let mut per_notch: u32 = 3; // The system default for both
+        let _ = unsafe {
+            SystemParametersInfoW(setting, 0, Some(&mut per_notch as *mut u32 as *mut _), SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0))
+        };
+        if per_notch == WHEEL_PAGESCROLL {
+            per_notch = if horizontal {
+                let mut client = RECT::default();
+                let _ = unsafe { GetClientRect(self.hwnd, &mut client) };
+                ((client.right - client.left) / self.font_width.max(1)).max(1) as u32
+            } else {
+                self.page_lines() as u32
+            };
+        }
+        if per_notch == 0 {
+            return; // Wheel scrolling is turned off
+        }
+
+        let remainder = if horizontal { &mut self.wheel_remainder.1 } else { &mut self.wheel_remainder.0 };
+        *remainder += delta as i32;
+        let units = *remainder as i64 * per_notch as i64 / WHEEL_DELTA as i64;
+        *remainder -= (units * WHEEL_DELTA as i64 / per_notch as i64) as i32;
+        if units == 0 {
+            return;
+        }
+        if horizontal {
+            let scroll_x = self.scroll_x as i64 + units * self.font_width as i64;
+            self.scroll_to(self.first_visible_line, scroll_x.clamp(0, i32::MAX as i64) as i32);
+        } else {
+            let first_line = (self.first_visible_line as i64 - units).max(0);
+            self.scroll_to(first_line as usize, self.scroll_x);
+        }
+    }
+
+    /// Returns where the thumb of a scroll bar is being dragged to. Unlike the
+    /// position in the scroll message, this isn't limited to 16 bits.
+    fn track_position(&self, bar: SCROLLBAR_CONSTANTS) -> i32 {
+        let mut info = SCROLLINFO {
+            cbSize: std::mem::size_of::<SCROLLINFO>() as u32,
+            fMask: SIF_TRACKPOS,
+            ..Default::default()
+        };
+        let _ = unsafe { GetScrollInfo(self.hwnd, bar, &mut info) };
+        info.nTrackPos
+    }
+
+    /// Handles WM_SETFOCUS: shows a blinking caret at the caret position.
+    fn on_set_focus(&mut self) {
+        self.has_focus = true;
+        if self.shows_caret() {
+            self.create_caret();
+        }
+    }
+
+    /// Handles WM_KILLFOCUS: the caret belongs to the window with the focus.
+    fn on_kill_focus(&mut self) {
+        self.destroy_caret();
+        self.has_focus = false;
+    }
+
+    /// Creates and shows the system caret, as wide as the user's caret width
+    /// setting, or a block over the character in vi normal and visual mode.
+    fn create_caret(&self) {
+        let mut caret_width: u32 = 0;
+        let width_result = unsafe {
+            SystemParametersInfoW(
+                SPI_GETCARETWIDTH,
+                0,
+                Some(&mut caret_width as *mut u32 as *mut _),
+                SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+            )
+        };
+        if width_result.is_err() {
+            caret_width = 1;
+        }
+        if self.in_vi_command_mode() {
+            caret_width = self.font_width as u32;
+        }
+        if let Err(e) = unsafe { CreateCaret(self.hwnd, None, caret_width as i32, self.font_height) } {
+            eprintln!("CreateCaret failed: {}", e);
+            return;
+        }
+        self.update_caret_position();
+        let _ = unsafe { ShowCaret(Some(self.hwnd)) };
+    }
+
+    fn destroy_caret(&self) {
+        let _ = unsafe { DestroyCaret() };
+    }
+
+    /// Whether the view shows the system caret: while it has the focus, unless
+    /// the timers are suspended.
+    fn shows_caret(&self) -> bool {
+        self.has_focus && !self.timers_suspended
+    }
+
+    /// Suspends or resumes the timers that run without user input: idle tasks,
+    /// the render timer and the caret's blinking.
+    fn suspend_timers(&mut self, suspend: bool) {
+        if suspend == self.timers_suspended {
+            return;
+        }
+        if suspend {
+            self.destroy_caret();
+            self.idle_scheduler.borrow_mut().suspend(self.hwnd);
+            self.frame_pacer.suspend(self.hwnd);
+        } else {
+            self.idle_scheduler.borrow_mut().resume(self.hwnd);
+            self.frame_pacer.resume(self.hwnd);
+        }
+        self.timers_suspended = suspend;
+        if self.shows_caret() {
+            self.create_caret();
+        }
+    }
+
+    /// Moves the system caret to the active end of the selection, if the view has the focus.
+    fn update_caret_position(&self) {
+        if !self.has_focus {
+            return;
+        }
+        let (x, y) = self.caret_point();
+        let _ = unsafe { SetCaretPos(x, y) };
+    }
+
+    /// Returns the client coordinates at which the caret is drawn.
+    fn caret_point(&self) -> (i32, i32) {
+        let (line, column) = self.screen_position(self.selection.active);
+        (column as i32 * self.font_width - self.scroll_x, self.line_top(line))
+    }
+
+    /// Returns the line of the byte offset `offset` and the screen column it is
+    /// drawn at, in the aligned view if that is on.
+    fn screen_position(&self, offset: usize) -> (usize, usize) {
+        let (line, offset) = self.document.offset_to_position(offset);
+        let line_text = self.document.getline(line).unwrap_or("");
+        // The offset may be after the line's text, before its line break
+        let offset = offset.min(line_text.len());
+
+        let column = match self.aligned_view.borrow_mut().as_mut() {
+            Some(aligned_view) => {
+                aligned_view.update(&self.document);
+                let fields = aligned_view.layout_line(line_text, usize::MAX);
+                aligned_column(&fields, line_text, offset).unwrap_or(0)
+            }
+            None => line_layout::display_column(line_text, offset),
+        };
+        (line, column)
+    }
+
+    /// Converts a screen column on `line` into a byte offset in the document, the
+    /// inverse of `screen_position`. Columns past the end of the line map to its
+    /// end, before the line break.
+    fn offset_at_column(&self, line: usize, column: usize) -> usize {
+        let line_text = self.document.getline(line).unwrap_or("");
+        let offset = match self.aligned_view.borrow_mut().as_mut() {
+            Some(aligned_view) => {
+                aligned_view.update(&self.document);
+                aligned_view
+                    .layout_line(line_text, usize::MAX)
+                    .into_iter()
+                    .take_while(|field| field.start_column <= column)
+                    .last()
+                    .map_or(0, |field| {
+                        let field_text = &line_text[field.range.clone()];
+                        field.range.start + line_layout::offset_at_column(field_text, column - field.start_column)
+                    })
+            }
+            None => line_layout::offset_at_column(line_text, column),
+        };
+        self.document.position_to_offset(line, offset)
+    }
+
+    /// Handles WM_LBUTTONDOWN: takes the focus and moves the caret to the
+    /// character boundary nearest to the clicked point, extending the selection
+    /// with Shift. The mouse is captured so dragging selects text.
+    fn on_left_button_down(&mut self, x: i32, y: i32, shift_down: bool) {
+        let _ = unsafe { SetFocus(Some(self.hwnd)) };
+        unsafe { SetCapture(self.hwnd) };
+        self.command_manager.close_step();
+        let offset = self.offset_at_point(x, y);
+        self.move_caret(offset, shift_down);
+    }
+
+    /// Handles WM_MOUSEMOVE: while the left button is held down after clicking
+    /// into the view, the selection follows the mouse.
+    fn on_mouse_move(&mut self, x: i32, y: i32) {
+        if unsafe { GetCapture() } != self.hwnd {
+            return;
+        }
+        let offset = self.offset_at_point(x, y);
+        self.move_caret(offset, true);
+    }
+
+    /// Handles WM_LBUTTONUP: ends selecting with the mouse.
+    fn on_left_button_up(&mut self) {
+        if unsafe { GetCapture() } != self.hwnd {
+            return;
+        }
+        let _ = unsafe { ReleaseCapture() };
+        self.on_selection_made();
+    }
+
+    /// Handles WM_MBUTTONDOWN while selections are copied automatically: pastes
+    /// the clipboard at the clicked point. Returns false if that mode is off.
+    fn on_middle_button_down(&mut self, x: i32, y: i32) -> bool {
+        if !self.auto_copy {
+            return false;
+        }
+        let _ = unsafe { SetFocus(Some(self.hwnd)) };
+        self.command_manager.close_step();
+        let offset = self.offset_at_point(x, y);
+        self.set_caret(offset);
+        self.paste();
+        usage_stats::record_command("Paste");
+        true
+    }
+
+    /// Called when the user finished making a selection with the mouse or
+    /// Shift+navigation keys: copies it to the clipboard if auto-copy is on.
+    fn on_selection_made(&mut self) {
+        if self.auto_copy && !self.selection.is_empty() {
+            self.copy();
+        }
+    }
+
+    /// Converts client coordinates into a byte offset in the document, the
+    /// inverse of `caret_point`. Points below the last line map to the last
+    /// line and points past the end of a line to its end, before the line break.
+    fn offset_at_point(&self, x: i32, y: i32) -> usize {
+        let row = (y.max(0) / self.font_height.max(1)) as usize;
+        let line = if row == 0 && self.header_frozen() { 0 } else { self.first_visible_line + row };
+        let line = line.min(self.document.line_count().saturating_sub(1));
+        // Round to the nearest cell boundary, as the caret sits between characters
+        let font_width = self.font_width.max(1);
+        let column = ((x + self.scroll_x).max(0) + font_width / 2) as usize / font_width as usize;
+        self.offset_at_column(line, column)
+    }
+
+    /// Handles WM_CHAR: inserts the typed character at the caret. Enter inserts
+    /// a line break in the document's convention; other control characters are ignored.
+    fn on_char(&mut self, code_unit: u16) {
+        let mut buffer = [0u8; 4];
+        let text = match (self.pending_surrogate.take(), code_unit) {
+            (_, 0xD800..=0xDBFF) => {
+                self.pending_surrogate = Some(code_unit);
+                return;
+            }
+            (Some(high), 0xDC00..=0xDFFF) => match char::decode_utf16([high, code_unit]).next() {
+                Some(Ok(ch)) => ch.encode_utf8(&mut buffer),
+                _ => return,
+            },
+            (_, 0x0D) => self.line_break(),
+            (_, 0x09) => "\t",
+            (_, 0x00..=0x1F | 0x7F) => return,
+            (_, code_unit) => match char::from_u32(code_unit as u32) {
+                Some(ch) => ch.encode_utf8(&mut buffer),
+                None => return, // An unpaired low surrogate
+            },
+        };
+        self.insert_text(text);
+    }
+
+    /// Returns the line break used by the document: the one ending its first
+    /// line, or CR LF for documents with a single line.
+    fn line_ending(&self) -> LineEnding {
+        let content = self.document.get_content();
+        match content.find('\n') {
+            Some(i) if i == 0 || content.as_bytes()[i - 1] != b'\r' => LineEnding::Lf,
+            _ => LineEnding::CrLf,
+        }
+    }
+
+    /// Returns the characters of the document's line break.
+    fn line_break(&self) -> &'static str {
+        match self.line_ending() {
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Lf => "\n",
+        }
+    }
+
+    /// Copies the selected text to the clipboard (WM_COPY). Returns false if
+    /// nothing is selected or the clipboard is unavailable.
+    fn copy(&self) -> bool {
+        if self.selection.is_empty() {
+            return false;
+        }
+        if let Err(e) = clipboard::set_text(self.hwnd, self.selected_text()) {
+            eprintln!("Failed to copy to the clipboard: {}", e);
+            return false;
+        }
+        true
+    }
+
+    /// Moves the selected text to the clipboard (WM_CUT).
+    fn cut(&mut self) {
+        if self.copy() {
+            self.replace_selection("");
+        }
+    }
+
+    /// Replaces the selection with the text on the clipboard (WM_PASTE). Its
+    /// line breaks are converted to the document's convention.
+    fn paste(&mut self) {
+        match clipboard::get_text(self.hwnd) {
+            Ok(Some(text)) => {
+                let text = file_io::convert_line_endings(&text, self.line_ending());
+                self.replace_selection(&text);
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to paste from the clipboard: {}", e),
+        }
+    }
+
+    /// Handles WM_CONTEXTMENU, sent for a right-click and for Shift+F10 or the
+    /// Menu key: shows the clipboard commands at the mouse pointer, or below the
+    /// caret when opened from the keyboard (`x` and `y` are -1 then).
+    fn on_context_menu(&mut self, x: i32, y: i32) {
+        let mut point = POINT { x, y };
+        if x == -1 && y == -1 {
+            let (caret_x, caret_y) = self.caret_point();
+            point = POINT { x: caret_x, y: caret_y + self.font_height };
+            let _ = unsafe { ClientToScreen(self.hwnd, &mut point) };
+        }
+
+        let selection_flag = if self.selection.is_empty() { MF_GRAYED } else { MF_ENABLED };
+        let paste_flag = if clipboard::has_text() { MF_ENABLED } else { MF_GRAYED };
+        let command = unsafe {
+            let Ok(hmenu) = CreatePopupMenu() else {
+                return;
+            };
+            let items = [
+                (CONTEXT_MENU_CUT, selection_flag, w!("Cu&t\tCtrl+X")),
+                (CONTEXT_MENU_COPY, selection_flag, w!("&Copy\tCtrl+C")),
+                (CONTEXT_MENU_PASTE, paste_flag, w!("&Paste\tCtrl+V")),
+            ];
+            for (id, flag, text) in items {
+                let _ = AppendMenuW(hmenu, MF_STRING | flag, id as usize, text);
+            }
+            // TPM_RETURNCMD returns the chosen item instead of sending WM_COMMAND
+            let command = TrackPopupMenu(hmenu, TPM_RETURNCMD | TPM_RIGHTBUTTON, point.x, point.y, None, self.hwnd, None);
+            let _ = DestroyMenu(hmenu);
+            command.0 as u32
+        };
+        let name = match command {
+            CONTEXT_MENU_CUT => {
+                self.cut();
+                "Cut"
+            }
+            CONTEXT_MENU_COPY => {
+                self.copy();
+                "Copy"
+            }
+            CONTEXT_MENU_PASTE => {
+                self.paste();
+                "Paste"
+            }
+            _ => return, // Dismissed
+        };
+        usage_stats::record_command(name);
+    }
+
+    /// Inserts typed `text` at the caret, replacing the selection, and moves
+    /// the caret after it. Typing is undone a word at a time: whitespace and
+    /// line breaks end the current undo step.
+    fn insert_text(&mut self, text: &str) {
+        if !self.selection.is_empty() {
+            self.replace_selection(text);
+            self.command_manager.close_step();
+            return;
+        }
+        let caret = self.selection.active;
+        let command = Box::new(InsertCommand::new(caret, text.to_string()));
+        match self.command_manager.execute_coalesced(command, Coalesce::Typing, &mut self.document) {
+            Ok(affected) => self.update_after_edit(affected),
+            Err(e) => {
+                eprintln!("Failed to insert text: {}", e);
+                return;
+            }
+        }
+        if text.chars().all(char::is_whitespace) {
+            self.command_manager.close_step();
+        }
+        self.set_caret(caret + text.len());
+        usage_stats::record_edit();
+    }
+
+    /// Handles Backspace and Delete: removes the selection, or else the character
+    /// before or after the caret. A line break counts as one character, so a CR LF
+    /// is removed as a whole and the lines on either side are joined.
+    fn delete_at_caret(&mut self, forward: bool) {
+        if !self.selection.is_empty() {
+            self.replace_selection("");
+            self.command_manager.close_step();
+            return;
+        }
+        let caret = self.selection.active;
+        let content = self.document.get_content();
+        let len = if forward {
+            let rest = &content[caret..];
+            if rest.starts_with("\r\n") { 2 } else { rest.chars().next().map_or(0, char::len_utf8) }
+        } else {
+            let before = &content[..caret];
+            if before.ends_with("\r\n") { 2 } else { before.chars().next_back().map_or(0, char::len_utf8) }
+        };
+        if len == 0 {
+            return; // At the start or end of the document
+        }
+        let (command, kind) = if forward {
+            (DeleteCommand::new(caret, len), Coalesce::Delete)
+        } else {
+            (DeleteCommand::backward(caret - len, len), Coalesce::Backspace)
+        };
+        let pos = command.pos;
+        match self.command_manager.execute_coalesced(Box::new(command), kind, &mut self.document) {
+            Ok(affected) => self.update_after_edit(affected),
+            Err(e) => {
+                eprintln!("Failed to delete text: {}", e);
+                return;
+            }
+        }
+        self.set_caret(pos);
+        usage_stats::record_edit();
+    }
+
+    /// Handles Ctrl+Backspace and Ctrl+Delete: removes the selection, or else
+    /// the text from the caret to the start of the word before it or the end
+    /// of the word after it, as its own undo step.
+    fn delete_word_at_caret(&mut self, forward: bool) {
+        if !self.selection.is_empty() {
+            self.replace_selection("");
+            self.command_manager.close_step();
+            return;
+        }
+        let caret = self.selection.active;
+        let content = self.document.get_content();
+        let range = if forward {
+            caret..emacs_keys::forward_word(content, caret)
+        } else {
+            emacs_keys::backward_word(content, caret)..caret
+        };
+        if range.is_empty() {
+            return;
+        }
+        let command = if forward {
+            DeleteCommand::new(range.start, range.len())
+        } else {
+            DeleteCommand::backward(range.start, range.len())
+        };
+        self.command_manager.close_step();
+        if let Err(e) = self.execute(Box::new(command)) {
+            eprintln!("Failed to delete text: {}", e);
+            return;
+        }
+        self.set_caret(range.start);
+        usage_stats::record_edit();
+    }
+
+    /// Executes an editing command on the document as a new undo step and
+    /// repaints the lines it changed.
+    fn execute(&mut self, command: Box<dyn Command>) -> Result<(), Box<dyn Error>> {
+        let affected = self.command_manager.execute(command, &mut self.document)?;
+        self.update_after_edit(affected);
+        Ok(())
+    }
+
+    /// Updates the line count and repaints after the document was edited.
+    fn update_after_edit(&mut self, affected: AffectedLines) {
+        self.line_count = self.document.line_count();
+        for line in affected.first..=affected.last {
+            let len = self.document.getline(line).map_or(0, str::len);
+            self.longest_line_len = self.longest_line_len.max(len);
+        }
+        if let Some(aligned_view) = self.aligned_view.get_mut() {
+            aligned_view.widen(&self.document, affected.first..=affected.last);
+            // Only measuring every line again shows whether a column got
+            // narrower; that waits until the user stops typing. Setting the
+            // timer again restarts its delay.
+            self.idle_scheduler.get_mut().cancel(self.hwnd, IdleTask::MeasureColumns { next: 0 });
+            unsafe { SetTimer(Some(self.hwnd), COLUMN_RESCAN_TIMER_ID, COLUMN_RESCAN_DELAY_MS, None) };
+        }
+        self.update_scroll_bars();
+        self.invalidate_affected(affected);
+        self.report_modified();
+    }
+
+    /// Takes back the last undo step (Ctrl+Z): a word of typing or one other edit.
+    /// Returns false when there was nothing to undo or undoing failed.
+    fn undo(&mut self) -> bool {
+        match self.command_manager.undo(&mut self.document) {
+            Ok(Some((affected, caret))) => {
+                self.update_after_edit(affected);
+                self.set_caret(caret);
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                eprintln!("Failed to undo: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Applies the last undone step again (Ctrl+Y). Returns false when there
+    /// was nothing to redo or redoing failed.
+    fn redo(&mut self) -> bool {
+        match self.command_manager.redo(&mut self.document) {
+            Ok(Some((affected, caret))) => {
+                self.update_after_edit(affected);
+                self.set_caret(caret);
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                eprintln!("Failed to redo: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Makes the last edit again at the caret (Ctrl+Shift+.): the last word typed,
+    /// the last deletion or the last other command, as a new undo step.
+    fn repeat_last_edit(&mut self) {
+        match self.command_manager.repeat_last_step(self.selection.active, &mut self.document) {
+            Ok(Some((affected, caret))) => {
+                self.update_after_edit(affected);
+                self.set_caret(caret);
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to repeat the last edit: {}", e),
+        }
+    }
+
+    /// Switches to another key binding scheme. vi key bindings start in normal mode.
+    fn set_key_bindings(&mut self, key_bindings: KeyBindings) {
+        if key_bindings == self.key_bindings() {
+            return;
+        }
+        self.vi = (key_bindings == KeyBindings::Vi).then(ViState::new);
+        self.emacs = (key_bindings == KeyBindings::Emacs).then(EmacsState::new);
+        self.command_manager.close_step();
+        self.update_caret_shape();
+        self.clamp_vi_caret();
+    }
+
+    fn key_bindings(&self) -> KeyBindings {
+        if self.vi.is_some() {
+            KeyBindings::Vi
+        } else if self.emacs.is_some() {
+            KeyBindings::Emacs
+        } else {
+            KeyBindings::Standard
+        }
+    }
+
+    /// Returns the vi mode, or None while the vi key bindings are off.
+    fn vi_mode(&self) -> Option<Mode> {
+        self.vi.as_ref().map(ViState::mode)
+    }
+
+    /// Whether typed keys are vi commands rather than text.
+    fn in_vi_command_mode(&self) -> bool {
+        self.vi_mode().is_some_and(|mode| mode != Mode::Insert)
+    }
+
+    /// Recreates the caret after the vi mode changed, which changes its shape.
+    fn update_caret_shape(&self) {
+        if self.shows_caret() {
+            self.destroy_caret();
+            self.create_caret();
+        }
+    }
+
+    /// Handles WM_CHAR while the vi key bindings are on: Esc leaves insert and
+    /// visual mode, and outside insert mode every key is part of a command.
+    /// Returns false for characters to be typed as text.
+    fn on_vi_char(&mut self, code_unit: u16) -> bool {
+        let Some(vi) = self.vi.as_mut() else {
+            return false;
+        };
+        let mode = vi.mode();
+        if mode == Mode::Insert && code_unit != 0x1B {
+            return false;
+        }
+        // Surrogates, for characters outside the BMP, are no commands
+        let action = char::from_u32(code_unit as u32).and_then(|ch| vi.handle_char(ch));
+        if let Some(action) = action {
+            self.execute_vi_action(action, mode);
+        }
+        if self.vi_mode() != Some(mode) {
+            self.update_caret_shape();
+        }
+        true
+    }
+
+    /// Carries out a vi command typed in `mode`. Edits go through the command
+    /// manager like any other, so they are undone the same way.
+    fn execute_vi_action(&mut self, action: Action, mode: Mode) {
+        let caret = self.selection.active;
+        let visual = mode == Mode::Visual;
+        match action {
+            Action::Move { motion: motion @ (Motion::Up | Motion::Down), count } => {
+                // As the arrow keys, which keep the screen column across shorter lines
+                let key = if motion == Motion::Up { VK_UP } else { VK_DOWN };
+                for _ in 0..count.min(self.document.line_count()) {
+                    self.on_navigation_key(key, false, visual);
+                }
+            }
+            Action::Move { motion, count } => {
+                let target = vi_mode::motion_target(&self.document, caret, motion, count);
+                self.command_manager.close_step();
+                self.move_caret(target, visual);
+                if visual {
+                    self.on_selection_made();
+                }
+            }
+            Action::Operate { operator, motion, count } => {
+                match vi_mode::operator_target(&self.document, caret, operator, motion, count) {
+                    Target::Chars(range) => self.vi_operate(operator, range),
+                    Target::Lines(first, last) => self.vi_operate_lines(operator, first, last),
+                }
+            }
+            Action::OperateLines { operator, count } => {
+                let first = self.document.line_from_offset(caret);
+                let last = first.saturating_add(count.saturating_sub(1)).min(self.document.line_count() - 1);
+                self.vi_operate_lines(operator, first, last);
+            }
+            Action::OperateSelection(operator) => {
+                // The selection includes the character under the caret at its end
+                let range = self.selection.range();
+                let rest = &self.document.get_content()[range.end..];
+                let last_char = if rest.starts_with("\r\n") { 2 } else { rest.chars().next().map_or(0, char::len_utf8) };
+                self.vi_operate(operator, range.start..range.end + last_char);
+            }
+            Action::DeleteChars(count) => {
+                let end = vi_mode::motion_target(&self.document, caret, Motion::Right, count);
+                self.vi_operate(Operator::Delete, caret..end);
+            }
+            Action::Put { before, count } => self.vi_put(before, count),
+            Action::Insert(at) => self.vi_insert(at),
+            Action::EndInsert => {
+                // As in vi, the caret moves back onto the last character typed
+                self.command_manager.close_step();
+                self.set_caret(vi_mode::motion_target(&self.document, caret, Motion::Left, 1));
+            }
+            Action::StartVisual => self.command_manager.close_step(),
+            Action::EndVisual => self.set_caret(caret),
+            Action::Undo(count) => {
+                // Stops at the oldest step, however large the count
+                for _ in 0..count {
+                    if !self.undo() {
+                        break;
+                    }
+                }
+            }
+            Action::Redo(count) => {
+                for _ in 0..count {
+                    if !self.redo() {
+                        break;
+                    }
+                }
+            }
+        }
+        self.clamp_vi_caret();
+    }
+
+    /// Applies a vi operator to `range`: its text goes into the register named
+    /// for the command, and `d` and `c` delete it.
+    fn vi_operate(&mut self, operator: Operator, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let text = self.document.get_content()[range.clone()].to_string();
+        self.vi_store(text, false);
+        if operator != Operator::Yank {
+            self.vi_delete(range.clone());
+        }
+        self.set_caret(range.start);
+    }
+
+    /// Applies a vi operator to the lines `first` to `last`. The register gets
+    /// them with a line break after each; `c` keeps an empty line to type on.
+    fn vi_operate_lines(&mut self, operator: Operator, first: usize, last: usize) {
+        let start = self.document.line_start(first).unwrap_or(self.document.len());
+        let range = vi_mode::line_range(&self.document, first, last);
+        let mut text = self.document.get_content()[start..range.end].to_string();
+        if !text.ends_with('\n') {
+            text.push_str(self.line_break());
+        }
+        self.vi_store(text, true);
+        match operator {
+            Operator::Yank => {
+                if self.document.line_from_offset(self.selection.active) != first {
+                    self.set_caret(vi_mode::first_non_blank(&self.document, first));
+                }
+            }
+            Operator::Delete => {
+                self.vi_delete(range);
+                let line = first.min(self.document.line_count() - 1);
+                self.set_caret(vi_mode::first_non_blank(&self.document, line));
+            }
+            Operator::Change => {
+                let end = self.document.position_to_offset(last, usize::MAX);
+                self.vi_delete(start..end);
+                self.set_caret(start);
+            }
+        }
+    }
+
+    /// Deletes `range` for a vi command, as a new undo step.
+    fn vi_delete(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        match self.execute(Box::new(DeleteCommand::new(range.start, range.len()))) {
+            Ok(()) => usage_stats::record_edit(),
+            Err(e) => eprintln!("Failed to delete text: {}", e),
+        }
+    }
+
+    /// Stores deleted or yanked text in the register named for the last vi
+    /// command. The `+` and `*` registers are the clipboard.
+    fn vi_store(&mut self, text: String, linewise: bool) {
+        let Some(vi) = self.vi.as_mut() else {
+            return;
+        };
+        match vi.register_name() {
+            '+' | '*' => {
+                if let Err(e) = clipboard::set_text(self.hwnd, &text) {
+                    eprintln!("Failed to copy to the clipboard: {}", e);
+                }
+            }
+            _ => vi.store(text, linewise),
+        }
+    }
+
+    /// Returns the register named for the last vi command. Text from the
+    /// clipboard gets the document's line breaks, and is whole lines if it ends
+    /// with a line break.
+    fn vi_fetch(&self) -> Option<Register> {
+        let vi = self.vi.as_ref()?;
+        match vi.register_name() {
+            '+' | '*' => match clipboard::get_text(self.hwnd) {
+                Ok(text) => text.map(|text| Register {
+                    linewise: text.ends_with('\n'),
+                    text: file_io::convert_line_endings(&text, self.line_ending()),
+                }),
+                Err(e) => {
+                    eprintln!("Failed to paste from the clipboard: {}", e);
+                    None
+                }
+            },
+            _ => vi.fetch().cloned(),
+        }
+    }
+
+    /// Puts the text of a register `count` times after the caret, or before it.
+    /// Whole lines go below or above the caret's line, and the caret moves to
+    /// the first of them; otherwise it ends on the last character put.
+    fn vi_put(&mut self, before: bool, count: usize) {
+        let Some(register) = self.vi_fetch() else {
+            return;
+        };
+        let count = count.min(MAX_PUT_LEN / register.text.len().max(1)).max(1);
+        let text = register.text.repeat(count);
+        if text.is_empty() {
+            return;
+        }
+        let caret = self.selection.active;
+        let line = self.document.line_from_offset(caret);
+        let (pos, text) = if register.linewise {
+            let next_line = if before { line } else { line + 1 };
+            match self.document.line_start(next_line) {
+                Some(pos) => (pos, text),
+                // Below the last line, which has no line break to put the lines after
+                None => {
+                    let lines = text.strip_suffix('\n').map_or(text.as_str(), |text| text.strip_suffix('\r').unwrap_or(text));
+                    (self.document.len(), format!("{}{}", self.line_break(), lines))
+                }
+            }
+        } else {
+            let rest = &self.document.get_content()[caret..];
+            let after = rest.chars().next().filter(|&ch| ch != '\r' && ch != '\n').map_or(0, char::len_utf8);
+            (if before { caret } else { caret + after }, text)
+        };
+        if let Err(e) = self.execute(Box::new(InsertCommand::new(pos, text.clone()))) {
+            eprintln!("Failed to insert text: {}", e);
+            return;
+        }
+        usage_stats::record_edit();
+        if register.linewise {
+            let first_line = if before { line } else { line + 1 };
+            self.set_caret(vi_mode::first_non_blank(&self.document, first_line));
+        } else {
+            let last_char = text.chars().next_back().map_or(0, char::len_utf8);
+            self.set_caret(pos + text.len() - last_char);
+        }
+    }
+
+    /// Moves the caret to where insert mode starts typing, opening a new line for `o` and `O`.
+    fn vi_insert(&mut self, at: InsertAt) {
+        self.command_manager.close_step();
+        let caret = self.selection.active;
+        let line = self.document.line_from_offset(caret);
+        let offset = match at {
+            InsertAt::BeforeCaret => caret,
+            InsertAt::AfterCaret => vi_mode::motion_target(&self.document, caret, Motion::Right, 1),
+            InsertAt::LineStart => vi_mode::first_non_blank(&self.document, line),
+            InsertAt::LineEnd => self.document.position_to_offset(line, usize::MAX),
+            InsertAt::LineBelow | InsertAt::LineAbove => {
+                let below = at == InsertAt::LineBelow;
+                let pos = if below {
+                    self.document.position_to_offset(line, usize::MAX)
+                } else {
+                    self.document.line_start(line).unwrap_or(0)
+                };
+                let line_break = self.line_break();
+                if let Err(e) = self.execute(Box::new(InsertCommand::new(pos, line_break.to_string()))) {
+                    eprintln!("Failed to insert a line: {}", e);
+                    return;
+                }
+                usage_stats::record_edit();
+                if below { pos + line_break.len() } else { pos }
+            }
+        };
+        self.set_caret(offset);
+    }
+
+    /// Keeps the caret on a character in vi normal mode, where the block caret
+    /// can't be after the end of a line with text.
+    fn clamp_vi_caret(&mut self) {
+        if self.vi_mode() != Some(Mode::Normal) {
+            return;
+        }
+        let (line, column) = self.document.offset_to_position(self.selection.active);
+        let line_text = self.document.getline(line).unwrap_or("");
+        if let Some(last_char) = line_text.chars().next_back().filter(|_| column >= line_text.len()) {
+            // Keep the column vertical moves aim for
+            let goal_column = self.goal_column;
+            self.set_caret(self.document.position_to_offset(line, line_text.len() - last_char.len_utf8()));
+            self.goal_column = goal_column;
+        }
+    }
+
+    /// Handles a key of the standard key bindings. Returns false for keys they
+    /// don't bind, which keep their usual meaning.
+    fn on_standard_key(&mut self, chord: Chord) -> bool {
+        match self.standard_keys.press(chord) {
+            Lookup::Found(command) => {
+                self.execute_standard_command(command);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Carries out a command of the standard key bindings.
+    fn execute_standard_command(&mut self, command: StandardCommand) {
+        let name = match command {
+            StandardCommand::Move(movement) => {
+                self.move_by(movement, false);
+                None
+            }
+            StandardCommand::Select(movement) => {
+                self.move_by(movement, true);
+                None
+            }
+            StandardCommand::Cut => {
+                self.cut();
+                Some("Cut")
+            }
+            StandardCommand::CutOrDelete if !self.selection.is_empty() => {
+                self.cut();
+                Some("Cut")
+            }
+            StandardCommand::DeleteBack | StandardCommand::DeleteForward | StandardCommand::CutOrDelete => {
+                let forward = command != StandardCommand::DeleteBack;
+                match self.vi_mode() {
+                    // vi commands: Backspace moves left, Delete removes the character under the caret
+                    Some(mode) if mode != Mode::Insert => {
+                        let action = if forward {
+                            Action::DeleteChars(1)
+                        } else {
+                            Action::Move { motion: Motion::Left, count: 1 }
+                        };
+                        self.execute_vi_action(action, mode);
+                    }
+                    _ => self.delete_at_caret(forward),
+                }
+                None
+            }
+            StandardCommand::DeleteWordBack | StandardCommand::DeleteWordForward => {
+                self.delete_word_at_caret(command == StandardCommand::DeleteWordForward);
+                None
+            }
+            StandardCommand::Copy => {
+                self.copy();
+                Some("Copy")
+            }
+            StandardCommand::Paste => {
+                self.paste();
+                Some("Paste")
+            }
+            StandardCommand::Undo => {
+                self.undo();
+                Some("Undo")
+            }
+            StandardCommand::Redo => {
+                self.redo();
+                Some("Redo")
+            }
+            StandardCommand::RepeatLastEdit => {
+                self.repeat_last_edit();
+                Some("Repeat Last Edit")
+            }
+            StandardCommand::ZoomIn => {
+                self.zoom(1);
+                Some("Zoom In")
+            }
+            StandardCommand::ZoomOut => {
+                self.zoom(-1);
+                Some("Zoom Out")
+            }
+            StandardCommand::ResetZoom => {
+                self.zoom(0);
+                Some("Restore Default Zoom")
+            }
+            StandardCommand::ToggleRenderingStats => {
+                self.toggle_debug_overlay();
+                None
+            }
+        };
+        if let Some(name) = name {
+            usage_stats::record_command(name);
+        }
+    }
+
+    /// Moves the caret as a navigation key does, extending the selection if
+    /// `extend` is set.
+    fn move_by(&mut self, movement: Movement, extend: bool) {
+        if let Some((key, ctrl_down)) = movement.navigation_key() {
+            self.on_navigation_key(key, ctrl_down, extend);
+            return;
+        }
+        let caret = self.selection.active;
+        let content = self.document.get_content();
+        let target = if movement == Movement::WordRight {
+            emacs_keys::forward_word(content, caret)
+        } else {
+            emacs_keys::backward_word(content, caret)
+        };
+        self.command_manager.close_step();
+        self.move_caret(target, extend);
+        if extend {
+            self.on_selection_made();
+        }
+    }
+
+    /// Handles a key pressed while the Emacs key bindings are on. Keys with
+    /// Ctrl or Alt, and any key after a prefix such as C-x, are looked up in
+    /// the keymap. Returns false for keys that keep their usual meaning.
+    fn on_emacs_key(&mut self, chord: Chord) -> bool {
+        let Some(emacs) = self.emacs.as_mut() else {
+            return false;
+        };
+        // Modifier keys on their own only start a chord, and AltGr, which
+        // reports Ctrl+Alt, types characters
+        let modifier = matches!(chord.key, VK_CONTROL | VK_SHIFT | VK_MENU);
+        if modifier || (chord.ctrl && chord.alt) {
+            return false;
+        }
+        // Other keys, e.g. typing, end a run of kills and deactivate the mark
+        if !chord.ctrl && !chord.alt && !emacs.keymap.is_pending() {
+            emacs.last_was_kill = false;
+            emacs.mark_active = false;
+            return false;
+        }
+        match emacs.keymap.press(chord) {
+            Lookup::Found(command) => {
+                self.execute_emacs_command(command);
+                true
+            }
+            // An undefined sequence, such as C-x q, is dropped as a whole
+            Lookup::Pending | Lookup::Undefined => true,
+            Lookup::Unbound => {
+                emacs.last_was_kill = false;
+                emacs.mark_active = false;
+                false
+            }
+        }
+    }
+
+    /// Carries out an Emacs command. While the mark is active, moving the
+    /// caret extends the selection from it.
+    fn execute_emacs_command(&mut self, command: EmacsCommand) {
+        let Some(emacs) = self.emacs.as_mut() else {
+            return;
+        };
+        let mark_active = emacs.mark_active;
+        let is_kill = matches!(command, EmacsCommand::KillWord | EmacsCommand::KillLine | EmacsCommand::KillRegion);
+        if !is_kill {
+            emacs.last_was_kill = false;
+        }
+        let caret = self.selection.active;
+        let key = match command {
+            EmacsCommand::LineStart => Some((VK_HOME, false)),
+            EmacsCommand::LineEnd => Some((VK_END, false)),
+            EmacsCommand::ForwardChar => Some((VK_RIGHT, false)),
+            EmacsCommand::BackwardChar => Some((VK_LEFT, false)),
+            EmacsCommand::NextLine => Some((VK_DOWN, false)),
+            EmacsCommand::PreviousLine => Some((VK_UP, false)),
+            EmacsCommand::BufferStart => Some((VK_HOME, true)),
+            EmacsCommand::BufferEnd => Some((VK_END, true)),
+            EmacsCommand::PageDown => Some((VK_NEXT, false)),
+            EmacsCommand::PageUp => Some((VK_PRIOR, false)),
+            _ => None,
+        };
+        // Movements the navigation keys make, e.g. keeping the column across lines
+        if let Some((key, ctrl_down)) = key {
+            self.on_navigation_key(key, ctrl_down, mark_active);
+            return;
+        }
+        match command {
+            EmacsCommand::ForwardWord | EmacsCommand::BackwardWord => {
+                let content = self.document.get_content();
+                let target = if command == EmacsCommand::ForwardWord {
+                    emacs_keys::forward_word(content, caret)
+                } else {
+                    emacs_keys::backward_word(content, caret)
+                };
+                self.command_manager.close_step();
+                self.move_caret(target, mark_active);
+                if mark_active {
+                    self.on_selection_made();
+                }
+            }
+            EmacsCommand::DeleteChar => {
+                self.set_caret(caret);
+                self.delete_at_caret(true);
+            }
+            EmacsCommand::KillWord => {
+                let end = emacs_keys::forward_word(self.document.get_content(), caret);
+                self.emacs_kill(caret..end);
+            }
+            EmacsCommand::KillLine => {
+                let line = self.document.line_from_offset(caret);
+                let line_end = self.document.position_to_offset(line, usize::MAX);
+                let end = if caret < line_end {
+                    line_end
+                } else {
+                    // At the end of the line, the line break is killed, joining the next line
+                    self.document.line_start(line + 1).unwrap_or(line_end)
+                };
+                self.emacs_kill(caret..end);
+            }
+            EmacsCommand::KillRegion => {
+                let range = self.selection.range();
+                self.emacs_kill(range);
+            }
+            EmacsCommand::CopyRegion => {
+                self.copy();
+                self.set_emacs_mark_active(false);
+            }
+            EmacsCommand::Yank => {
+                self.set_emacs_mark_active(false);
+                self.paste();
+            }
+            EmacsCommand::SetMark => self.set_emacs_mark_active(true),
+            EmacsCommand::ExchangePointAndMark => {
+                let anchor = self.selection.anchor;
+                self.set_caret(caret);
+                self.move_caret(anchor, true);
+                if let Some(emacs) = self.emacs.as_mut() {
+                    emacs.mark_active = true;
+                }
+            }
+            EmacsCommand::MarkWholeBuffer => {
+                self.set_caret(self.document.len());
+                self.move_caret(0, true);
+                if let Some(emacs) = self.emacs.as_mut() {
+                    emacs.mark_active = true;
+                }
+                self.on_selection_made();
+            }
+            EmacsCommand::Undo => {
+                self.set_emacs_mark_active(false);
+                self.undo();
+            }
+            EmacsCommand::KeyboardQuit => self.set_emacs_mark_active(false),
+            _ => {}
+        }
+    }
+
+    /// Sets the mark at the caret and activates it, or deactivates it; either
+    /// way the selection collapses at the caret.
+    fn set_emacs_mark_active(&mut self, active: bool) {
+        self.command_manager.close_step();
+        self.set_caret(self.selection.active);
+        if let Some(emacs) = self.emacs.as_mut() {
+            emacs.mark_active = active;
+        }
+    }
+
+    /// Deletes `range` for an Emacs kill command and puts the killed text on
+    /// the clipboard. Kills right after each other are put there together.
+    fn emacs_kill(&mut self, range: Range<usize>) {
+        let Some(emacs) = self.emacs.as_mut() else {
+            return;
+        };
+        emacs.mark_active = false;
+        if range.is_empty() {
+            return;
+        }
+        let text = &self.document.get_content()[range.clone()];
+        if emacs.last_was_kill {
+            emacs.killed.push_str(text);
+        } else {
+            emacs.killed = text.to_string();
+        }
+        emacs.last_was_kill = true;
+        if let Err(e) = clipboard::set_text(self.hwnd, &emacs.killed) {
+            eprintln!("Failed to copy to the clipboard: {}", e);
+        }
+        match self.execute(Box::new(DeleteCommand::new(range.start, range.len()))) {
+            Ok(()) => usage_stats::record_edit(),
+            Err(e) => eprintln!("Failed to delete text: {}", e),
+        }
+        self.set_caret(range.start);
+    }
+
+    /// Adds the lines changed by a command to the dirty region.
+    fn invalidate_affected(&mut self, affected: AffectedLines) {
+        if self.aligned_view.get_mut().is_some() {
+            // Column widths may have changed, which moves the fields of every line
+            self.frame_pacer.invalidate(self.hwnd, None);
+            return;
+        }
+        let mut rect = RECT::default();
+        let _ = unsafe { GetClientRect(self.hwnd, &mut rect) };
+        rect.top = rect.top.max(self.line_top(affected.first));
+        // Otherwise all lines below moved up or down
+        if !affected.lines_moved {
+            rect.bottom = rect.bottom.min(self.line_top(affected.last + 1));
+        }
+        self.frame_pacer.invalidate(self.hwnd, Some(rect));
+    }
+
+    /// Adds the rows of the given lines to the dirty region.
+    fn invalidate_lines(&mut self, lines: RangeInclusive<usize>) {
+        let mut rect = RECT::default();
+        let _ = unsafe { GetClientRect(self.hwnd, &mut rect) };
+        if *lines.start() == 0 && self.header_frozen() {
+            let header = RECT { bottom: rect.top + self.font_height, ..rect };
+            self.frame_pacer.invalidate(self.hwnd, Some(header));
+        }
+        rect.top = rect.top.max(self.row_top(*lines.start()));
+        rect.bottom = rect.bottom.min(self.row_top(*lines.end() + 1));
+        self.frame_pacer.invalidate(self.hwnd, Some(rect));
+    }
+
+    /// Copies the document text into `buffer` as a null-terminated wide string (WM_GETTEXT).
+    /// Returns the number of characters copied, excluding the terminator.
+    fn get_text(&self, buffer: &mut [u16]) -> usize {
+        let Some((terminator, text)) = buffer.split_last_mut() else {
+            return 0;
+        };
+        let mut copied = 0;
+        for (slot, unit) in text.iter_mut().zip(self.document.get_content().encode_utf16()) {
+            *slot = unit;
+            copied += 1;
+        }
+        if copied < text.len() {
+            text[copied] = 0;
+        } else {
+            *terminator = 0;
+        }
+        copied
+    }
+
+    /// Replaces the whole document with `text` (WM_SETTEXT). The document stays
+    /// associated with its file path.
+    fn set_text(&mut self, text: String) {
+        self.document.set_content(text);
+        self.command_manager.clear();
+        self.set_caret(0);
+        self.line_count = self.document.line_count();
+        self.reset_scroll();
+        self.frame_pacer.invalidate(self.hwnd, None);
+        self.report_modified();
+    }
+
+    /// Marks the document as changed or unchanged (EM_SETMODIFY).
+    fn set_modified(&mut self, modified: bool) {
+        self.document.set_modified(modified);
+        self.report_modified();
+    }
+
+    /// Sends EVN_MODIFIEDCHANGE to the parent window when the document became
+    /// modified or unmodified since the last report, e.g. to mark the window title.
+    fn report_modified(&mut self) {
+        let modified = self.document.is_modified();
+        if modified == self.reported_modified {
+            return;
+        }
+        self.reported_modified = modified;
+        if let Ok(parent) = unsafe { GetParent(self.hwnd) } {
+            let wparam = WPARAM((EVN_MODIFIEDCHANGE as usize) << 16);
+            unsafe { SendMessageW(parent, WM_COMMAND, Some(wparam), Some(LPARAM(self.hwnd.0 as isize))) };
+        }
+    }
+
+    /// Returns the selection as character indices (EM_GETSEL).
+    fn get_selection(&self) -> (usize, usize) {
+        let content = self.document.get_content();
+        (utf16_index_of(content, self.selection.start()), utf16_index_of(content, self.selection.end()))
+    }
+
+    /// Selects the characters from `start` to `end` (EM_SETSEL). A negative `start`
+    /// removes the selection, a negative `end` selects to the end of the text.
+    fn set_selection(&mut self, start: isize, end: isize) {
+        self.command_manager.close_step();
+        if start < 0 {
+            self.set_caret(self.selection.active);
+            return;
+        }
+        let content = self.document.get_content();
+        let start = offset_of_utf16_index(content, start as usize);
+        let end = if end < 0 { content.len() } else { offset_of_utf16_index(content, end as usize) };
+        // As in the edit control, the caret is at the end given, even for a reversed selection
+        self.set_caret(start);
+        self.move_caret(end, true);
+    }
+
+    /// Replaces the selected text with `text` and places an empty selection
+    /// after it (EM_REPLACESEL).
+    fn replace_selection(&mut self, text: &str) {
+        let selection = self.selection.range();
+        if !selection.is_empty() {
+            if let Err(e) = self.execute(Box::new(DeleteCommand::new(selection.start, selection.len()))) {
+                eprintln!("Failed to delete the selection: {}", e);
+                return;
+            }
+        }
+        if !text.is_empty() {
+            // Undone together with the deletion
+            let command = Box::new(InsertCommand::new(selection.start, text.to_string()));
+            let result = if selection.is_empty() {
+                self.command_manager.execute(command, &mut self.document)
+            } else {
+                self.command_manager.execute_in_last_step(command, &mut self.document)
+            };
+            match result {
+                Ok(affected) => self.update_after_edit(affected),
+                Err(e) => eprintln!("Failed to insert text: {}", e),
+            }
+        }
+        self.set_caret(selection.start + text.len());
+        usage_stats::record_edit();
+    }
+
+    /// Returns the line containing the character at `index` (EM_LINEFROMCHAR),
+    /// or the line of the selection start for a negative `index`.
+    fn line_from_char(&self, index: isize) -> usize {
+        let offset = if index < 0 {
+            self.selection.start()
+        } else {
+            offset_of_utf16_index(self.document.get_content(), index as usize)
+        };
+        self.document.line_from_offset(offset)
+    }
+
+    /// Returns the character index at which `line` starts (EM_LINEINDEX), or the
+    /// start of the caret's line for a negative `line`. None if there is no such line.
+    fn line_index(&self, line: isize) -> Option<usize> {
+        let line = if line < 0 { self.document.line_from_offset(self.selection.active) } else { line as usize };
+        let offset = self.document.line_start(line)?;
+        Some(utf16_index_of(self.document.get_content(), offset))
+    }
+
+   // TODO: Additional methods handling scrolling, keyboard input, etc.
+}
+
+impl Drop for EditorView {
+    fn drop(&mut self) {
+        if let Some(zoomed_font) = self.zoomed_font.take() {
+            let _ = unsafe { DeleteObject(zoomed_font.into()) };
+        }
+    }
+}
+
+/// Returns a slightly darker shade of `color` (a GetSysColor value) for
+/// tinting alternate columns, so it works with light and dark color schemes.
+fn column_tint(color: u32) -> COLORREF {
+    let darken = |shift: u32| (((color >> shift) & 0xFF) * 92 / 100) << shift;
+    COLORREF(darken(0) | darken(8) | darken(16))
+}
+
+/// Returns the screen column of the byte offset `offset` in a line laid out
+/// as aligned `fields`, or None past the last field.
+fn aligned_column(fields: &[AlignedField], line_text: &str, offset: usize) -> Option<usize> {
+    fields.iter().find(|field| offset <= field.range.end).map(|field| {
+        let field_text = &line_text[field.range.clone()];
+        field.start_column + line_layout::display_column(field_text, offset.max(field.range.start) - field.range.start)
+    })
+}
+
+/// Converts a character index of the edit-control messages, which count
+/// UTF-16 code units, to a byte offset into `text`, clamped to its end.
+fn offset_of_utf16_index(text: &str, index: usize) -> usize {
+    let mut units = 0;
+    for (offset, ch) in text.char_indices() {
+        if units >= index {
+            return offset;
+        }
+        units += ch.len_utf16();
+    }
+    text.len()
+}
+
+/// Converts a byte offset into `text` to an edit-control character index.
+fn utf16_index_of(text: &str, offset: usize) -> usize {
+    text[..offset].encode_utf16().count()
+}
+
+/// Returns `text` with the line endings and byte order mark that SAVE_* `flags` ask for.
+fn save_conversion(text: &str, flags: usize) -> String {
+    let mut converted = if flags & SAVE_LINE_ENDINGS_CRLF != 0 {
+        file_io::convert_line_endings(text, LineEnding::CrLf)
+    } else if flags & SAVE_LINE_ENDINGS_LF != 0 {
+        file_io::convert_line_endings(text, LineEnding::Lf)
+    } else {
+        text.to_string()
+    };
+    let has_bom = converted.starts_with(file_io::BYTE_ORDER_MARK);
+    if flags & SAVE_ENCODING_UTF8 != 0 && has_bom {
+        converted.remove(0);
+    } else if flags & SAVE_ENCODING_UTF8_BOM != 0 && !has_bom {
+        converted.insert(0, file_io::BYTE_ORDER_MARK);
+    }
+    converted
+}
+
+/// Removes the characters TranslateMessage posted for a key that was handled
+/// as a command, so they aren't typed or taken as menu mnemonics.
+fn discard_typed_chars(hwnd: HWND) {
+    let mut msg = MSG::default();
+    unsafe {
+        while PeekMessageW(&mut msg, Some(hwnd), WM_CHAR, WM_CHAR, PM_REMOVE).as_bool() {}
+        while PeekMessageW(&mut msg, Some(hwnd), WM_SYSCHAR, WM_SYSCHAR, PM_REMOVE).as_bool() {}
+    }
+}
+
+pub fn init_editor_view() -> Result<(), Box<dyn Error>> {
+    unsafe {
+        let hinstance = GetModuleHandleW(None)?;
+
+        // Load the standard arrow cursor
+        let hcursor = LoadCursorW(None, IDC_ARROW)?;
+
+        let wc = WNDCLASSW {
+            hInstance: hinstance.into(),
+            lpszClassName: EDITOR_VIEW_CLASS,
+            lpfnWndProc: Some(wndproc),
+            hCursor: hcursor,
+            hbrBackground: HBRUSH(ptr::null_mut()), // No background brush (we handle painting)
+            cbWndExtra: std::mem::size_of::<*mut EditorView>() as i32, // Reserve extra space for pointer
+            ..Default::default()
+        };
+
+        // Register the window class; embedding hosts may already have done so
+        if RegisterClassW(&wc) == 0 {
+            let error = windows::core::Error::from_win32();
+            if error.code() != ERROR_CLASS_ALREADY_EXISTS.to_hresult() {
+                return Err(error.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn create_editor_view(hwnd_parent: HWND) -> Result<HWND, Box<dyn Error>> {
+    unsafe {
+        let hinstance = GetModuleHandleW(None)?;
+
+        let hwnd = CreateWindowExW(
+            WINDOW_EX_STYLE::default(),                      // Optional window styles
+            EDITOR_VIEW_CLASS,                               // Window class name
+            EDITOR_VIEW_CLASS,                                          // Window title (none)
+            WS_CHILD | WS_VISIBLE | WS_VSCROLL | WS_HSCROLL, // Window styles
+            0, 0, 0, 0,                                      // Position and size (set later)
+            Some(hwnd_parent),                               // Wrap hwnd_parent in Some()
+            None,                                            // No menu or child ID
+            Some(hinstance.into()),                          // Wrap hinstance in Some() and convert
+            None,                                            // No additional application data
+        )?;
+
+        Ok(hwnd)
+    }
+}
+
+extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    unsafe {
+
+        match msg {
+            // First message received by any window:
+            WM_NCCREATE => {
+                // Create the EditorView instance when the window is created
+                let editor_view = Box::new(EditorView::new(hwnd));
+                // Store a raw pointer to the EditorView in the window's extra data (at offset 0)
+                SetWindowLongPtrW(hwnd, WINDOW_LONG_PTR_INDEX(0), Box::into_raw(editor_view) as isize); // Use WINDOW_LONG_PTR_INDEX
+                return LRESULT(1); 
+            }
+            // Last message received - clean up the EditorView instance
+            WM_NCDESTROY => {
+                // Retrieve the pointer from window extra storage
+                let ptr = GetWindowLongPtrW(hwnd, WINDOW_LONG_PTR_INDEX(0)) as *mut EditorView;
+                if !ptr.is_null() {
+                    // Convert the raw pointer back to a Box to allow Rust to drop it
+                    let _ = Box::from_raw(ptr);
+                    // Clear the pointer from window storage to prevent double-free
+                    SetWindowLongPtrW(hwnd, WINDOW_LONG_PTR_INDEX(0), 0); // Use WINDOW_LONG_PTR_INDEX
+                }
+                return LRESULT(0);
+            }
+            WM_PAINT => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    if let Err(_e) = editor_view.on_paint() {
+                        // eprintln!("on_paint error: {:?}", e); // Keep commented for debugging
+                    }
+                }
+                return LRESULT(0);
+            }
+            WM_TIMER => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    match wparam.0 {
+                        RENDER_TIMER_ID => editor_view.frame_pacer.flush(hwnd),
+                        IDLE_TIMER_ID => editor_view.on_idle_timer(),
+                        COLUMN_RESCAN_TIMER_ID => editor_view.start_column_rescan(),
+                        _ => {}
+                    }
+                }
+                return LRESULT(0);
+            }
+            WM_KEYDOWN => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    let chord = Chord {
+                        ctrl: GetKeyState(VK_CONTROL.0 as i32) < 0,
+                        alt: GetKeyState(VK_MENU.0 as i32) < 0,
+                        shift: GetKeyState(VK_SHIFT.0 as i32) < 0,
+                        key: VIRTUAL_KEY(wparam.0 as u16),
+                    };
+                    if editor_view.on_emacs_key(chord) {
+                        discard_typed_chars(hwnd);
+                        return LRESULT(0);
+                    }
+                    if editor_view.on_standard_key(chord) {
+                        return LRESULT(0);
+                    }
+                }
+                return DefWindowProcW(hwnd, msg, wparam, lparam);
+            }
+            WM_SYSKEYDOWN => {
+                // Keys pressed with Alt, which Emacs key bindings use as Meta, and F10.
+                // Bit 29 of lparam is set while Alt is down.
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    let ctrl_down = GetKeyState(VK_CONTROL.0 as i32) < 0;
+                    let shift_down = GetKeyState(VK_SHIFT.0 as i32) < 0;
+                    let alt_down = lparam.0 & (1 << 29) != 0;
+                    let chord = Chord { ctrl: ctrl_down, alt: alt_down, shift: shift_down, key: VIRTUAL_KEY(wparam.0 as u16) };
+                    if editor_view.on_emacs_key(chord) {
+                        discard_typed_chars(hwnd);
+                        return LRESULT(0);
+                    }
+                }
+                return DefWindowProcW(hwnd, msg, wparam, lparam);
+            }
+            WM_LBUTTONDOWN => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    // Client coordinates are signed 16-bit values
+                    let x = (lparam.0 & 0xFFFF) as i16 as i32;
+                    let y = ((lparam.0 >> 16) & 0xFFFF) as i16 as i32;
+                    editor_view.on_left_button_down(x, y, wparam.0 & MK_SHIFT.0 as usize != 0);
+                }
+                return LRESULT(0);
+            }
+            WM_MOUSEMOVE => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    let x = (lparam.0 & 0xFFFF) as i16 as i32;
+                    let y = ((lparam.0 >> 16) & 0xFFFF) as i16 as i32;
+                    editor_view.on_mouse_move(x, y);
+                }
+                return LRESULT(0);
+            }
+            WM_LBUTTONUP => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.on_left_button_up();
+                } else {
+                    let _ = ReleaseCapture();
+                }
+                return LRESULT(0);
+            }
+            WM_MBUTTONDOWN => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    let x = (lparam.0 & 0xFFFF) as i16 as i32;
+                    let y = ((lparam.0 >> 16) & 0xFFFF) as i16 as i32;
+                    if editor_view.on_middle_button_down(x, y) {
+                        return LRESULT(0);
+                    }
+                }
+                return DefWindowProcW(hwnd, msg, wparam, lparam);
+            }
+            WM_SETFOCUS => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.on_set_focus();
+                }
+                return LRESULT(0);
+            }
+            WM_KILLFOCUS => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.on_kill_focus();
+                }
+                return LRESULT(0);
+            }
+            WM_CHAR => {
+                // wparam is a UTF-16 code unit; characters outside the BMP arrive as two messages
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    if !editor_view.on_vi_char(wparam.0 as u16) {
+                        editor_view.on_char(wparam.0 as u16);
+                    }
+                }
+                return LRESULT(0);
+            }
+            WM_MOUSEWHEEL | WM_MOUSEHWHEEL => {
+                let delta = (wparam.0 >> 16) as u16 as i16;
+                let keys = wparam.0 & 0xFFFF;
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    if msg == WM_MOUSEHWHEEL {
+                        editor_view.on_mouse_wheel(delta, true);
+                    } else if keys & MK_CONTROL.0 as usize != 0 {
+                        // Ctrl+wheel zooms by one step per notch, whatever the wheel resolution
+                        if delta != 0 {
+                            editor_view.zoom(delta.signum() as i32);
+                        }
+                    } else if keys & MK_SHIFT.0 as usize != 0 {
+                        // Shift turns the wheel sideways; turning it towards the user scrolls right
+                        editor_view.on_mouse_wheel(-delta, true);
+                    } else {
+                        editor_view.on_mouse_wheel(delta, false);
+                    }
+                }
+                return LRESULT(0);
+            }
+            WM_SIZE => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.update_scroll_bars();
+                }
+                return LRESULT(0);
+            }
+            WM_VSCROLL => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.on_vscroll(SCROLLBAR_COMMAND((wparam.0 & 0xFFFF) as i32));
+                }
+                return LRESULT(0);
+            }
+            WM_HSCROLL => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.on_hscroll(SCROLLBAR_COMMAND((wparam.0 & 0xFFFF) as i32));
+                }
+                return LRESULT(0);
+            }
+            WM_CONTEXTMENU => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    // Screen coordinates, both -1 when opened from the keyboard
+                    let x = (lparam.0 & 0xFFFF) as i16 as i32;
+                    let y = ((lparam.0 >> 16) & 0xFFFF) as i16 as i32;
+                    editor_view.on_context_menu(x, y);
+                }
+                return LRESULT(0);
+            }
+            WM_SETTINGCHANGE | WM_SYSCOLORCHANGE | WM_THEMECHANGED | WM_FONTCHANGE => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.on_settings_change();
+                }
+                return LRESULT(0);
+            }
+            WM_SETFONT => {
+                let hfont = HFONT(wparam.0 as _); // Cast usize directly to *mut c_void implicitly
+                let redraw = lparam != LPARAM(0);
+
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    if let Err(_e) = editor_view.on_set_font(hfont) {
+                        // eprintln!("WM_SETFONT error: {:?}", e); // Keep commented for debugging
+                    } else if redraw {
+                        InvalidateRect(Some(hwnd), None, true);
+                    }
+                }
+                return LRESULT(0);
+            }
+            // A subset of the edit control protocol, so automation tools and test
+            // frameworks expecting an edit control can read and change the text
+            WM_CUT => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.cut();
+                }
+                return LRESULT(0);
+            }
+            WM_COPY => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.copy();
+                }
+                return LRESULT(0);
+            }
+            WM_PASTE => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.paste();
+                }
+                return LRESULT(0);
+            }
+            WM_GETTEXTLENGTH => {
+                let length = EditorView::from_hwnd(hwnd)
+                    .map_or(0, |editor_view| editor_view.document.get_content().encode_utf16().count());
+                return LRESULT(length as isize);
+            }
+            WM_GETTEXT => {
+                // wparam is the buffer capacity in characters, lparam the buffer pointer
+                let mut copied = 0;
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    if lparam.0 != 0 {
+                        let buffer = std::slice::from_raw_parts_mut(lparam.0 as *mut u16, wparam.0);
+                        copied = editor_view.get_text(buffer);
+                    }
+                }
+                return LRESULT(copied as isize);
+            }
+            WM_SETTEXT => {
+                let text_pcwstr = PCWSTR(lparam.0 as *const u16); // lparam is PCWSTR, may be null
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    let text = if text_pcwstr.is_null() {
+                        String::new()
+                    } else {
+                        String::from_utf16_lossy(text_pcwstr.as_wide())
+                    };
+                    editor_view.set_text(text);
+                    return LRESULT(1);
+                }
+                return LRESULT(0);
+            }
+            EM_GETSEL => {
+                // wparam and lparam optionally point to DWORDs receiving the start and end
+                let (start, end) = EditorView::from_hwnd(hwnd).map_or((0, 0), |editor_view| editor_view.get_selection());
+                if wparam.0 != 0 {
+                    *(wparam.0 as *mut u32) = start as u32;
+                }
+                if lparam.0 != 0 {
+                    *(lparam.0 as *mut u32) = end as u32;
+                }
+                // Both positions are packed into the result only if they fit in a word
+                if start > 0xFFFF || end > 0xFFFF {
+                    return LRESULT(-1);
+                }
+                return LRESULT((start | end << 16) as isize);
+            }
+            EM_GETMODIFY => {
+                let modified = EditorView::from_hwnd(hwnd).is_some_and(|editor_view| editor_view.document.is_modified());
+                return LRESULT(modified as isize);
+            }
+            EM_SETMODIFY => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.set_modified(wparam.0 != 0);
+                }
+                return LRESULT(0);
+            }
+            EM_SETSEL => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.set_selection(wparam.0 as isize, lparam.0);
+                }
+                return LRESULT(0);
+            }
+            EM_REPLACESEL => {
+                // wparam (whether the change can be undone) is ignored, lparam is PCWSTR
+                let text_pcwstr = PCWSTR(lparam.0 as *const u16);
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    if !text_pcwstr.is_null() {
+                        editor_view.replace_selection(&String::from_utf16_lossy(text_pcwstr.as_wide()));
+                    }
+                }
+                return LRESULT(0);
+            }
+            EM_LINEFROMCHAR => {
+                let line = EditorView::from_hwnd(hwnd)
+                    .map_or(0, |editor_view| editor_view.line_from_char(wparam.0 as isize));
+                return LRESULT(line as isize);
+            }
+            EM_LINEINDEX => {
+                let index = EditorView::from_hwnd(hwnd)
+                    .and_then(|editor_view| editor_view.line_index(wparam.0 as isize));
+                return LRESULT(index.map_or(-1, |index| index as isize));
+            }
+            EVM_OPENFILE => {
+                let filename_pcwstr = PCWSTR(lparam.0 as *const u16); // lparam is PCWSTR
+                let mut success = false;
+
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    match editor_view.open_file(filename_pcwstr) {
+                        Ok(_) => success = true,
                         Err(_e) => {
                             // eprintln!("EVM_OPENFILE error: {:?}", e); // Keep commented for debugging
                             // TODO: Show a message box to the user on error
@@ -297,6 +2915,21 @@ extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM)
                 // Return 1 for success, 0 for failure
                 return LRESULT(if success { 1 } else { 0 });
             }
+            EVM_OPENLOADEDFILE => {
+                let filename_pcwstr = PCWSTR(lparam.0 as *const u16); // lparam is PCWSTR, or null
+                // wparam points to the file's content, which is taken over
+                let (text, replacement_offsets) = std::mem::take(&mut *(wparam.0 as *mut (String, Vec<usize>)));
+                let path = (!filename_pcwstr.is_null()).then(|| PathBuf::from(std::ffi::OsString::from_wide(filename_pcwstr.as_wide())));
+                let mut success = false;
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    match editor_view.open_loaded_file(path.as_deref(), text, replacement_offsets) {
+                        Ok(_) => success = true,
+                        Err(e) => eprintln!("EVM_OPENLOADEDFILE error: {}", e),
+                    }
+                }
+                // Return 1 for success, 0 for failure
+                return LRESULT(if success { 1 } else { 0 });
+            }
             EVM_CLEARFILE => {
                 let mut success = false;
                 if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
@@ -310,6 +2943,154 @@ extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM)
                  // Return 1 for success, 0 for failure
                 return LRESULT(if success { 1 } else { 0 });
             }
+            EVM_SAVEFILE => {
+                let filename_pcwstr = PCWSTR(lparam.0 as *const u16); // lparam is PCWSTR or null
+                let mut success = false;
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    // wparam holds SAVE_* conversion flags
+                    match editor_view.save_file(filename_pcwstr, wparam.0) {
+                        Ok(_) => success = true,
+                        Err(e) => eprintln!("EVM_SAVEFILE error: {}", e),
+                    }
+                }
+                // Return 1 for success, 0 for failure
+                return LRESULT(if success { 1 } else { 0 });
+            }
+            EVM_GETFILEPATH => {
+                // wparam is the buffer capacity in characters, lparam the buffer pointer
+                let mut copied = 0;
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    let buffer: &mut [u16] = if lparam.0 == 0 {
+                        &mut []
+                    } else {
+                        std::slice::from_raw_parts_mut(lparam.0 as *mut u16, wparam.0)
+                    };
+                    copied = editor_view.get_file_path(buffer);
+                }
+                return LRESULT(copied as isize);
+            }
+            EVM_GETENCODINGERRORS => {
+                // Returns the number of replacement characters inserted on load
+                let count = EditorView::from_hwnd(hwnd)
+                    .map_or(0, |editor_view| editor_view.document.encoding_error_count());
+                return LRESULT(count as isize);
+            }
+            EVM_FORMATDOCUMENT => {
+                // wparam is nonzero to minify, zero to pretty-print; lparam is the
+                // number of spaces to indent by, zero for the default
+                let style = if wparam.0 != 0 { FormatStyle::Minify } else { FormatStyle::Pretty };
+                let indent = if lparam.0 > 0 { lparam.0 as usize } else { DEFAULT_INDENT };
+                let mut success = false;
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    match editor_view.format_document(style, indent) {
+                        Ok(_) => success = true,
+                        Err(e) => eprintln!("EVM_FORMATDOCUMENT error: {}", e),
+                    }
+                }
+                // Return 1 for success, 0 for failure
+                return LRESULT(if success { 1 } else { 0 });
+            }
+            EVM_SETALIGNEDVIEW => {
+                // wparam is nonzero to show aligned columns; returns 1 if they are shown
+                let enabled = EditorView::from_hwnd(hwnd)
+                    .is_some_and(|editor_view| editor_view.set_aligned_view(wparam.0 != 0));
+                return LRESULT(enabled as isize);
+            }
+            EVM_GETALIGNEDVIEW => {
+                let enabled = EditorView::from_hwnd(hwnd)
+                    .is_some_and(|editor_view| editor_view.aligned_view.get_mut().is_some());
+                return LRESULT(enabled as isize);
+            }
+            EVM_SETFREEZEHEADER => {
+                // wparam is nonzero to keep the header row of aligned columns in view
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.freeze_header = wparam.0 != 0;
+                    editor_view.frame_pacer.invalidate(hwnd, None);
+                    editor_view.update_caret_position();
+                }
+                return LRESULT(0);
+            }
+            EVM_GETFREEZEHEADER => {
+                let freeze = EditorView::from_hwnd(hwnd).is_some_and(|editor_view| editor_view.freeze_header);
+                return LRESULT(freeze as isize);
+            }
+            EVM_ZOOM => {
+                // wparam is the signed number of zoom steps; 0 restores the default zoom
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.zoom((wparam.0 as isize).clamp(i32::MIN as isize, i32::MAX as isize) as i32);
+                }
+                return LRESULT(0);
+            }
+            EVM_SETZOOMSYNC => {
+                // wparam is nonzero to use one zoom level for all documents
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.set_zoom_sync(wparam.0 != 0);
+                }
+                return LRESULT(0);
+            }
+            EVM_GETZOOMSYNC => {
+                let sync_all = EditorView::from_hwnd(hwnd).is_some_and(|editor_view| editor_view.zoom.sync_all());
+                return LRESULT(sync_all as isize);
+            }
+            EVM_SETAUTOCOPY => {
+                // wparam is nonzero to copy selections as they are made
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.auto_copy = wparam.0 != 0;
+                }
+                return LRESULT(0);
+            }
+            EVM_GETAUTOCOPY => {
+                let auto_copy = EditorView::from_hwnd(hwnd).is_some_and(|editor_view| editor_view.auto_copy);
+                return LRESULT(auto_copy as isize);
+            }
+            EVM_SETSCROLLMARGIN => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.scroll_margin = wparam.0;
+                    editor_view.ensure_caret_visible();
+                }
+                return LRESULT(0);
+            }
+            EVM_SETKEYBINDINGS => {
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.set_key_bindings(KeyBindings::from_index(wparam.0));
+                }
+                return LRESULT(0);
+            }
+            EVM_GETKEYBINDINGS => {
+                let key_bindings = EditorView::from_hwnd(hwnd).map_or(KeyBindings::Standard, |editor_view| editor_view.key_bindings());
+                return LRESULT(key_bindings.index() as isize);
+            }
+            EVM_SUSPENDTIMERS => {
+                // wparam is nonzero to suspend background timers, zero to resume them
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    editor_view.suspend_timers(wparam.0 != 0);
+                }
+                return LRESULT(0);
+            }
+            EVM_NEWFROMTEMPLATE => {
+                let template_pcwstr = PCWSTR(lparam.0 as *const u16); // lparam is PCWSTR
+                let mut success = false;
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    match editor_view.new_from_template(template_pcwstr) {
+                        Ok(_) => success = true,
+                        Err(e) => eprintln!("EVM_NEWFROMTEMPLATE error: {}", e),
+                    }
+                }
+                // Return 1 for success, 0 for failure
+                return LRESULT(if success { 1 } else { 0 });
+            }
+            EVM_RESTOREFILE => {
+                let filename_pcwstr = PCWSTR(lparam.0 as *const u16); // lparam is PCWSTR
+                let mut success = false;
+                if let Some(editor_view) = EditorView::from_hwnd(hwnd) {
+                    match editor_view.restore_file(filename_pcwstr) {
+                        Ok(_) => success = true,
+                        Err(e) => eprintln!("EVM_RESTOREFILE error: {}", e),
+                    }
+                }
+                // Return 1 for success, 0 for failure
+                return LRESULT(if success { 1 } else { 0 });
+            }
             _ => return DefWindowProcW(hwnd, msg, wparam, lparam),
         }
     }
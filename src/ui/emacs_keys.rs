@@ -0,0 +1,144 @@
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    VK_A, VK_B, VK_D, VK_E, VK_F, VK_G, VK_H, VK_K, VK_N, VK_OEM_2, VK_OEM_COMMA, VK_OEM_MINUS,
+    VK_OEM_PERIOD, VK_P, VK_SPACE, VK_U, VK_V, VK_W, VK_X, VK_Y,
+};
+use crate::ui::keymap::{Binding, Chord, Keymap};
+
+/// Editing commands of the Emacs key bindings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmacsCommand {
+    LineStart,
+    LineEnd,
+    ForwardChar,
+    BackwardChar,
+    NextLine,
+    PreviousLine,
+    /// To the end of the word, or of the next one.
+    ForwardWord,
+    /// To the start of the word, or of the previous one.
+    BackwardWord,
+    BufferStart,
+    BufferEnd,
+    PageDown,
+    PageUp,
+    DeleteChar,
+    /// Kills to the end of the word.
+    KillWord,
+    /// Kills to the end of the line, or the line break at the end of a line.
+    KillLine,
+    /// Kills the text between the mark and the caret.
+    KillRegion,
+    /// Copies the text between the mark and the caret to the kill ring.
+    CopyRegion,
+    /// Inserts the last killed text.
+    Yank,
+    SetMark,
+    ExchangePointAndMark,
+    /// Selects the whole document, with the caret at its start.
+    MarkWholeBuffer,
+    Undo,
+    /// Deactivates the mark and drops a partly typed key sequence.
+    KeyboardQuit,
+}
+
+/// Returns the core Emacs key bindings.
+pub fn keymap() -> Keymap<EmacsCommand> {
+    use EmacsCommand::*;
+    Keymap::new(vec![
+        Binding::new(&[Chord::ctrl(VK_A)], LineStart, "Move to the start of the line"),
+        Binding::new(&[Chord::ctrl(VK_E)], LineEnd, "Move to the end of the line"),
+        Binding::new(&[Chord::ctrl(VK_F)], ForwardChar, "Move forward a character"),
+        Binding::new(&[Chord::ctrl(VK_B)], BackwardChar, "Move back a character"),
+        Binding::new(&[Chord::ctrl(VK_N)], NextLine, "Move to the next line"),
+        Binding::new(&[Chord::ctrl(VK_P)], PreviousLine, "Move to the previous line"),
+        Binding::new(&[Chord::alt(VK_F)], ForwardWord, "Move forward a word"),
+        Binding::new(&[Chord::alt(VK_B)], BackwardWord, "Move back a word"),
+        Binding::new(&[Chord::alt(VK_OEM_COMMA).with_shift()], BufferStart, "Move to the start of the document"),
+        Binding::new(&[Chord::alt(VK_OEM_PERIOD).with_shift()], BufferEnd, "Move to the end of the document"),
+        Binding::new(&[Chord::ctrl(VK_V)], PageDown, "Scroll down a page"),
+        Binding::new(&[Chord::alt(VK_V)], PageUp, "Scroll up a page"),
+        Binding::new(&[Chord::ctrl(VK_D)], DeleteChar, "Delete the next character"),
+        Binding::new(&[Chord::alt(VK_D)], KillWord, "Kill to the end of the word"),
+        Binding::new(&[Chord::ctrl(VK_K)], KillLine, "Kill to the end of the line"),
+        Binding::new(&[Chord::ctrl(VK_W)], KillRegion, "Kill the region"),
+        Binding::new(&[Chord::alt(VK_W)], CopyRegion, "Copy the region"),
+        Binding::new(&[Chord::ctrl(VK_Y)], Yank, "Yank the last killed text"),
+        Binding::new(&[Chord::ctrl(VK_SPACE)], SetMark, "Set the mark"),
+        Binding::new(&[Chord::ctrl(VK_X), Chord::ctrl(VK_X)], ExchangePointAndMark, "Exchange the caret and the mark"),
+        Binding::new(&[Chord::ctrl(VK_X), Chord::plain(VK_H)], MarkWholeBuffer, "Select the whole document"),
+        Binding::new(&[Chord::ctrl(VK_OEM_2)], Undo, "Undo"),
+        Binding::new(&[Chord::ctrl(VK_OEM_MINUS).with_shift()], Undo, "Undo"),
+        Binding::new(&[Chord::ctrl(VK_X), Chord::plain(VK_U)], Undo, "Undo"),
+        Binding::new(&[Chord::ctrl(VK_G)], KeyboardQuit, "Cancel the mark or a key sequence"),
+    ])
+}
+
+/// Key sequence, mark and kill state of a view using the Emacs key bindings.
+pub struct EmacsState {
+    pub keymap: Keymap<EmacsCommand>,
+    /// Set while the mark is active: the selection's anchor is the mark, and
+    /// moving the caret extends the selection.
+    pub mark_active: bool,
+    /// The text killed by the last kill command, which further kills right
+    /// after it add to. It is put on the clipboard, which serves as the kill ring.
+    pub killed: String,
+    /// Set when the last command was a kill.
+    pub last_was_kill: bool,
+}
+
+impl EmacsState {
+    pub fn new() -> Self {
+        EmacsState {
+            keymap: keymap(),
+            mark_active: false,
+            killed: String::new(),
+            last_was_kill: false,
+        }
+    }
+}
+
+/// Returns the end of the word at or after `offset`. Words are letters and digits.
+pub fn forward_word(text: &str, offset: usize) -> usize {
+    let mut chars = text[offset..].char_indices().map(|(i, ch)| (offset + i, ch)).peekable();
+    while chars.next_if(|&(_, ch)| !ch.is_alphanumeric()).is_some() {}
+    while chars.next_if(|&(_, ch)| ch.is_alphanumeric()).is_some() {}
+    chars.peek().map_or(text.len(), |&(i, _)| i)
+}
+
+/// Returns the start of the word before `offset`, which may be the one `offset` is in.
+pub fn backward_word(text: &str, offset: usize) -> usize {
+    let mut chars = text[..offset].char_indices().rev().peekable();
+    while chars.next_if(|&(_, ch)| !ch.is_alphanumeric()).is_some() {}
+    let mut start = chars.peek().map_or(0, |&(i, _)| i);
+    while let Some((i, _)) = chars.next_if(|&(_, ch)| ch.is_alphanumeric()) {
+        start = i;
+    }
+    start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forward_word_moves_to_end_of_word() {
+        let text = "one, two  thrée";
+        assert_eq!(forward_word(text, 0), 3);
+        assert_eq!(forward_word(text, 1), 3);
+        assert_eq!(forward_word(text, 3), 8);
+        assert_eq!(forward_word(text, 8), text.len());
+        assert_eq!(forward_word(text, text.len()), text.len());
+        assert_eq!(forward_word("one  ", 3), 5);
+    }
+
+    #[test]
+    fn backward_word_moves_to_start_of_word() {
+        let text = "one, two  thrée";
+        assert_eq!(backward_word(text, text.len()), 10);
+        assert_eq!(backward_word(text, 12), 10);
+        assert_eq!(backward_word(text, 10), 5);
+        assert_eq!(backward_word(text, 5), 0);
+        assert_eq!(backward_word(text, 0), 0);
+        assert_eq!(backward_word("  one", 2), 0);
+    }
+}
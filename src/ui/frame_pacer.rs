@@ -0,0 +1,90 @@
+use windows::Win32::{
+    Foundation::{HWND, RECT},
+    Graphics::Gdi::InvalidateRect,
+    UI::WindowsAndMessaging::{GetClientRect, KillTimer, SetTimer},
+};
+
+/// Timer that flushes the accumulated dirty region once per frame.
+pub const RENDER_TIMER_ID: usize = 2;
+const FRAME_INTERVAL_MS: u32 = 16; // ~60 fps
+
+/// Coalesces invalidations into a single dirty rectangle that is handed to
+/// InvalidateRect at most once per frame. Bursts of changes (fast typing,
+/// streaming appends) then cause one repaint instead of one per message.
+pub struct FramePacer {
+    dirty: Option<RECT>,
+    timer_active: bool,
+    /// Set while the window isn't shown; the dirty region then waits for `resume`.
+    suspended: bool,
+}
+
+impl FramePacer {
+    pub fn new() -> Self {
+        FramePacer {
+            dirty: None,
+            timer_active: false,
+            suspended: false,
+        }
+    }
+
+    /// Adds `rect` (or the whole client area for None) to the dirty region and
+    /// makes sure a flush is scheduled for the next frame.
+    pub fn invalidate(&mut self, hwnd: HWND, rect: Option<RECT>) {
+        let rect = match rect {
+            Some(rect) => rect,
+            None => {
+                let mut client = RECT::default();
+                let _ = unsafe { GetClientRect(hwnd, &mut client) };
+                client
+            }
+        };
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => RECT {
+                left: dirty.left.min(rect.left),
+                top: dirty.top.min(rect.top),
+                right: dirty.right.max(rect.right),
+                bottom: dirty.bottom.max(rect.bottom),
+            },
+            None => rect,
+        });
+
+        self.start_timer(hwnd);
+    }
+
+    /// Stops the render timer, keeping the dirty region for `resume`.
+    pub fn suspend(&mut self, hwnd: HWND) {
+        self.suspended = true;
+        self.stop_timer(hwnd);
+    }
+
+    /// Restarts the render timer if something became dirty while suspended.
+    pub fn resume(&mut self, hwnd: HWND) {
+        self.suspended = false;
+        if self.dirty.is_some() {
+            self.start_timer(hwnd);
+        }
+    }
+
+    /// Handles the render timer: invalidates the accumulated region and stops
+    /// the timer until something else becomes dirty.
+    pub fn flush(&mut self, hwnd: HWND) {
+        if let Some(dirty) = self.dirty.take() {
+            let _ = unsafe { InvalidateRect(Some(hwnd), Some(&dirty), true) };
+        }
+        self.stop_timer(hwnd);
+    }
+
+    fn start_timer(&mut self, hwnd: HWND) {
+        if !self.timer_active && !self.suspended {
+            unsafe { SetTimer(Some(hwnd), RENDER_TIMER_ID, FRAME_INTERVAL_MS, None) };
+            self.timer_active = true;
+        }
+    }
+
+    fn stop_timer(&mut self, hwnd: HWND) {
+        if self.timer_active {
+            let _ = unsafe { KillTimer(Some(hwnd), RENDER_TIMER_ID) };
+            self.timer_active = false;
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use windows::Win32::{
+    Foundation::HWND,
+    UI::WindowsAndMessaging::{GetQueueStatus, KillTimer, SetTimer, QS_INPUT},
+};
+
+/// Timer used to run idle work; WM_TIMER is a low-priority message, so it is
+/// only delivered once input and paint messages have been handled.
+pub const IDLE_TIMER_ID: usize = 1;
+const IDLE_TIMER_INTERVAL_MS: u32 = 50;
+
+/// Maximum time spent on idle work per timer tick.
+const IDLE_SLICE_BUDGET: Duration = Duration::from_millis(4);
+
+/// A unit of deferrable background work for the editor view.
+#[derive(Clone, Copy, PartialEq)]
+pub enum IdleTask {
+    /// Lay out lines `next..end` into the render cache ahead of scrolling.
+    PrewarmLines { next: usize, end: usize, max_columns: usize },
+    /// Measure the aligned view's column widths again, from line `next` on.
+    MeasureColumns { next: usize },
+}
+
+/// Queue of idle tasks run in small time slices from a timer, so they never
+/// hold up input handling.
+pub struct IdleScheduler {
+    tasks: VecDeque<IdleTask>,
+    timer_active: bool,
+    /// Set while the window is minimized, the session is locked or the system sleeps.
+    suspended: bool,
+}
+
+impl IdleScheduler {
+    pub fn new() -> Self {
+        IdleScheduler {
+            tasks: VecDeque::new(),
+            timer_active: false,
+            suspended: false,
+        }
+    }
+
+    /// Queues a task, replacing any queued task of the same kind, and starts
+    /// the idle timer if needed.
+    pub fn schedule(&mut self, hwnd: HWND, task: IdleTask) {
+        self.tasks.retain(|queued| std::mem::discriminant(queued) != std::mem::discriminant(&task));
+        self.tasks.push_back(task);
+        self.start_timer(hwnd);
+    }
+
+    /// Drops the queued task of the same kind as `task`, if any.
+    pub fn cancel(&mut self, hwnd: HWND, task: IdleTask) {
+        self.tasks.retain(|queued| std::mem::discriminant(queued) != std::mem::discriminant(&task));
+        if self.tasks.is_empty() {
+            self.stop_timer(hwnd);
+        }
+    }
+
+    /// Stops the idle timer without dropping queued tasks, so an inactive
+    /// window doesn't keep waking the CPU.
+    pub fn suspend(&mut self, hwnd: HWND) {
+        self.suspended = true;
+        self.stop_timer(hwnd);
+    }
+
+    /// Restarts the idle timer if tasks were queued while suspended.
+    pub fn resume(&mut self, hwnd: HWND) {
+        self.suspended = false;
+        if !self.tasks.is_empty() {
+            self.start_timer(hwnd);
+        }
+    }
+
+    fn start_timer(&mut self, hwnd: HWND) {
+        if !self.timer_active && !self.suspended {
+            unsafe { SetTimer(Some(hwnd), IDLE_TIMER_ID, IDLE_TIMER_INTERVAL_MS, None) };
+            self.timer_active = true;
+        }
+    }
+
+    fn stop_timer(&mut self, hwnd: HWND) {
+        if self.timer_active {
+            let _ = unsafe { KillTimer(Some(hwnd), IDLE_TIMER_ID) };
+            self.timer_active = false;
+        }
+    }
+
+    /// Runs queued tasks until the slice budget is used up or input arrives.
+    /// `run_step` performs one step of a task and returns the remaining task,
+    /// or None once it is complete. The timer is stopped when the queue empties.
+    pub fn run_slice(&mut self, hwnd: HWND, mut run_step: impl FnMut(IdleTask) -> Option<IdleTask>) {
+        let deadline = Instant::now() + IDLE_SLICE_BUDGET;
+        while let Some(task) = self.tasks.pop_front() {
+            if let Some(remaining) = run_step(task) {
+                self.tasks.push_front(remaining);
+            }
+            if Instant::now() >= deadline || input_pending() {
+                break;
+            }
+        }
+        if self.tasks.is_empty() {
+            self.stop_timer(hwnd);
+        }
+    }
+}
+
+/// Returns true if keyboard or mouse input is waiting in the thread's queue.
+fn input_pending() -> bool {
+    // The high word holds the types of messages currently in the queue
+    (unsafe { GetQueueStatus(QS_INPUT) } >> 16) != 0
+}
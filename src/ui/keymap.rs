@@ -0,0 +1,327 @@
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    VIRTUAL_KEY, VK_ADD, VK_BACK, VK_DELETE, VK_DOWN, VK_END, VK_ESCAPE, VK_F1, VK_F24, VK_HOME, VK_INSERT,
+    VK_LEFT, VK_NEXT, VK_NUMPAD0, VK_NUMPAD9, VK_OEM_2, VK_OEM_COMMA, VK_OEM_MINUS, VK_OEM_PERIOD, VK_OEM_PLUS,
+    VK_PRIOR, VK_RETURN, VK_RIGHT, VK_SPACE, VK_SUBTRACT, VK_TAB, VK_UP,
+};
+
+/// The key binding schemes the editor offers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyBindings {
+    /// The usual Windows keys only.
+    Standard,
+    /// Modal editing with vi keys on top of the usual Windows keys.
+    Vi,
+    /// Emacs keys, which take precedence over the Windows keys they share.
+    Emacs,
+}
+
+impl KeyBindings {
+    /// Returns the scheme for the number stored in settings and sent with
+    /// EVM_SETKEYBINDINGS. Unknown numbers give the standard scheme.
+    pub fn from_index(index: usize) -> Self {
+        match index {
+            1 => KeyBindings::Vi,
+            2 => KeyBindings::Emacs,
+            _ => KeyBindings::Standard,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// A key pressed together with modifier keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chord {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub key: VIRTUAL_KEY,
+}
+
+impl Chord {
+    pub const fn plain(key: VIRTUAL_KEY) -> Self {
+        Chord { ctrl: false, alt: false, shift: false, key }
+    }
+
+    pub const fn ctrl(key: VIRTUAL_KEY) -> Self {
+        Chord { ctrl: true, alt: false, shift: false, key }
+    }
+
+    pub const fn alt(key: VIRTUAL_KEY) -> Self {
+        Chord { ctrl: false, alt: true, shift: false, key }
+    }
+
+    pub const fn with_shift(self) -> Self {
+        Chord { shift: true, ..self }
+    }
+
+    /// Returns the chord in Emacs notation, e.g. `C-a` or `M-<`. Shifted
+    /// punctuation is named after the character it types on a US layout.
+    pub fn name(&self) -> String {
+        let mut name = String::new();
+        if self.ctrl {
+            name.push_str("C-");
+        }
+        if self.alt {
+            name.push_str("M-");
+        }
+        let key = match (self.key, self.shift) {
+            (VK_SPACE, _) => "SPC".to_string(),
+            (VK_BACK, _) => "DEL".to_string(),
+            (VK_DELETE, _) => "<delete>".to_string(),
+            (VK_OEM_COMMA, true) => "<".to_string(),
+            (VK_OEM_COMMA, false) => ",".to_string(),
+            (VK_OEM_PERIOD, true) => ">".to_string(),
+            (VK_OEM_PERIOD, false) => ".".to_string(),
+            (VK_OEM_MINUS, true) => "_".to_string(),
+            (VK_OEM_MINUS, false) => "-".to_string(),
+            (VK_OEM_2, _) => "/".to_string(),
+            // Letter and digit keys have the codes of their characters
+            (VIRTUAL_KEY(code @ (0x30..=0x39 | 0x41..=0x5A)), shift) => {
+                let ch = code as u8 as char;
+                if shift { ch.to_string() } else { ch.to_ascii_lowercase().to_string() }
+            }
+            (VIRTUAL_KEY(code), _) => format!("<{:#04x}>", code),
+        };
+        name.push_str(&key);
+        name
+    }
+
+    /// Returns the chord in Windows notation, e.g. `Ctrl+Shift+Z` or `Page Up`.
+    pub fn windows_name(&self) -> String {
+        let mut name = String::new();
+        if self.ctrl {
+            name.push_str("Ctrl+");
+        }
+        if self.alt {
+            name.push_str("Alt+");
+        }
+        if self.shift {
+            name.push_str("Shift+");
+        }
+        let key = match self.key {
+            VK_LEFT => "Left".to_string(),
+            VK_RIGHT => "Right".to_string(),
+            VK_UP => "Up".to_string(),
+            VK_DOWN => "Down".to_string(),
+            VK_HOME => "Home".to_string(),
+            VK_END => "End".to_string(),
+            VK_PRIOR => "Page Up".to_string(),
+            VK_NEXT => "Page Down".to_string(),
+            VK_INSERT => "Insert".to_string(),
+            VK_DELETE => "Delete".to_string(),
+            VK_BACK => "Backspace".to_string(),
+            VK_RETURN => "Enter".to_string(),
+            VK_TAB => "Tab".to_string(),
+            VK_ESCAPE => "Esc".to_string(),
+            VK_SPACE => "Space".to_string(),
+            VK_OEM_PLUS => "+".to_string(),
+            VK_OEM_MINUS => "-".to_string(),
+            VK_OEM_COMMA => ",".to_string(),
+            VK_OEM_PERIOD => ".".to_string(),
+            VK_OEM_2 => "/".to_string(),
+            VK_ADD => "Num +".to_string(),
+            VK_SUBTRACT => "Num -".to_string(),
+            VIRTUAL_KEY(code) if (VK_NUMPAD0.0..=VK_NUMPAD9.0).contains(&code) => format!("Num {}", code - VK_NUMPAD0.0),
+            VIRTUAL_KEY(code) if (VK_F1.0..=VK_F24.0).contains(&code) => format!("F{}", code - VK_F1.0 + 1),
+            // Letter and digit keys have the codes of their characters
+            VIRTUAL_KEY(code @ (0x30..=0x39 | 0x41..=0x5A)) => (code as u8 as char).to_string(),
+            VIRTUAL_KEY(code) => format!("<{:#04x}>", code),
+        };
+        name.push_str(&key);
+        name
+    }
+}
+
+/// A key that bindings are made of: a chord, or a character typed as vi keys are.
+pub trait Key: Copy + PartialEq {
+    /// Returns the name of a key sequence, for listing the bindings.
+    fn sequence_name(keys: &[Self]) -> String;
+}
+
+impl Key for Chord {
+    /// Names the chords in Emacs notation, e.g. `C-x u`.
+    fn sequence_name(keys: &[Self]) -> String {
+        keys.iter().map(Chord::name).collect::<Vec<_>>().join(" ")
+    }
+}
+
+impl Key for char {
+    /// Names the characters as vi does, e.g. `gg`, with the control characters
+    /// and blanks spelled out.
+    fn sequence_name(keys: &[Self]) -> String {
+        keys.iter()
+            .map(|&ch| match ch {
+                ' ' => "Space".to_string(),
+                '\r' => "Enter".to_string(),
+                '\x1b' => "Esc".to_string(),
+                // Ctrl+A to Ctrl+Z type the control characters 1 to 26
+                '\x01'..='\x1a' => format!("Ctrl+{}", (b'A' + ch as u8 - 1) as char),
+                _ => ch.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// A key sequence bound to an action.
+pub struct Binding<A, K = Chord> {
+    pub keys: Vec<K>,
+    pub action: A,
+    /// What the action does, for listing the bindings.
+    pub description: &'static str,
+}
+
+impl<A, K: Key> Binding<A, K> {
+    pub fn new(keys: &[K], action: A, description: &'static str) -> Self {
+        Binding { keys: keys.to_vec(), action, description }
+    }
+
+    /// Returns the key sequence, e.g. `C-x u` or `gg`.
+    pub fn keys_name(&self) -> String {
+        K::sequence_name(&self.keys)
+    }
+}
+
+/// What a key pressed means to a keymap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lookup<A> {
+    /// The key completed the sequence of a binding.
+    Found(A),
+    /// The key is a prefix, such as `C-x`, and more keys must follow.
+    Pending,
+    /// The key followed a prefix but completes no binding. The sequence is dropped.
+    Undefined,
+    /// The key isn't bound, and keeps its usual meaning.
+    Unbound,
+}
+
+/// Key bindings made of single keys or of sequences of them, with the
+/// prefix typed so far.
+pub struct Keymap<A, K = Chord> {
+    bindings: Vec<Binding<A, K>>,
+    pending: Vec<K>,
+}
+
+impl<A: Copy, K: Key> Keymap<A, K> {
+    pub fn new(bindings: Vec<Binding<A, K>>) -> Self {
+        Keymap { bindings, pending: Vec::new() }
+    }
+
+    /// Looks up `key` as the next key of the sequence being typed.
+    pub fn press(&mut self, key: K) -> Lookup<A> {
+        self.pending.push(key);
+        if let Some(binding) = self.bindings.iter().find(|binding| binding.keys == self.pending) {
+            self.pending.clear();
+            return Lookup::Found(binding.action);
+        }
+        if self.bindings.iter().any(|binding| binding.keys.starts_with(&self.pending)) {
+            return Lookup::Pending;
+        }
+        let after_prefix = self.pending.len() > 1;
+        self.pending.clear();
+        if after_prefix { Lookup::Undefined } else { Lookup::Unbound }
+    }
+
+    /// Whether a prefix was typed and the keymap waits for the rest of the sequence.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Drops a partly typed sequence.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+    }
+
+    pub fn bindings(&self) -> &[Binding<A, K>] {
+        &self.bindings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use windows::Win32::UI::Input::KeyboardAndMouse::{VK_A, VK_F12, VK_H, VK_U, VK_X, VK_Z};
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Command {
+        LineStart,
+        MarkWholeBuffer,
+        Undo,
+    }
+
+    fn keymap() -> Keymap<Command> {
+        Keymap::new(vec![
+            Binding::new(&[Chord::ctrl(VK_A)], Command::LineStart, "Move to the start of the line"),
+            Binding::new(&[Chord::ctrl(VK_X), Chord::plain(VK_H)], Command::MarkWholeBuffer, "Select the whole document"),
+            Binding::new(&[Chord::ctrl(VK_X), Chord::plain(VK_U)], Command::Undo, "Undo"),
+        ])
+    }
+
+    #[test]
+    fn single_chord_is_found() {
+        let mut keymap = keymap();
+        assert_eq!(keymap.press(Chord::ctrl(VK_A)), Lookup::Found(Command::LineStart));
+        assert!(!keymap.is_pending());
+    }
+
+    #[test]
+    fn prefix_is_pending_until_sequence_completes() {
+        let mut keymap = keymap();
+        assert_eq!(keymap.press(Chord::ctrl(VK_X)), Lookup::Pending);
+        assert!(keymap.is_pending());
+        assert_eq!(keymap.press(Chord::plain(VK_U)), Lookup::Found(Command::Undo));
+        assert!(!keymap.is_pending());
+    }
+
+    #[test]
+    fn unknown_key_after_prefix_is_undefined() {
+        let mut keymap = keymap();
+        keymap.press(Chord::ctrl(VK_X));
+        assert_eq!(keymap.press(Chord::plain(VK_A)), Lookup::Undefined);
+        assert!(!keymap.is_pending());
+    }
+
+    #[test]
+    fn unknown_key_is_unbound() {
+        let mut keymap = keymap();
+        assert_eq!(keymap.press(Chord::plain(VK_A)), Lookup::Unbound);
+        assert_eq!(keymap.press(Chord::plain(VK_H)), Lookup::Unbound);
+        assert!(!keymap.is_pending());
+    }
+
+    #[test]
+    fn sequence_starts_over_after_undefined_key_or_reset() {
+        let mut keymap = keymap();
+        keymap.press(Chord::ctrl(VK_X));
+        keymap.press(Chord::ctrl(VK_A));
+        // C-a after the dropped C-x C-a is a binding of its own again
+        assert_eq!(keymap.press(Chord::ctrl(VK_A)), Lookup::Found(Command::LineStart));
+
+        keymap.press(Chord::ctrl(VK_X));
+        keymap.reset();
+        assert!(!keymap.is_pending());
+        assert_eq!(keymap.press(Chord::plain(VK_H)), Lookup::Unbound);
+        assert_eq!(keymap.press(Chord::ctrl(VK_X)), Lookup::Pending);
+        assert_eq!(keymap.press(Chord::plain(VK_H)), Lookup::Found(Command::MarkWholeBuffer));
+    }
+
+    #[test]
+    fn keys_are_named_in_emacs_notation() {
+        let binding = Binding::new(&[Chord::ctrl(VK_X), Chord::plain(VK_U)], (), "Undo");
+        assert_eq!(binding.keys_name(), "C-x u");
+        assert_eq!(Chord::alt(VK_OEM_COMMA).with_shift().name(), "M-<");
+        assert_eq!(Chord::ctrl(VK_SPACE).name(), "C-SPC");
+    }
+
+    #[test]
+    fn keys_are_named_in_windows_and_vi_notation() {
+        assert_eq!(Chord::ctrl(VK_Z).with_shift().windows_name(), "Ctrl+Shift+Z");
+        assert_eq!(Chord::plain(VK_PRIOR).windows_name(), "Page Up");
+        assert_eq!(Chord::ctrl(VK_F12).windows_name(), "Ctrl+F12");
+        assert_eq!(Binding::new(&['g', 'g'], (), "First line").keys_name(), "gg");
+        assert_eq!(Binding::new(&['\x12'], (), "Redo").keys_name(), "Ctrl+R");
+        assert_eq!(Binding::new(&['\x1b'], (), "Normal mode").keys_name(), "Esc");
+    }
+}
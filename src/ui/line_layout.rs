@@ -0,0 +1,81 @@
+/// Mnemonics for the C0 control characters, indexed by code point.
+const C0_MNEMONICS: [&str; 32] = [
+    "NUL", "SOH", "STX", "ETX", "EOT", "ENQ", "ACK", "BEL",
+    "BS", "HT", "LF", "VT", "FF", "CR", "SO", "SI",
+    "DLE", "DC1", "DC2", "DC3", "DC4", "NAK", "SYN", "ETB",
+    "CAN", "EM", "SUB", "ESC", "FS", "GS", "RS", "US",
+];
+
+/// Returns the mnemonic drawn in place of a non-printable control character,
+/// or None for characters that are drawn as-is. Tabs are left alone.
+pub fn control_mnemonic(ch: char) -> Option<&'static str> {
+    match ch {
+        '\t' => None,
+        '\u{0}'..='\u{1F}' => Some(C0_MNEMONICS[ch as usize]),
+        '\u{7F}' => Some("DEL"),
+        _ => None,
+    }
+}
+
+/// A piece of a line as it is drawn: either plain text or a control character mnemonic.
+pub enum LineRun<'a> {
+    Text(&'a str),
+    Control(&'static str),
+}
+
+/// Splits a line into runs of plain text and control characters.
+///
+/// Layout stops once `max_columns` cells are filled, so only the visible part
+/// of very long lines (e.g. minified files) is ever measured. The last run may
+/// extend slightly past the limit when a mnemonic straddles it.
+pub fn line_runs(line: &str, max_columns: usize) -> Vec<LineRun<'_>> {
+    let mut runs = Vec::new();
+    let mut text_start = 0;
+    let mut column = 0;
+
+    for (i, ch) in line.char_indices() {
+        if column >= max_columns {
+            if text_start < i {
+                runs.push(LineRun::Text(&line[text_start..i]));
+            }
+            return runs;
+        }
+        if let Some(mnemonic) = control_mnemonic(ch) {
+            if text_start < i {
+                runs.push(LineRun::Text(&line[text_start..i]));
+            }
+            runs.push(LineRun::Control(mnemonic));
+            text_start = i + ch.len_utf8();
+            column += mnemonic.len();
+        } else {
+            column += ch.len_utf16();
+        }
+    }
+    if text_start < line.len() {
+        runs.push(LineRun::Text(&line[text_start..]));
+    }
+    runs
+}
+
+/// Converts a screen column into the byte offset of the character drawn at it,
+/// the inverse of `display_column`. Columns past the end of the line map to its end.
+pub fn offset_at_column(line: &str, column: usize) -> usize {
+    let mut current = 0;
+    for (i, ch) in line.char_indices() {
+        if current >= column {
+            return i;
+        }
+        current += control_mnemonic(ch).map_or(ch.len_utf16(), str::len);
+    }
+    line.len()
+}
+
+/// Converts a byte offset within a line into the screen column it is drawn at,
+/// accounting for control characters being drawn as multi-cell mnemonics.
+/// With a fixed-width font every other UTF-16 unit occupies one cell.
+pub fn display_column(line: &str, byte_offset: usize) -> usize {
+    line[..byte_offset]
+        .chars()
+        .map(|ch| control_mnemonic(ch).map_or(ch.len_utf16(), str::len))
+        .sum()
+}
@@ -1,22 +1,60 @@
 use std::{
     ffi::OsString,
     os::windows::ffi::{OsStrExt, OsStringExt},
-    path::PathBuf,
+    path::{Path, PathBuf},
     ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
 };
 
+use crate::document::file_hash::{self, FileHashes};
+use crate::document::file_io::{self, BackgroundRead, LineEnding};
+use crate::document::line_diff;
+use crate::document::local_history;
+use crate::document::templates;
+use crate::ui::control::{
+    EVM_CLEARFILE, EVM_FORMATDOCUMENT, EVM_GETALIGNEDVIEW, EVM_GETENCODINGERRORS, EVM_GETFILEPATH, EVM_GETFREEZEHEADER,
+    EVM_GETAUTOCOPY, EVM_GETKEYBINDINGS, EVM_GETZOOMSYNC, EVM_NEWFROMTEMPLATE, EVM_OPENFILE, EVM_OPENLOADEDFILE, EVM_RESTOREFILE, EVM_SAVEFILE,
+    EVM_SETALIGNEDVIEW, EVM_SETAUTOCOPY, EVM_SETFREEZEHEADER, EVM_SETKEYBINDINGS, EVM_SETSCROLLMARGIN, EVM_SETZOOMSYNC, EVM_SUSPENDTIMERS, EVM_ZOOM, EVN_MODIFIEDCHANGE, SAVE_ENCODING_UTF8,
+    SAVE_ENCODING_UTF8_BOM, SAVE_LINE_ENDINGS_CRLF, SAVE_LINE_ENDINGS_LF,
+};
+use crate::ui::announce;
+use crate::ui::clipboard;
+use crate::ui::drop_target::{self, DroppedItem, VirtualFile};
 use crate::ui::editor_view;
+use crate::ui::keymap::KeyBindings;
+use crate::ui::remote_control::{self, RemoteCommand, RemoteRequest, RemoteResult};
+use crate::ui::settings;
+use crate::ui::shortcuts;
+use crate::ui::update_check::{self, Release};
+use crate::ui::usage_stats;
 
 use windows::{
     core::*,
     Win32::{
         Foundation::*, 
-        Graphics::Gdi::HBRUSH,
-        System::LibraryLoader::GetModuleHandleW,
+        Graphics::Gdi::{
+            GetStockObject, GetSysColor, GetSysColorBrush, InvalidateRect, SetBkColor, SetTextColor, ANSI_FIXED_FONT,
+            COLOR_INFOBK, COLOR_INFOTEXT, DEFAULT_GUI_FONT, HBRUSH, HDC,
+        },
+        System::{
+            Com::{CoCreateInstance, CoTaskMemFree, CLSCTX_INPROC_SERVER},
+            DataExchange::COPYDATASTRUCT,
+            LibraryLoader::GetModuleHandleW,
+            SystemServices::{SS_CENTERIMAGE, SS_NOTIFY},
+            RemoteDesktop::{WTSRegisterSessionNotification, WTSUnRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION},
+            Threading::{CreateMutexW, ReleaseMutex, WaitForSingleObject},
+        },
         UI::{
-            Controls::Dialogs::{
-                GetOpenFileNameW,
-                OFN_FILEMUSTEXIST, OFN_PATHMUSTEXIST, OPENFILENAMEW,
+            Controls::{EM_GETMODIFY, EM_GETSEL, EM_LINEINDEX, EM_REPLACESEL, EM_SETCUEBANNER, EM_SETLIMITTEXT, EM_SETSEL},
+            Input::KeyboardAndMouse::{EnableWindow, SetFocus},
+            Shell::{
+                Common::COMDLG_FILTERSPEC, FileOpenDialog, FileSaveDialog, IFileDialog,
+                IFileDialogCustomize, IFileOpenDialog, IFileSaveDialog, IShellItem,
+                SHCreateItemFromParsingName, ShellExecuteW, FOS_FILEMUSTEXIST, FOS_FORCEFILESYSTEM,
+                FOS_OVERWRITEPROMPT, FOS_PATHMUSTEXIST, SIGDN_FILESYSPATH,
             },
             WindowsAndMessaging::*,
         },
@@ -28,11 +66,119 @@ const APP_TITLE: PCWSTR = w!("Jedit");
 // --- Menu Item IDs --- (typically be defined in a resource file (.rc) and header (.h))
 const IDM_FILE_NEW: u16 = 1001;
 const IDM_FILE_OPEN: u16 = 1002;
+const IDM_FILE_SAVE: u16 = 1003;
+const IDM_FILE_SAVE_AS: u16 = 1004;
+const IDM_FILE_LOCAL_HISTORY: u16 = 1005;
+const IDM_FILE_NEW_FROM_TEMPLATE: u16 = 1006;
+const IDM_TOOLS_FORMAT: u16 = 3001;
+const IDM_TOOLS_MINIFY: u16 = 3002;
+const IDM_TOOLS_FILE_HASHES: u16 = 3003;
+const IDM_TOOLS_USAGE_STATS: u16 = 3004;
+const IDM_VIEW_ALIGN_COLUMNS: u16 = 4001;
+const IDM_VIEW_ZOOM_IN: u16 = 4002;
+const IDM_VIEW_ZOOM_OUT: u16 = 4003;
+const IDM_VIEW_ZOOM_RESET: u16 = 4004;
+const IDM_VIEW_ZOOM_SYNC: u16 = 4005;
+const IDM_VIEW_AUTO_COPY: u16 = 4006;
+const IDM_VIEW_KEYS_STANDARD: u16 = 4007;
+const IDM_VIEW_KEYS_VI: u16 = 4008;
+const IDM_VIEW_KEYS_EMACS: u16 = 4009;
+const IDM_VIEW_FREEZE_HEADER: u16 = 4010;
 const IDM_HELP_ABOUT: u16 = 2001;
+const IDM_HELP_CHECK_FOR_UPDATES: u16 = 2002;
+const IDM_HELP_KEYBOARD_SHORTCUTS: u16 = 2003;
+
+// Controls added to the Save As dialog
+const IDC_SAVE_ENCODING_GROUP: u32 = 1;
+const IDC_SAVE_ENCODING: u32 = 2;
+const IDC_SAVE_LINE_ENDINGS_GROUP: u32 = 3;
+const IDC_SAVE_LINE_ENDINGS: u32 = 4;
+
+// Child windows of the bar shown above the editor when an update is available
+const IDC_UPDATE_BAR: u16 = 101;
+const IDC_UPDATE_BAR_DISMISS: u16 = 102;
+
+// Child windows of the bar shown above the editor while a cloud file downloads
+const IDC_DOWNLOAD_BAR: u16 = 106;
+const IDC_DOWNLOAD_BAR_CANCEL: u16 = 107;
+
+/// Size of the bars above the editor and of their buttons.
+const INFO_BAR_HEIGHT: i32 = 28;
+const INFO_BAR_BUTTON_WIDTH: i32 = 80;
+
+// Child windows of the Keyboard Shortcuts pane, shown beside the editor
+const IDC_SHORTCUTS_SEARCH: u16 = 103;
+const IDC_SHORTCUTS_CLOSE: u16 = 104;
+const IDC_SHORTCUTS_LIST: u16 = 105;
+const SHORTCUTS_PANE_WIDTH: i32 = 360;
+const SHORTCUTS_SEARCH_HEIGHT: i32 = 24;
+const SHORTCUTS_CLOSE_WIDTH: i32 = 60;
+
+// Child windows of the Local History pane, shown beside the editor instead of
+// the Keyboard Shortcuts pane
+const IDC_HISTORY_RESTORE: u16 = 108;
+const IDC_HISTORY_CLOSE: u16 = 109;
+const IDC_HISTORY_LIST: u16 = 110;
+const IDC_HISTORY_PREVIEW: u16 = 111;
+const HISTORY_PANE_WIDTH: i32 = 480;
+const HISTORY_BUTTON_HEIGHT: i32 = 24;
+const HISTORY_BUTTON_WIDTH: i32 = 80;
+const HISTORY_LIST_HEIGHT: i32 = 160;
+
+/// Unchanged lines shown around each change in the Local History preview.
+const HISTORY_PREVIEW_CONTEXT: usize = 3;
+
+// Messages posted to the main window by the file hashing thread
+const WM_APP_HASHPROGRESS: u32 = WM_APP + 1; // wparam: percentage hashed
+const WM_APP_HASHESDONE: u32 = WM_APP + 2; // lparam: Box<HashResult>
+const WM_APP_REMOTECOMMAND: u32 = WM_APP + 3; // lparam: *mut RemoteRequest, sent by the remote control server
+const WM_APP_DROPPED: u32 = WM_APP + 4; // lparam: Box<DroppedItem>, posted by the drop target
+const WM_APP_UPDATEAVAILABLE: u32 = WM_APP + 5; // lparam: Box<Release>, posted by the update check
+const WM_APP_DOWNLOADED: u32 = WM_APP + 6; // wparam: download number, lparam: Box<DownloadResult>
+
+/// WM_COPYDATA dwData asking a running instance to open a file. lpData holds
+/// the absolute path as UTF-16, optionally null-terminated; cbData is its size
+/// in bytes. The window returns the OpenOutcome as 1 + its index, or 0 if it
+/// didn't handle the request.
+pub const COPYDATA_OPEN_FILE: usize = 0x4A45_4F46; // "JEOF"
+
+/// Named mutex held by an instance that is starting up, until its main window exists.
+const STARTUP_MUTEX_NAME: PCWSTR = w!("Local\\JeditStartup");
 
-// Custom application messages for communcation with editor view control
-const EVM_OPENFILE: u32 = WM_USER + 1;
-const EVM_CLEARFILE: u32 = WM_USER + 2;
+/// Longest an instance waits for another one to finish starting up.
+const STARTUP_WAIT_MS: u32 = 10_000;
+
+/// Timer that saves the usage statistics now and then, so a crash loses little.
+const USAGE_STATS_TIMER_ID: usize = 1;
+const USAGE_STATS_SAVE_INTERVAL_MS: u32 = 10 * 60 * 1000;
+
+/// The hashed file and its digests, or the error message.
+type HashResult = (PathBuf, std::result::Result<FileHashes, String>);
+
+/// The downloaded file, its title and its content as file_io::load returns it, or the error message.
+type DownloadResult = (PathBuf, String, std::result::Result<(String, Vec<usize>), String>);
+
+/// Set while a file is being hashed in the background; one at a time.
+static HASHING: AtomicBool = AtomicBool::new(false);
+
+/// Set while the session is locked or the system suspended, when the editor's
+/// background timers stay paused even if the window is restored.
+static SESSION_LOCKED: AtomicBool = AtomicBool::new(false);
+static SYSTEM_SUSPENDED: AtomicBool = AtomicBool::new(false);
+
+/// Set while the background timers are paused.
+static TIMERS_SUSPENDED: AtomicBool = AtomicBool::new(false);
+
+/// The snapshots listed in the Local History pane, in the order of its list.
+static HISTORY_SNAPSHOTS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// The newer release the update bar links to, while it is shown.
+static AVAILABLE_RELEASE: Mutex<Option<Release>> = Mutex::new(None);
+
+/// The cloud file being downloaded and its number; one at a time. Results of
+/// earlier, cancelled downloads carry other numbers and are dropped.
+static DOWNLOAD: Mutex<Option<(usize, BackgroundRead)>> = Mutex::new(None);
+static DOWNLOAD_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 // Helper function to replicate the LOWORD macro
 #[inline]
@@ -41,13 +187,16 @@ fn loword(dword: usize) -> u16 {
 }
 
 /// Sets the title text of the main window.
-/// Prepends the application title to the given file name.
+/// Prepends the application title to the given file name, marked with `*`
+/// while the document has unsaved changes.
 fn set_window_file_name(hwnd: HWND, file_name: PCWSTR) -> Result<()> {
     unsafe {
         let app_title_str = APP_TITLE.to_string().unwrap_or_else(|_| "Jedit".to_string());
         let file_name_str = file_name.to_string().unwrap_or_else(|_| "Untitled".to_string());
+        let hwnd_editor = HWND(GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut _);
+        let modified = !hwnd_editor.0.is_null() && is_editor_modified(hwnd_editor);
 
-        let combined_title = format!("{} - {}", file_name_str, app_title_str);
+        let combined_title = format!("{}{} - {}", if modified { "*" } else { "" }, file_name_str, app_title_str);
 
         // Convert the combined title to a null-terminated wide string (Vec<u16>)
         let title_wide: Vec<u16> = combined_title
@@ -59,52 +208,1156 @@ fn set_window_file_name(hwnd: HWND, file_name: PCWSTR) -> Result<()> {
             return Err(Error::from_win32());
         }
     }
+    // The title changes when another document is shown or a save adds a snapshot
+    fill_history_list(hwnd);
     Ok(())
 }
 
-/// Shows the standard Windows "Open File" common dialog.
+/// Returns the file type filters shown in the Open and Save As dialogs.
+fn file_type_filters() -> [COMDLG_FILTERSPEC; 7] {
+    let filter = |name: PCWSTR, spec: PCWSTR| COMDLG_FILTERSPEC { pszName: name, pszSpec: spec };
+    [
+        filter(w!("Text Files (*.txt)"), w!("*.txt")),
+        filter(w!("JSON Files (*.json)"), w!("*.json")),
+        filter(w!("XML Files (*.xml; *.xsd; *.xsl; *.svg)"), w!("*.xml;*.xsd;*.xsl;*.xslt;*.svg;*.csproj;*.props;*.targets")),
+        filter(w!("Delimited Files (*.csv; *.tsv)"), w!("*.csv;*.tsv;*.tab")),
+        filter(w!("Stylesheets (*.css; *.scss; *.less)"), w!("*.css;*.scss;*.sass;*.less")),
+        filter(w!("Configuration Files (*.ini; *.cfg; *.conf; *.toml; *.yaml)"), w!("*.ini;*.cfg;*.conf;*.config;*.toml;*.yaml;*.yml")),
+        filter(w!("All Files (*.*)"), w!("*.*")),
+    ]
+}
+
+/// Shows a file dialog modally. Returns false if the user cancelled it.
+fn show_file_dialog(dialog: &IFileDialog, hwnd: HWND) -> Result<bool> {
+    match unsafe { dialog.Show(Some(hwnd)) } {
+        Ok(()) => Ok(true),
+        Err(e) if e.code() == HRESULT::from_win32(ERROR_CANCELLED.0) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the chosen path of a file dialog, along with its file name (title).
+/// The shell returns the full path, however long, so nothing is truncated.
+fn file_dialog_result(dialog: &IFileDialog) -> Result<(PathBuf, String)> {
+    unsafe {
+        let item = dialog.GetResult()?;
+        let path_pwstr = item.GetDisplayName(SIGDN_FILESYSPATH)?;
+        let file_path = PathBuf::from(OsString::from_wide(path_pwstr.as_wide()));
+        CoTaskMemFree(Some(path_pwstr.0 as *const _));
+
+        let file_title = file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok((file_path, file_title))
+    }
+}
+
+/// Shows the Windows "Open" dialog (IFileOpenDialog).
+/// `initial_dir` and `title` override the dialog defaults when given.
 /// Returns Option<(PathBuf, String)> containing the full path and the file name (title)
 /// if the user selects a file, otherwise returns None.
-fn show_open_file_dialog(hwnd: HWND) -> Option<(PathBuf, String)> {
+fn show_open_file_dialog(hwnd: HWND, initial_dir: Option<&Path>, title: Option<&str>) -> Option<(PathBuf, String)> {
+    let result = (|| -> Result<Option<(PathBuf, String)>> {
+        unsafe {
+            let dialog: IFileOpenDialog = CoCreateInstance(&FileOpenDialog, None, CLSCTX_INPROC_SERVER)?;
+            dialog.SetFileTypes(&file_type_filters())?;
+            dialog.SetFileTypeIndex(1)?;
+            dialog.SetOptions(dialog.GetOptions()? | FOS_FORCEFILESYSTEM | FOS_PATHMUSTEXIST | FOS_FILEMUSTEXIST)?;
+            if let Some(dir) = initial_dir {
+                // A folder that can't be resolved just leaves the default in place
+                if let Ok(folder) = SHCreateItemFromParsingName::<_, _, IShellItem>(&HSTRING::from(dir.as_os_str()), None) {
+                    dialog.SetFolder(&folder)?;
+                }
+            }
+            if let Some(title) = title {
+                dialog.SetTitle(&HSTRING::from(title))?;
+            }
+
+            if !show_file_dialog(&dialog, hwnd)? {
+                return Ok(None); // User cancelled
+            }
+            file_dialog_result(&dialog).map(Some)
+        }
+    })();
+
+    result.unwrap_or_else(|e| {
+        eprintln!("Open dialog failed: {}", e);
+        None
+    })
+}
+
+/// Shows the Windows "Save As" dialog (IFileSaveDialog), with drop-downs to
+/// change the encoding and line endings the file is saved with.
+/// Returns the chosen full path, file name (title) and SAVE_* flags for
+/// EVM_SAVEFILE, or None if the user cancelled.
+fn show_save_file_dialog(hwnd: HWND) -> Option<(PathBuf, String, usize)> {
+    let result = (|| -> Result<Option<(PathBuf, String, usize)>> {
+        unsafe {
+            let dialog: IFileSaveDialog = CoCreateInstance(&FileSaveDialog, None, CLSCTX_INPROC_SERVER)?;
+            dialog.SetFileTypes(&file_type_filters())?;
+            dialog.SetFileTypeIndex(1)?;
+            // Follows the selected file type, e.g. ".json" for JSON Files
+            dialog.SetDefaultExtension(w!("txt"))?;
+            dialog.SetOptions(dialog.GetOptions()? | FOS_FORCEFILESYSTEM | FOS_PATHMUSTEXIST | FOS_OVERWRITEPROMPT)?;
+
+            // The item ids of the drop-downs are the SAVE_* flags they select
+            let customize: IFileDialogCustomize = dialog.cast()?;
+            customize.StartVisualGroup(IDC_SAVE_ENCODING_GROUP, w!("Encoding:"))?;
+            customize.AddComboBox(IDC_SAVE_ENCODING)?;
+            customize.AddControlItem(IDC_SAVE_ENCODING, 0, w!("Unchanged"))?;
+            customize.AddControlItem(IDC_SAVE_ENCODING, SAVE_ENCODING_UTF8 as u32, w!("UTF-8"))?;
+            customize.AddControlItem(IDC_SAVE_ENCODING, SAVE_ENCODING_UTF8_BOM as u32, w!("UTF-8 with BOM"))?;
+            customize.SetSelectedControlItem(IDC_SAVE_ENCODING, 0)?;
+            customize.EndVisualGroup()?;
+            customize.StartVisualGroup(IDC_SAVE_LINE_ENDINGS_GROUP, w!("Line endings:"))?;
+            customize.AddComboBox(IDC_SAVE_LINE_ENDINGS)?;
+            customize.AddControlItem(IDC_SAVE_LINE_ENDINGS, 0, w!("Unchanged"))?;
+            customize.AddControlItem(IDC_SAVE_LINE_ENDINGS, SAVE_LINE_ENDINGS_CRLF as u32, w!("Windows (CR LF)"))?;
+            customize.AddControlItem(IDC_SAVE_LINE_ENDINGS, SAVE_LINE_ENDINGS_LF as u32, w!("Unix (LF)"))?;
+            customize.SetSelectedControlItem(IDC_SAVE_LINE_ENDINGS, 0)?;
+            customize.EndVisualGroup()?;
+
+            if !show_file_dialog(&dialog, hwnd)? {
+                return Ok(None); // User cancelled
+            }
+            let (file_path, file_title) = file_dialog_result(&dialog)?;
+            let flags = customize.GetSelectedControlItem(IDC_SAVE_ENCODING)?
+                | customize.GetSelectedControlItem(IDC_SAVE_LINE_ENDINGS)?;
+            Ok(Some((file_path, file_title, flags as usize)))
+        }
+    })();
+
+    result.unwrap_or_else(|e| {
+        eprintln!("Save As dialog failed: {}", e);
+        None
+    })
+}
+
+/// Switches the editor between the plain and the aligned column view of delimited files.
+fn toggle_aligned_view(hwnd: HWND, hwnd_editor: HWND) {
+    let aligned = unsafe { SendMessageW(hwnd_editor, EVM_GETALIGNEDVIEW, Some(WPARAM(0)), Some(LPARAM(0))) }.0 != 0;
+    let result = unsafe { SendMessageW(hwnd_editor, EVM_SETALIGNEDVIEW, Some(WPARAM(!aligned as usize)), Some(LPARAM(0))) };
+    if !aligned && result.0 == 0 {
+        unsafe { MessageBoxW(Some(hwnd), w!("Aligned columns are only available for .csv and .tsv files."), APP_TITLE, MB_OK | MB_ICONINFORMATION) };
+    }
+}
+
+/// Pretty-prints or minifies the editor's document and reports failures to the user.
+fn format_document(hwnd: HWND, hwnd_editor: HWND, minify: bool) {
+    let indent = settings::number(settings::FORMAT_INDENT).unwrap_or(0);
+    let result = unsafe { SendMessageW(hwnd_editor, EVM_FORMATDOCUMENT, Some(WPARAM(minify as usize)), Some(LPARAM(indent as isize))) };
+    if result.0 == 0 {
+        unsafe { MessageBoxW(Some(hwnd), w!("The document could not be formatted. Make sure it is valid JSON or XML."), APP_TITLE, MB_OK | MB_ICONERROR) };
+    }
+}
+
+/// Starts computing the hashes of the document's file on a background thread.
+/// The thread reports progress and the result by posting messages to `hwnd`.
+fn start_file_hashes(hwnd: HWND, hwnd_editor: HWND) {
+    let Some(file_path) = get_editor_file_path(hwnd_editor) else {
+        unsafe { MessageBoxW(Some(hwnd), w!("The document has not been saved yet, so there is no file to hash."), APP_TITLE, MB_OK | MB_ICONINFORMATION) };
+        return;
+    };
+    if HASHING.swap(true, Ordering::SeqCst) {
+        unsafe { MessageBoxW(Some(hwnd), w!("File hashes are already being computed."), APP_TITLE, MB_OK | MB_ICONINFORMATION) };
+        return;
+    }
+
+    // HWND isn't Send, so the handle crosses to the thread as an integer
+    let hwnd_raw = hwnd.0 as isize;
+    std::thread::spawn(move || {
+        let hwnd = HWND(hwnd_raw as *mut _);
+        let mut last_percent = None;
+        let hashes = file_hash::hash_file(&file_path, |done, total| {
+            let percent = if total == 0 { 100 } else { done * 100 / total };
+            if last_percent != Some(percent) {
+                last_percent = Some(percent);
+                let _ = unsafe { PostMessageW(Some(hwnd), WM_APP_HASHPROGRESS, WPARAM(percent as usize), LPARAM(0)) };
+            }
+        });
+        let result: Box<HashResult> = Box::new((file_path, hashes.map_err(|e| e.to_string())));
+        let result_ptr = Box::into_raw(result);
+        if unsafe { PostMessageW(Some(hwnd), WM_APP_HASHESDONE, WPARAM(0), LPARAM(result_ptr as isize)) }.is_err() {
+            // The window is gone, so nobody will take ownership of the result
+            drop(unsafe { Box::from_raw(result_ptr) });
+            HASHING.store(false, Ordering::SeqCst);
+        }
+    });
+}
+
+/// Shows the computed hashes and offers to copy them to the clipboard.
+fn show_file_hashes(hwnd: HWND, result: &HashResult) {
+    let (file_path, hashes) = result;
+    let hashes = match hashes {
+        Ok(hashes) => hashes,
+        Err(e) => {
+            let message = format!("The hashes of {} could not be computed:\n{}", file_path.display(), e);
+            let message_wide: Vec<u16> = message.encode_utf16().chain(std::iter::once(0)).collect();
+            unsafe { MessageBoxW(Some(hwnd), PCWSTR(message_wide.as_ptr()), APP_TITLE, MB_OK | MB_ICONERROR) };
+            return;
+        }
+    };
+
+    let hashes_text = format!(
+        "{}\r\nMD5: {}\r\nSHA-1: {}\r\nSHA-256: {}",
+        file_path.display(), hashes.md5, hashes.sha1, hashes.sha256
+    );
+    let message = format!("{}\n\nCopy the hashes to the clipboard?", hashes_text);
+    let message_wide: Vec<u16> = message.encode_utf16().chain(std::iter::once(0)).collect();
+    let answer = unsafe { MessageBoxW(Some(hwnd), PCWSTR(message_wide.as_ptr()), w!("File Hashes"), MB_YESNO | MB_ICONINFORMATION) };
+    if answer == IDYES {
+        if let Err(e) = clipboard::set_text(hwnd, &hashes_text) {
+            eprintln!("Failed to copy the file hashes: {}", e);
+        }
+    }
+}
+
+/// Sets the window title to the editor's file name, e.g. after showing progress
+/// in it or when the document was modified.
+fn update_window_title(hwnd: HWND, hwnd_editor: HWND) {
+    let file_name = editor_file_name(hwnd_editor);
+    let file_name_wide: Vec<u16> = file_name.encode_wide().chain(std::iter::once(0)).collect();
+    if let Err(e) = set_window_file_name(hwnd, PCWSTR(file_name_wide.as_ptr())) {
+        eprintln!("Failed to restore window title: {}", e);
+    }
+}
+
+/// Opens a file in the editor and shows `file_title` in the window title.
+/// Cloud files that aren't stored locally are downloaded in the background
+/// first and opened once that is done. Returns false if the file couldn't be
+/// opened; the editor then keeps its document.
+fn open_document(hwnd: HWND, hwnd_editor: HWND, file_path: &Path, file_title: &str) -> bool {
+    // The file opened now takes the place of one still downloading
+    cancel_download(hwnd);
+    match file_io::is_cloud_placeholder(file_path) {
+        Ok(true) => {
+            start_download(hwnd, file_path, file_title);
+            return true;
+        }
+        Ok(false) => {}
+        Err(e) => {
+            eprintln!("Failed to open {}: {}", file_path.display(), e);
+            return false;
+        }
+    }
+
+    let file_path_wide: Vec<u16> = file_path
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+    let file_ptr = file_path_wide.as_ptr();
+
+    // Send message to editor view to open the file
+    // EVM_OPENFILE returns LRESULT(1) on success, LRESULT(0) on failure
+    let open_result = unsafe { SendMessageW(hwnd_editor, EVM_OPENFILE, Some(WPARAM(0)), Some(LPARAM(file_ptr as isize))) };
+    if open_result != LRESULT(1) {
+        return false;
+    }
+    document_opened(hwnd, file_title);
+    true
+}
+
+/// Counts an opened file and shows `file_title` in the window title.
+fn document_opened(hwnd: HWND, file_title: &str) {
+    usage_stats::record_file_opened();
+
+    // Update the main window title
+    let file_title_pcwstr = OsString::from(file_title)
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect::<Vec<_>>();
+    if let Err(e) = set_window_file_name(hwnd, PCWSTR(file_title_pcwstr.as_ptr())) {
+        eprintln!("Failed to set window title after Open File: {}", e);
+    }
+}
+
+/// Downloads and reads the cloud file at `file_path` on a background thread,
+/// showing a bar with a Cancel button meanwhile. The window stays usable; the
+/// file is opened once WM_APP_DOWNLOADED brings its content.
+fn start_download(hwnd: HWND, file_path: &Path, file_title: &str) {
+    let number = DOWNLOAD_COUNT.fetch_add(1, Ordering::SeqCst) + 1;
+    // HWND isn't Send, so the handle crosses to the thread as an integer
+    let hwnd_raw = hwnd.0 as isize;
+    let downloaded_path = file_path.to_path_buf();
+    let downloaded_title = file_title.to_string();
+    let read = file_io::read_in_background(file_path.to_path_buf(), move |content| {
+        let hwnd = HWND(hwnd_raw as *mut _);
+        let result: Box<DownloadResult> = Box::new((downloaded_path, downloaded_title, content));
+        let result_ptr = Box::into_raw(result);
+        if unsafe { PostMessageW(Some(hwnd), WM_APP_DOWNLOADED, WPARAM(number), LPARAM(result_ptr as isize)) }.is_err() {
+            // The window is gone, so nobody will take ownership of the result
+            drop(unsafe { Box::from_raw(result_ptr) });
+        }
+    });
+    *DOWNLOAD.lock().unwrap() = Some((number, read));
+
+    let message = format!("Downloading {}...", file_title);
+    announce::announce(hwnd, &message, "DownloadStarted");
+    show_info_bar(hwnd, &message, IDC_DOWNLOAD_BAR, IDC_DOWNLOAD_BAR_CANCEL, w!("Cancel"));
+}
+
+/// Gives up on the download in progress, if any, and removes its bar.
+fn cancel_download(hwnd: HWND) {
+    if let Some((_, read)) = DOWNLOAD.lock().unwrap().take() {
+        read.cancel();
+    }
+    hide_info_bar(hwnd, IDC_DOWNLOAD_BAR, IDC_DOWNLOAD_BAR_CANCEL);
+}
+
+/// Opens a downloaded cloud file in the editor, or reports why it couldn't be downloaded.
+fn finish_download(hwnd: HWND, hwnd_editor: HWND, result: DownloadResult) {
+    let (file_path, file_title, content) = result;
+    let mut content = match content {
+        Ok(content) => content,
+        Err(e) => {
+            let message = format!("{} could not be downloaded:\n{}", file_path.display(), e);
+            let message_wide: Vec<u16> = message.encode_utf16().chain(std::iter::once(0)).collect();
+            unsafe { MessageBoxW(Some(hwnd), PCWSTR(message_wide.as_ptr()), APP_TITLE, MB_OK | MB_ICONEXCLAMATION) };
+            return;
+        }
+    };
+    // The document may have been edited while the file downloaded
+    if !confirm_discard_changes(hwnd, hwnd_editor) {
+        return;
+    }
+    let file_path_wide: Vec<u16> = file_path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let content_ptr = &mut content as *mut (String, Vec<usize>);
+    let open_result = unsafe { SendMessageW(hwnd_editor, EVM_OPENLOADEDFILE, Some(WPARAM(content_ptr as usize)), Some(LPARAM(file_path_wide.as_ptr() as isize))) };
+    if open_result != LRESULT(1) {
+        unsafe { MessageBoxW(Some(hwnd), w!("Error opening file."), APP_TITLE, MB_OK | MB_ICONEXCLAMATION) };
+        return;
+    }
+    document_opened(hwnd, &file_title);
+}
+
+/// How a request to open a file ended.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OpenOutcome {
+    Opened,
+    /// The file couldn't be opened; the user was told why.
+    Failed,
+    /// The user chose to keep a document with unsaved changes.
+    Cancelled,
+}
+
+impl OpenOutcome {
+    const ALL: [OpenOutcome; 3] = [OpenOutcome::Opened, OpenOutcome::Failed, OpenOutcome::Cancelled];
+
+    /// Returns the WM_COPYDATA result for a COPYDATA_OPEN_FILE request that ended this way.
+    fn to_copydata_result(self) -> LRESULT {
+        LRESULT(OpenOutcome::ALL.iter().position(|&outcome| outcome == self).map_or(0, |index| index as isize + 1))
+    }
+
+    /// The inverse of `to_copydata_result`; None if the request wasn't handled.
+    fn from_copydata_result(result: LRESULT) -> Option<OpenOutcome> {
+        OpenOutcome::ALL.get(usize::try_from(result.0).ok()?.checked_sub(1)?).copied()
+    }
+}
+
+/// Opens a file in the editor of the main window `hwnd`, e.g. one given on the
+/// command line. Reports failures to the user.
+pub fn open_file(hwnd: HWND, file_path: &Path) -> OpenOutcome {
+    let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+    if !confirm_discard_changes(hwnd, hwnd_editor) {
+        return OpenOutcome::Cancelled;
+    }
+    let file_title = file_path.file_name().map_or_else(|| file_path.to_string_lossy(), |name| name.to_string_lossy());
+    if !open_document(hwnd, hwnd_editor, file_path, &file_title) {
+        unsafe { MessageBoxW(Some(hwnd), w!("Error opening file."), APP_TITLE, MB_OK | MB_ICONEXCLAMATION) };
+        return OpenOutcome::Failed;
+    }
+    OpenOutcome::Opened
+}
+
+/// Held while this instance starts up, so that an instance started at the
+/// same time waits and then finds this one's window. Released when dropped.
+pub struct StartupLock(HANDLE);
+
+impl StartupLock {
+    /// Waits until no other instance is starting up, at most STARTUP_WAIT_MS,
+    /// and takes the lock. Starts up without it if the mutex can't be created.
+    pub fn acquire() -> Option<StartupLock> {
+        let mutex = unsafe { CreateMutexW(None, false, STARTUP_MUTEX_NAME) }
+            .inspect_err(|e| eprintln!("Failed to create the startup mutex: {}", e))
+            .ok()?;
+        // An abandoned mutex, left by an instance that crashed, is taken over too
+        let wait = unsafe { WaitForSingleObject(mutex, STARTUP_WAIT_MS) };
+        if wait != WAIT_OBJECT_0 && wait != WAIT_ABANDONED {
+            let _ = unsafe { CloseHandle(mutex) };
+            return None;
+        }
+        Some(StartupLock(mutex))
+    }
+}
+
+impl Drop for StartupLock {
+    fn drop(&mut self) {
+        let _ = unsafe { ReleaseMutex(self.0) };
+        let _ = unsafe { CloseHandle(self.0) };
+    }
+}
+
+/// Hands a file to an already running instance with WM_COPYDATA and brings
+/// its window to the front. Returns how opening it ended there, or None if
+/// there is no running instance to take it.
+pub fn open_in_running_instance(file_path: &Path) -> Option<OpenOutcome> {
+    let hwnd = unsafe { FindWindowW(APP_TITLE, PCWSTR::null()) }.ok()?;
+    // The running instance has its own working directory
+    let file_path = std::path::absolute(file_path).ok()?;
+    let mut file_path_wide: Vec<u16> = file_path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let copy_data = COPYDATASTRUCT {
+        dwData: COPYDATA_OPEN_FILE,
+        cbData: (file_path_wide.len() * std::mem::size_of::<u16>()) as u32,
+        lpData: file_path_wide.as_mut_ptr() as *mut _,
+    };
+    // Only the foreground process may give the foreground to another window
+    let _ = unsafe { SetForegroundWindow(hwnd) };
+    let result = unsafe { SendMessageW(hwnd, WM_COPYDATA, Some(WPARAM(0)), Some(LPARAM(&copy_data as *const COPYDATASTRUCT as isize))) };
+    OpenOutcome::from_copydata_result(result)
+}
+
+/// Runs a command received by the remote control server.
+fn run_remote_command(hwnd: HWND, hwnd_editor: HWND, command: &RemoteCommand) -> RemoteResult {
+    match command {
+        RemoteCommand::OpenFile(file_path) => {
+            if !confirm_discard_changes(hwnd, hwnd_editor) {
+                return Err("The document has unsaved changes".to_string());
+            }
+            let file_title = file_path.file_name().map_or_else(|| file_path.to_string_lossy(), |name| name.to_string_lossy());
+            if !open_document(hwnd, hwnd_editor, file_path, &file_title) {
+                return Err(format!("Failed to open {}", file_path.display()));
+            }
+            Ok(Vec::new())
+        }
+        RemoteCommand::GotoLine(line) => {
+            // Lines are 1-based, as in compiler messages; the edit control messages count from 0
+            let index = unsafe { SendMessageW(hwnd_editor, EM_LINEINDEX, Some(WPARAM(line - 1)), Some(LPARAM(0))) }.0;
+            if index < 0 {
+                return Err(format!("The document has no line {}", line));
+            }
+            unsafe { SendMessageW(hwnd_editor, EM_SETSEL, Some(WPARAM(index as usize)), Some(LPARAM(index))) };
+            Ok(Vec::new())
+        }
+        RemoteCommand::GetSelection => {
+            let (mut start, mut end) = (0u32, 0u32);
+            unsafe {
+                SendMessageW(
+                    hwnd_editor,
+                    EM_GETSEL,
+                    Some(WPARAM(&mut start as *mut u32 as usize)),
+                    Some(LPARAM(&mut end as *mut u32 as isize)),
+                )
+            };
+            Ok(vec![("start", start as usize), ("end", end as usize)])
+        }
+        RemoteCommand::InsertText(text) => {
+            let text_wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+            unsafe { SendMessageW(hwnd_editor, EM_REPLACESEL, Some(WPARAM(0)), Some(LPARAM(text_wide.as_ptr() as isize))) };
+            Ok(Vec::new())
+        }
+    }
+}
+
+/// Returns the file name of the editor's document, or Untitled.
+fn editor_file_name(hwnd_editor: HWND) -> OsString {
+    get_editor_file_path(hwnd_editor)
+        .and_then(|path| path.file_name().map(|name| name.to_os_string()))
+        .unwrap_or_else(|| OsString::from("Untitled"))
+}
+
+/// Returns whether the editor's document has changes that weren't saved.
+fn is_editor_modified(hwnd_editor: HWND) -> bool {
+    unsafe { SendMessageW(hwnd_editor, EM_GETMODIFY, Some(WPARAM(0)), Some(LPARAM(0))) }.0 != 0
+}
+
+/// Asks whether to save the editor's unsaved changes before its document is
+/// closed or replaced, and saves them if so. Returns false if the user cancelled
+/// or the changes weren't saved after all, so the document must stay open.
+fn confirm_discard_changes(hwnd: HWND, hwnd_editor: HWND) -> bool {
+    if !is_editor_modified(hwnd_editor) {
+        return true;
+    }
+    let message = format!("Do you want to save changes to {}?", editor_file_name(hwnd_editor).to_string_lossy());
+    let message_wide: Vec<u16> = message.encode_utf16().chain(std::iter::once(0)).collect();
+    let answer = unsafe { MessageBoxW(Some(hwnd), PCWSTR(message_wide.as_ptr()), APP_TITLE, MB_YESNOCANCEL | MB_ICONWARNING) };
+    match answer {
+        IDYES => {
+            // Saving fails, or the user cancels the Save As dialog of an untitled document
+            save_document(hwnd, hwnd_editor, false);
+            !is_editor_modified(hwnd_editor)
+        }
+        IDNO => true,
+        _ => false,
+    }
+}
+
+/// Asks the editor view for the path of its document. Returns None for an untitled document.
+fn get_editor_file_path(hwnd_editor: HWND) -> Option<PathBuf> {
+    // Ask for the length first so long paths aren't truncated
+    let path_len = unsafe { SendMessageW(hwnd_editor, EVM_GETFILEPATH, Some(WPARAM(0)), Some(LPARAM(0))) }.0 as usize;
+    if path_len == 0 {
+        return None;
+    }
+    let mut buffer: Vec<u16> = vec![0; path_len + 1];
+    let len = unsafe {
+        SendMessageW(hwnd_editor, EVM_GETFILEPATH, Some(WPARAM(buffer.len())), Some(LPARAM(buffer.as_mut_ptr() as isize)))
+    }.0 as usize;
+    if len == 0 {
+        return None;
+    }
+    Some(PathBuf::from(OsString::from_wide(&buffer[..len])))
+}
+
+/// Saves the editor's document, asking for a file name if it is untitled or `save_as` is set.
+/// Updates the window title on success and reports failures to the user.
+fn save_document(hwnd: HWND, hwnd_editor: HWND, save_as: bool) {
+    // Files loaded with invalid byte sequences would be saved with U+FFFD in their place
+    let encoding_errors = unsafe { SendMessageW(hwnd_editor, EVM_GETENCODINGERRORS, Some(WPARAM(0)), Some(LPARAM(0))) }.0;
+    if encoding_errors > 0 {
+        let warning = format!(
+            "This file contained {} invalid byte sequence(s) that are shown as replacement characters (U+FFFD).\n\n\
+             Saving will write the replacement characters and the original bytes will be lost. Save anyway?",
+            encoding_errors
+        );
+        let warning_wide: Vec<u16> = warning.encode_utf16().chain(std::iter::once(0)).collect();
+        let answer = unsafe { MessageBoxW(Some(hwnd), PCWSTR(warning_wide.as_ptr()), APP_TITLE, MB_YESNO | MB_ICONWARNING | MB_DEFBUTTON2) };
+        if answer != IDYES {
+            return;
+        }
+    }
+
+    let target = if save_as || get_editor_file_path(hwnd_editor).is_none() {
+        match show_save_file_dialog(hwnd) {
+            Some(selection) => Some(selection),
+            None => return, // User cancelled
+        }
+    } else {
+        None
+    };
+
+    let file_path_wide: Option<Vec<u16>> = target
+        .as_ref()
+        .map(|(file_path, _, _)| file_path.as_os_str().encode_wide().chain(std::iter::once(0)).collect());
+    let file_ptr = file_path_wide.as_ref().map_or(ptr::null(), |path| path.as_ptr());
+    let save_flags = target.as_ref().map_or(0, |(_, _, flags)| *flags);
+
+    // EVM_SAVEFILE returns LRESULT(1) on success, LRESULT(0) on failure
+    let save_result = unsafe { SendMessageW(hwnd_editor, EVM_SAVEFILE, Some(WPARAM(save_flags)), Some(LPARAM(file_ptr as isize))) };
+    if save_result != LRESULT(1) {
+        unsafe { MessageBoxW(Some(hwnd), w!("Error saving file."), APP_TITLE, MB_OK | MB_ICONEXCLAMATION) };
+        return;
+    }
+
+    if let Some(file_path) = get_editor_file_path(hwnd_editor) {
+        remote_control::notify_saved(&file_path);
+        let file_name = file_path.file_name().map_or_else(|| file_path.to_string_lossy(), |name| name.to_string_lossy());
+        announce::announce(hwnd, &format!("Saved {}", file_name), "FileSaved");
+    }
+
+    if let Some((_, file_title, _)) = target {
+        let file_title_pcwstr = OsString::from(file_title)
+            .encode_wide()
+            .chain(std::iter::once(0))
+            .collect::<Vec<_>>();
+        if let Err(e) = set_window_file_name(hwnd, PCWSTR(file_title_pcwstr.as_ptr())) {
+            eprintln!("Failed to set window title after Save As: {}", e);
+        }
+    }
+}
+
+/// Returns the whole text of the editor's document.
+fn editor_text(hwnd_editor: HWND) -> String {
+    let length = unsafe { SendMessageW(hwnd_editor, WM_GETTEXTLENGTH, Some(WPARAM(0)), Some(LPARAM(0))) }.0 as usize;
+    let mut buffer: Vec<u16> = vec![0; length + 1];
+    let copied = unsafe {
+        SendMessageW(hwnd_editor, WM_GETTEXT, Some(WPARAM(buffer.len())), Some(LPARAM(buffer.as_mut_ptr() as isize)))
+    }.0 as usize;
+    String::from_utf16_lossy(&buffer[..copied])
+}
+
+/// Shows the Local History pane beside the editor, listing the snapshots of
+/// the current file, or closes it if it is shown.
+fn toggle_history_pane(hwnd: HWND, hwnd_editor: HWND) {
+    if unsafe { GetDlgItem(Some(hwnd), IDC_HISTORY_LIST as i32) }.is_ok() {
+        hide_history_pane(hwnd);
+        return;
+    }
+    let Some(file_path) = get_editor_file_path(hwnd_editor) else {
+        unsafe { MessageBoxW(Some(hwnd), w!("The document has not been saved yet, so it has no local history."), APP_TITLE, MB_OK | MB_ICONINFORMATION) };
+        return;
+    };
+    if local_history::list_snapshots(&file_path).is_empty() {
+        unsafe { MessageBoxW(Some(hwnd), w!("No local history snapshots exist for this file."), APP_TITLE, MB_OK | MB_ICONINFORMATION) };
+        return;
+    }
+
+    // The panes share the space beside the editor
+    hide_shortcuts_pane(hwnd);
+    let hinstance = unsafe { GetModuleHandleW(None) }.ok().map(|hinstance| hinstance.into());
+    let font = unsafe { GetStockObject(DEFAULT_GUI_FONT) };
+    let preview_font = unsafe { GetStockObject(ANSI_FIXED_FONT) };
+    let preview_style = ES_MULTILINE | ES_READONLY | ES_AUTOVSCROLL | ES_AUTOHSCROLL;
+    let children = [
+        (WINDOW_EX_STYLE::default(), w!("BUTTON"), IDC_HISTORY_RESTORE, w!("Restore"), WINDOW_STYLE(BS_PUSHBUTTON as u32)),
+        (WINDOW_EX_STYLE::default(), w!("BUTTON"), IDC_HISTORY_CLOSE, w!("Close"), WINDOW_STYLE(BS_PUSHBUTTON as u32)),
+        (WS_EX_CLIENTEDGE, w!("LISTBOX"), IDC_HISTORY_LIST, w!(""), WINDOW_STYLE((LBS_NOINTEGRALHEIGHT | LBS_NOTIFY) as u32) | WS_VSCROLL),
+        (WS_EX_CLIENTEDGE, w!("EDIT"), IDC_HISTORY_PREVIEW, w!(""), WINDOW_STYLE(preview_style as u32) | WS_VSCROLL | WS_HSCROLL),
+    ];
+    for (ex_style, class, id, text, style) in children {
+        match unsafe { CreateWindowExW(ex_style, class, text, WS_CHILD | WS_VISIBLE | style, 0, 0, 0, 0, Some(hwnd), Some(HMENU(id as usize as *mut _)), hinstance, None) } {
+            Ok(hwnd_child) => unsafe {
+                let child_font = if id == IDC_HISTORY_PREVIEW { preview_font } else { font };
+                SendMessageW(hwnd_child, WM_SETFONT, Some(WPARAM(child_font.0 as usize)), Some(LPARAM(1)));
+            },
+            Err(e) => {
+                eprintln!("Failed to create the Local History pane: {}", e);
+                hide_history_pane(hwnd);
+                return;
+            }
+        }
+    }
+    if let Ok(hwnd_preview) = unsafe { GetDlgItem(Some(hwnd), IDC_HISTORY_PREVIEW as i32) } {
+        // Lift the edit control's default limit of 32K characters
+        unsafe { SendMessageW(hwnd_preview, EM_SETLIMITTEXT, Some(WPARAM(0)), Some(LPARAM(0))) };
+    }
+    fill_history_list(hwnd);
+    if let Ok(hwnd_list) = unsafe { GetDlgItem(Some(hwnd), IDC_HISTORY_LIST as i32) } {
+        let _ = unsafe { SetFocus(Some(hwnd_list)) };
+    }
+    layout_children(hwnd);
+}
+
+/// Removes the Local History pane and gives the keyboard back to the editor.
+fn hide_history_pane(hwnd: HWND) {
+    if unsafe { GetDlgItem(Some(hwnd), IDC_HISTORY_LIST as i32) }.is_err() {
+        return;
+    }
+    for id in [IDC_HISTORY_RESTORE, IDC_HISTORY_CLOSE, IDC_HISTORY_LIST, IDC_HISTORY_PREVIEW] {
+        if let Ok(hwnd_child) = unsafe { GetDlgItem(Some(hwnd), id as i32) } {
+            let _ = unsafe { DestroyWindow(hwnd_child) };
+        }
+    }
+    HISTORY_SNAPSHOTS.lock().unwrap().clear();
+    let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+    let _ = unsafe { SetFocus(Some(hwnd_editor)) };
+    layout_children(hwnd);
+}
+
+/// Returns the snapshot selected in the Local History pane.
+fn selected_snapshot(hwnd: HWND) -> Option<PathBuf> {
+    let hwnd_list = unsafe { GetDlgItem(Some(hwnd), IDC_HISTORY_LIST as i32) }.ok()?;
+    let index = unsafe { SendMessageW(hwnd_list, LB_GETCURSEL, Some(WPARAM(0)), Some(LPARAM(0))) }.0;
+    usize::try_from(index).ok().and_then(|index| HISTORY_SNAPSHOTS.lock().unwrap().get(index).cloned())
+}
+
+/// Lists the snapshots of the editor's document in the Local History pane,
+/// newest first, keeping the selected one selected, and previews it.
+fn fill_history_list(hwnd: HWND) {
+    let Ok(hwnd_list) = (unsafe { GetDlgItem(Some(hwnd), IDC_HISTORY_LIST as i32) }) else {
+        return;
+    };
+    let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+    let selected = selected_snapshot(hwnd);
+    let snapshots = get_editor_file_path(hwnd_editor)
+        .map(|file_path| local_history::list_snapshots(&file_path))
+        .unwrap_or_default();
+
+    let mut labels: Vec<String> = snapshots.iter().map(|snapshot| local_history::snapshot_label(snapshot)).collect();
+    if labels.is_empty() {
+        labels.push("No snapshots exist for this document.".to_string());
+    }
+    let index = selected.and_then(|selected| snapshots.iter().position(|snapshot| *snapshot == selected)).unwrap_or(0);
+    let has_snapshots = !snapshots.is_empty();
+    *HISTORY_SNAPSHOTS.lock().unwrap() = snapshots;
+
     unsafe {
-        let mut file_buffer: [u16; 260] = [0; 260];
-        let mut title_buffer: [u16; 260] = [0; 260];
+        SendMessageW(hwnd_list, WM_SETREDRAW, Some(WPARAM(0)), Some(LPARAM(0)));
+        SendMessageW(hwnd_list, LB_RESETCONTENT, Some(WPARAM(0)), Some(LPARAM(0)));
+        for label in labels {
+            let label_wide: Vec<u16> = label.encode_utf16().chain(std::iter::once(0)).collect();
+            SendMessageW(hwnd_list, LB_ADDSTRING, Some(WPARAM(0)), Some(LPARAM(label_wide.as_ptr() as isize)));
+        }
+        if has_snapshots {
+            SendMessageW(hwnd_list, LB_SETCURSEL, Some(WPARAM(index)), Some(LPARAM(0)));
+        }
+        SendMessageW(hwnd_list, WM_SETREDRAW, Some(WPARAM(1)), Some(LPARAM(0)));
+        let _ = InvalidateRect(Some(hwnd_list), None, true);
+    }
+    if let Ok(hwnd_restore) = unsafe { GetDlgItem(Some(hwnd), IDC_HISTORY_RESTORE as i32) } {
+        let _ = unsafe { EnableWindow(hwnd_restore, has_snapshots) };
+    }
+    show_snapshot_diff(hwnd);
+}
 
-        // Define the filter string (null-terminated pairs, double-null terminated at the end)
-        let filter: Vec<u16> = "Text Files (*.txt)\0*.txt\0All Files (*.*)\0*.*\0\0"
-            .encode_utf16()
+/// Shows in the Local History pane how restoring the selected snapshot would
+/// change the document.
+fn show_snapshot_diff(hwnd: HWND) {
+    let Ok(hwnd_preview) = (unsafe { GetDlgItem(Some(hwnd), IDC_HISTORY_PREVIEW as i32) }) else {
+        return;
+    };
+    let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+    let text = match selected_snapshot(hwnd) {
+        None => String::new(),
+        Some(snapshot_path) => match file_io::load(&snapshot_path) {
+            Ok((snapshot, _)) => {
+                let document = editor_text(hwnd_editor);
+                let preview = line_diff::preview(&line_diff::diff_lines(&document, &snapshot), HISTORY_PREVIEW_CONTEXT);
+                if preview.is_empty() {
+                    "The snapshot has the same lines as the document.".to_string()
+                } else {
+                    preview
+                }
+            }
+            Err(e) => format!("Failed to read the snapshot: {}", e),
+        },
+    };
+    // Edit controls only break lines at CRLF
+    let text = file_io::convert_line_endings(&text, LineEnding::CrLf);
+    let text_wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe { SendMessageW(hwnd_preview, WM_SETTEXT, Some(WPARAM(0)), Some(LPARAM(text_wide.as_ptr() as isize))) };
+}
+
+/// Replaces the editor's document with the snapshot selected in the Local
+/// History pane, then closes the pane. The document keeps its file path.
+fn restore_selected_snapshot(hwnd: HWND, hwnd_editor: HWND) {
+    let Some(snapshot_path) = selected_snapshot(hwnd) else {
+        return;
+    };
+    if !confirm_discard_changes(hwnd, hwnd_editor) {
+        return;
+    }
+    let snapshot_wide: Vec<u16> = snapshot_path
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+    let restore_result = unsafe { SendMessageW(hwnd_editor, EVM_RESTOREFILE, Some(WPARAM(0)), Some(LPARAM(snapshot_wide.as_ptr() as isize))) };
+    if restore_result != LRESULT(1) {
+        unsafe { MessageBoxW(Some(hwnd), w!("Error restoring snapshot."), APP_TITLE, MB_OK | MB_ICONEXCLAMATION) };
+        return;
+    }
+    hide_history_pane(hwnd);
+}
+
+/// Opens the content of a dropped virtual file, which has no path on disk, as
+/// a new untitled document.
+fn open_dropped_content(hwnd: HWND, hwnd_editor: HWND, file: VirtualFile) {
+    let name = file.name.clone();
+    let show_error = |message: String| {
+        let message_wide: Vec<u16> = message.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe { MessageBoxW(Some(hwnd), PCWSTR(message_wide.as_ptr()), APP_TITLE, MB_OK | MB_ICONEXCLAMATION) };
+    };
+    let mut content = match file.read() {
+        Ok(bytes) => file_io::decode(&bytes),
+        Err(e) => return show_error(format!("{} could not be read:\n{}", name, e)),
+    };
+    if content.0.contains('\0') {
+        return show_error(format!("{} is not a text file.", name));
+    }
+    let content_ptr = &mut content as *mut (String, Vec<usize>);
+    let open_result = unsafe { SendMessageW(hwnd_editor, EVM_OPENLOADEDFILE, Some(WPARAM(content_ptr as usize)), Some(LPARAM(0))) };
+    if open_result != LRESULT(1) {
+        return show_error(format!("{} could not be opened.", name));
+    }
+    if let Err(e) = set_window_file_name(hwnd, w!("Untitled")) {
+        eprintln!("Failed to set window title for dropped file {}: {}", name, e);
+    }
+}
+
+/// Lets the user pick a file from the templates directory and starts a new
+/// untitled document from it.
+fn new_from_template(hwnd: HWND, hwnd_editor: HWND) {
+    let Some((templates_dir, has_templates)) = templates::ensure_templates_dir() else {
+        unsafe { MessageBoxW(Some(hwnd), w!("The templates directory is unavailable."), APP_TITLE, MB_OK | MB_ICONEXCLAMATION) };
+        return;
+    };
+    if !has_templates {
+        let message = format!(
+            "No templates found.\n\nSave files to {} to use them as templates. \
+             They may contain {{{{date}}}}, {{{{time}}}}, {{{{author}}}} and {{{{filename}}}} placeholders.",
+            templates_dir.display()
+        );
+        let message_wide: Vec<u16> = message.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe { MessageBoxW(Some(hwnd), PCWSTR(message_wide.as_ptr()), APP_TITLE, MB_OK | MB_ICONINFORMATION) };
+        return;
+    }
+
+    if !confirm_discard_changes(hwnd, hwnd_editor) {
+        return;
+    }
+    if let Some((template_path, _)) = show_open_file_dialog(hwnd, Some(&templates_dir), Some("New From Template")) {
+        let template_wide: Vec<u16> = template_path
+            .as_os_str()
+            .encode_wide()
+            .chain(std::iter::once(0))
             .collect();
+        let result = unsafe { SendMessageW(hwnd_editor, EVM_NEWFROMTEMPLATE, Some(WPARAM(0)), Some(LPARAM(template_wide.as_ptr() as isize))) };
+        if result != LRESULT(1) {
+            unsafe { MessageBoxW(Some(hwnd), w!("Error reading template."), APP_TITLE, MB_OK | MB_ICONEXCLAMATION) };
+            return;
+        }
+        if let Err(e) = set_window_file_name(hwnd, w!("Untitled")) {
+            eprintln!("Failed to set window title for New From Template: {}", e);
+        }
+    }
+}
 
-        // Initialize the OPENFILENAMEW structure.
-        let mut ofn = OPENFILENAMEW {
-            lStructSize: std::mem::size_of::<OPENFILENAMEW>() as u32,
-            hwndOwner: hwnd,
-            lpstrFile: PWSTR(file_buffer.as_mut_ptr()),
-            nMaxFile: file_buffer.len() as u32,
-            lpstrFileTitle: PWSTR(title_buffer.as_mut_ptr()),
-            nMaxFileTitle: title_buffer.len() as u32,
-            lpstrFilter: PCWSTR(filter.as_ptr()),
-            nFilterIndex: 1,
-            lpstrInitialDir: PCWSTR::null(),
-            Flags: OFN_PATHMUSTEXIST | OFN_FILEMUSTEXIST,
-            ..Default::default()
-        };
+/// Pauses the editor's background timers, the saving of the usage statistics
+/// and the count of time spent in the editor while jedit is inactive
+/// (minimized, session locked, system suspended), and resumes them once it is
+/// visible again.
+fn update_background_timers(hwnd: HWND, hwnd_editor: HWND) {
+    if hwnd_editor.0.is_null() {
+        return;
+    }
+    // Only resume when none of the reasons applies any more, e.g. not when a
+    // window is restored while the session is still locked
+    let suspend = unsafe { IsIconic(hwnd) }.as_bool()
+        || SESSION_LOCKED.load(Ordering::SeqCst)
+        || SYSTEM_SUSPENDED.load(Ordering::SeqCst);
+    unsafe { SendMessageW(hwnd_editor, EVM_SUSPENDTIMERS, Some(WPARAM(suspend as usize)), Some(LPARAM(0))) };
+    if TIMERS_SUSPENDED.swap(suspend, Ordering::SeqCst) == suspend {
+        return;
+    }
+    // Time away from the editor isn't time spent in it, and nothing new is
+    // counted that would need saving
+    if suspend {
+        let _ = unsafe { KillTimer(Some(hwnd), USAGE_STATS_TIMER_ID) };
+        usage_stats::pause_session();
+    } else {
+        unsafe { SetTimer(Some(hwnd), USAGE_STATS_TIMER_ID, USAGE_STATS_SAVE_INTERVAL_MS, None) };
+        usage_stats::start_session();
+    }
+}
+
+/// Turns keeping the header row of aligned columns in view on or off, and
+/// remembers the choice.
+fn toggle_freeze_header(hwnd_editor: HWND) {
+    let freeze = unsafe { SendMessageW(hwnd_editor, EVM_GETFREEZEHEADER, Some(WPARAM(0)), Some(LPARAM(0))) }.0 == 0;
+    unsafe { SendMessageW(hwnd_editor, EVM_SETFREEZEHEADER, Some(WPARAM(freeze as usize)), Some(LPARAM(0))) };
+    if let Err(e) = settings::set_flag(settings::FREEZE_HEADER_ROW, freeze) {
+        eprintln!("Failed to save the freeze header row setting: {}", e);
+    }
+}
+
+/// Turns copying selections as they are made on or off, and remembers the choice.
+fn toggle_auto_copy(hwnd_editor: HWND) {
+    let enable = unsafe { SendMessageW(hwnd_editor, EVM_GETAUTOCOPY, Some(WPARAM(0)), Some(LPARAM(0))) }.0 == 0;
+    unsafe { SendMessageW(hwnd_editor, EVM_SETAUTOCOPY, Some(WPARAM(enable as usize)), Some(LPARAM(0))) };
+    if let Err(e) = settings::set_flag(settings::AUTO_COPY_SELECTION, enable) {
+        eprintln!("Failed to save the copy on select setting: {}", e);
+    }
+}
+
+/// Switches the editor to another key binding scheme, and remembers the choice.
+fn set_key_bindings(hwnd: HWND, hwnd_editor: HWND, key_bindings: KeyBindings) {
+    unsafe { SendMessageW(hwnd_editor, EVM_SETKEYBINDINGS, Some(WPARAM(key_bindings.index())), Some(LPARAM(0))) };
+    fill_shortcuts_list(hwnd);
+    if let Err(e) = settings::set_number(settings::KEY_BINDINGS, key_bindings.index() as u32) {
+        eprintln!("Failed to save the key bindings setting: {}", e);
+    }
+}
+
+/// Turns automatic update checks on or off, checking right away when turned on.
+fn toggle_update_checks(hwnd: HWND) {
+    let enable = !update_check::enabled();
+    if let Err(e) = update_check::set_enabled(enable) {
+        eprintln!("Failed to save the update check setting: {}", e);
+        return;
+    }
+    if enable {
+        update_check::start(hwnd, WM_APP_UPDATEAVAILABLE);
+    }
+}
+
+/// Shows a bar above the editor saying that `release` is available. Clicking
+/// it opens the download page; it never blocks editing.
+fn show_update_bar(hwnd: HWND, release: Release) {
+    let message = format!(
+        "Jedit {} is available. Click here to open the download page.",
+        release.version
+    );
+    *AVAILABLE_RELEASE.lock().unwrap() = Some(release);
+    announce::announce(hwnd, &message, "UpdateAvailable");
+    show_info_bar(hwnd, &message, IDC_UPDATE_BAR, IDC_UPDATE_BAR_DISMISS, w!("Dismiss"));
+}
 
-        if GetOpenFileNameW(&mut ofn) == TRUE { 
-            // Find the actual length of the returned path string
-            let path_len = file_buffer.iter().position(|&c| c == 0).unwrap_or(file_buffer.len());
-            let file_path = PathBuf::from(OsString::from_wide(&file_buffer[..path_len]));
+/// Removes the update bar, e.g. once it was clicked or dismissed.
+fn hide_update_bar(hwnd: HWND) {
+    hide_info_bar(hwnd, IDC_UPDATE_BAR, IDC_UPDATE_BAR_DISMISS);
+    *AVAILABLE_RELEASE.lock().unwrap() = None;
+}
 
-            // Find the actual length of the returned title string
-            let title_len = title_buffer.iter().position(|&c| c == 0).unwrap_or(title_buffer.len());
-            let file_title = String::from_utf16_lossy(&title_buffer[..title_len]);
+/// Shows a bar with `message` and a button above the editor, or updates the
+/// message if the bar is already shown.
+fn show_info_bar(hwnd: HWND, message: &str, bar_id: u16, button_id: u16, button_text: PCWSTR) {
+    let message_wide: Vec<u16> = message.encode_utf16().chain(std::iter::once(0)).collect();
+    if let Ok(hwnd_bar) = unsafe { GetDlgItem(Some(hwnd), bar_id as i32) } {
+        let _ = unsafe { SetWindowTextW(hwnd_bar, PCWSTR(message_wide.as_ptr())) };
+        return;
+    }
+    let hinstance = unsafe { GetModuleHandleW(None) }.ok().map(|hinstance| hinstance.into());
+    let font = unsafe { GetStockObject(DEFAULT_GUI_FONT) };
+    let children = [
+        (w!("STATIC"), PCWSTR(message_wide.as_ptr()), bar_id, WINDOW_STYLE(SS_NOTIFY.0 | SS_CENTERIMAGE.0)),
+        (w!("BUTTON"), button_text, button_id, WINDOW_STYLE(BS_PUSHBUTTON as u32)),
+    ];
+    for (class, text, id, style) in children {
+        match unsafe { CreateWindowExW(WINDOW_EX_STYLE::default(), class, text, WS_CHILD | WS_VISIBLE | style, 0, 0, 0, 0, Some(hwnd), Some(HMENU(id as usize as *mut _)), hinstance, None) } {
+            Ok(hwnd_child) => unsafe {
+                SendMessageW(hwnd_child, WM_SETFONT, Some(WPARAM(font.0 as usize)), Some(LPARAM(1)));
+            },
+            Err(e) => eprintln!("Failed to create an info bar: {}", e),
+        }
+    }
+    layout_children(hwnd);
+}
 
-            Some((file_path, file_title))
-        } else {
-            // User cancelled or an error occurred. Check CommDlgExtendedError for details if needed.
-            None
+/// Removes a bar shown by show_info_bar.
+fn hide_info_bar(hwnd: HWND, bar_id: u16, button_id: u16) {
+    for id in [bar_id, button_id] {
+        if let Ok(hwnd_child) = unsafe { GetDlgItem(Some(hwnd), id as i32) } {
+            let _ = unsafe { DestroyWindow(hwnd_child) };
         }
     }
+    layout_children(hwnd);
+}
+
+/// Opens the download page of the available release in the default browser.
+fn open_release_page(hwnd: HWND) {
+    let Some(url) = AVAILABLE_RELEASE.lock().unwrap().as_ref().map(|release| release.url.clone()) else {
+        return;
+    };
+    let url_wide: Vec<u16> = url.encode_utf16().chain(std::iter::once(0)).collect();
+    // ShellExecuteW returns a value greater than 32 on success
+    let result = unsafe { ShellExecuteW(Some(hwnd), w!("open"), PCWSTR(url_wide.as_ptr()), PCWSTR::null(), PCWSTR::null(), SW_SHOWNORMAL) };
+    if result.0 as isize <= 32 {
+        eprintln!("Failed to open {}", url);
+        return;
+    }
+    hide_update_bar(hwnd);
+}
+
+/// Shows the Keyboard Shortcuts pane beside the editor, or closes it if it is shown.
+fn toggle_shortcuts_pane(hwnd: HWND) {
+    if unsafe { GetDlgItem(Some(hwnd), IDC_SHORTCUTS_LIST as i32) }.is_ok() {
+        hide_shortcuts_pane(hwnd);
+        return;
+    }
+    hide_history_pane(hwnd);
+    let hinstance = unsafe { GetModuleHandleW(None) }.ok().map(|hinstance| hinstance.into());
+    let font = unsafe { GetStockObject(DEFAULT_GUI_FONT) };
+    let children = [
+        (WS_EX_CLIENTEDGE, w!("EDIT"), IDC_SHORTCUTS_SEARCH, WINDOW_STYLE(ES_AUTOHSCROLL as u32)),
+        (WINDOW_EX_STYLE::default(), w!("BUTTON"), IDC_SHORTCUTS_CLOSE, WINDOW_STYLE(BS_PUSHBUTTON as u32)),
+        (WS_EX_CLIENTEDGE, w!("LISTBOX"), IDC_SHORTCUTS_LIST, WINDOW_STYLE((LBS_NOINTEGRALHEIGHT | LBS_USETABSTOPS) as u32) | WS_VSCROLL),
+    ];
+    for (ex_style, class, id, style) in children {
+        let text = if id == IDC_SHORTCUTS_CLOSE { w!("Close") } else { w!("") };
+        match unsafe { CreateWindowExW(ex_style, class, text, WS_CHILD | WS_VISIBLE | style, 0, 0, 0, 0, Some(hwnd), Some(HMENU(id as usize as *mut _)), hinstance, None) } {
+            Ok(hwnd_child) => unsafe {
+                SendMessageW(hwnd_child, WM_SETFONT, Some(WPARAM(font.0 as usize)), Some(LPARAM(1)));
+            },
+            Err(e) => {
+                eprintln!("Failed to create the Keyboard Shortcuts pane: {}", e);
+                hide_shortcuts_pane(hwnd);
+                return;
+            }
+        }
+    }
+    if let Ok(hwnd_search) = unsafe { GetDlgItem(Some(hwnd), IDC_SHORTCUTS_SEARCH as i32) } {
+        unsafe { SendMessageW(hwnd_search, EM_SETCUEBANNER, Some(WPARAM(1)), Some(LPARAM(w!("Search shortcuts").as_ptr() as isize))) };
+        let _ = unsafe { SetFocus(Some(hwnd_search)) };
+    }
+    if let Ok(hwnd_list) = unsafe { GetDlgItem(Some(hwnd), IDC_SHORTCUTS_LIST as i32) } {
+        // Tab stop in dialog units between the keys and the description
+        let tab_stops = [100i32];
+        unsafe { SendMessageW(hwnd_list, LB_SETTABSTOPS, Some(WPARAM(tab_stops.len())), Some(LPARAM(tab_stops.as_ptr() as isize))) };
+    }
+    fill_shortcuts_list(hwnd);
+    layout_children(hwnd);
+}
+
+/// Removes the Keyboard Shortcuts pane and gives the keyboard back to the editor.
+fn hide_shortcuts_pane(hwnd: HWND) {
+    for id in [IDC_SHORTCUTS_SEARCH, IDC_SHORTCUTS_CLOSE, IDC_SHORTCUTS_LIST] {
+        if let Ok(hwnd_child) = unsafe { GetDlgItem(Some(hwnd), id as i32) } {
+            let _ = unsafe { DestroyWindow(hwnd_child) };
+        }
+    }
+    let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+    let _ = unsafe { SetFocus(Some(hwnd_editor)) };
+    layout_children(hwnd);
+}
+
+/// Lists the shortcuts of the editor's key binding scheme that match the search
+/// text in the Keyboard Shortcuts pane, under a heading per category.
+fn fill_shortcuts_list(hwnd: HWND) {
+    let Ok(hwnd_list) = (unsafe { GetDlgItem(Some(hwnd), IDC_SHORTCUTS_LIST as i32) }) else {
+        return;
+    };
+    let mut query = String::new();
+    if let Ok(hwnd_search) = unsafe { GetDlgItem(Some(hwnd), IDC_SHORTCUTS_SEARCH as i32) } {
+        let mut buffer = [0u16; 256];
+        let len = unsafe { GetWindowTextW(hwnd_search, &mut buffer) } as usize;
+        query = String::from_utf16_lossy(&buffer[..len]);
+    }
+    let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+    let key_bindings = unsafe { SendMessageW(hwnd_editor, EVM_GETKEYBINDINGS, Some(WPARAM(0)), Some(LPARAM(0))) }.0 as usize;
+
+    let mut lines = Vec::new();
+    let mut category = "";
+    for shortcut in shortcuts::shortcuts(KeyBindings::from_index(key_bindings)) {
+        if !shortcut.matches(&query) {
+            continue;
+        }
+        if shortcut.category != category {
+            category = shortcut.category;
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines.push(category.to_string());
+        }
+        lines.push(format!("    {}\t{}", shortcut.keys, shortcut.description));
+    }
+    if lines.is_empty() {
+        lines.push("No shortcuts match the search.".to_string());
+    }
+
+    unsafe {
+        SendMessageW(hwnd_list, WM_SETREDRAW, Some(WPARAM(0)), Some(LPARAM(0)));
+        SendMessageW(hwnd_list, LB_RESETCONTENT, Some(WPARAM(0)), Some(LPARAM(0)));
+        for line in lines {
+            let line_wide: Vec<u16> = line.encode_utf16().chain(std::iter::once(0)).collect();
+            SendMessageW(hwnd_list, LB_ADDSTRING, Some(WPARAM(0)), Some(LPARAM(line_wide.as_ptr() as isize)));
+        }
+        SendMessageW(hwnd_list, WM_SETREDRAW, Some(WPARAM(1)), Some(LPARAM(0)));
+        let _ = InvalidateRect(Some(hwnd_list), None, true);
+    }
+}
+
+/// Fills the client area with the editor view, below the update bar if it is
+/// shown and left of the Keyboard Shortcuts or Local History pane if one is.
+fn layout_children(hwnd: HWND) {
+    let mut rect = RECT::default();
+    let _ = unsafe { GetClientRect(hwnd, &mut rect) };
+    let width = rect.right - rect.left;
+    let mut top = 0;
+    // The info bars that are shown are stacked above the editor
+    for (bar_id, button_id) in [(IDC_UPDATE_BAR, IDC_UPDATE_BAR_DISMISS), (IDC_DOWNLOAD_BAR, IDC_DOWNLOAD_BAR_CANCEL)] {
+        let Ok(hwnd_bar) = (unsafe { GetDlgItem(Some(hwnd), bar_id as i32) }) else {
+            continue;
+        };
+        let text_width = (width - INFO_BAR_BUTTON_WIDTH).max(0);
+        let _ = unsafe { SetWindowPos(hwnd_bar, None, 0, top, text_width, INFO_BAR_HEIGHT, SWP_NOZORDER) };
+        if let Ok(hwnd_button) = unsafe { GetDlgItem(Some(hwnd), button_id as i32) } {
+            let _ = unsafe { SetWindowPos(hwnd_button, None, text_width, top, INFO_BAR_BUTTON_WIDTH, INFO_BAR_HEIGHT, SWP_NOZORDER) };
+        }
+        top += INFO_BAR_HEIGHT;
+    }
+    let height = (rect.bottom - rect.top - top).max(0);
+
+    let mut editor_width = width;
+    if let Ok(hwnd_list) = unsafe { GetDlgItem(Some(hwnd), IDC_SHORTCUTS_LIST as i32) } {
+        let pane_width = SHORTCUTS_PANE_WIDTH.min(width);
+        editor_width = width - pane_width;
+        if let Ok(hwnd_search) = unsafe { GetDlgItem(Some(hwnd), IDC_SHORTCUTS_SEARCH as i32) } {
+            let search_width = (pane_width - SHORTCUTS_CLOSE_WIDTH).max(0);
+            let _ = unsafe { SetWindowPos(hwnd_search, None, editor_width, top, search_width, SHORTCUTS_SEARCH_HEIGHT, SWP_NOZORDER) };
+        }
+        if let Ok(hwnd_close) = unsafe { GetDlgItem(Some(hwnd), IDC_SHORTCUTS_CLOSE as i32) } {
+            let _ = unsafe { SetWindowPos(hwnd_close, None, width - SHORTCUTS_CLOSE_WIDTH, top, SHORTCUTS_CLOSE_WIDTH, SHORTCUTS_SEARCH_HEIGHT, SWP_NOZORDER) };
+        }
+        let list_height = (height - SHORTCUTS_SEARCH_HEIGHT).max(0);
+        let _ = unsafe { SetWindowPos(hwnd_list, None, editor_width, top + SHORTCUTS_SEARCH_HEIGHT, pane_width, list_height, SWP_NOZORDER) };
+    }
+    if let Ok(hwnd_list) = unsafe { GetDlgItem(Some(hwnd), IDC_HISTORY_LIST as i32) } {
+        let pane_width = HISTORY_PANE_WIDTH.min(width);
+        editor_width = width - pane_width;
+        if let Ok(hwnd_restore) = unsafe { GetDlgItem(Some(hwnd), IDC_HISTORY_RESTORE as i32) } {
+            let _ = unsafe { SetWindowPos(hwnd_restore, None, editor_width, top, HISTORY_BUTTON_WIDTH, HISTORY_BUTTON_HEIGHT, SWP_NOZORDER) };
+        }
+        if let Ok(hwnd_close) = unsafe { GetDlgItem(Some(hwnd), IDC_HISTORY_CLOSE as i32) } {
+            let _ = unsafe { SetWindowPos(hwnd_close, None, width - HISTORY_BUTTON_WIDTH, top, HISTORY_BUTTON_WIDTH, HISTORY_BUTTON_HEIGHT, SWP_NOZORDER) };
+        }
+        let list_height = HISTORY_LIST_HEIGHT.min((height - HISTORY_BUTTON_HEIGHT).max(0));
+        let _ = unsafe { SetWindowPos(hwnd_list, None, editor_width, top + HISTORY_BUTTON_HEIGHT, pane_width, list_height, SWP_NOZORDER) };
+        if let Ok(hwnd_preview) = unsafe { GetDlgItem(Some(hwnd), IDC_HISTORY_PREVIEW as i32) } {
+            let preview_top = HISTORY_BUTTON_HEIGHT + list_height;
+            let preview_height = (height - preview_top).max(0);
+            let _ = unsafe { SetWindowPos(hwnd_preview, None, editor_width, top + preview_top, pane_width, preview_height, SWP_NOZORDER) };
+        }
+    }
+
+    let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+    if !hwnd_editor.0.is_null() {
+        let _ = unsafe { SetWindowPos(hwnd_editor, None, 0, top, editor_width, height, SWP_NOZORDER) };
+    }
+}
+
+/// Returns the name usage statistics count a menu command under.
+fn command_name(command_id: u16) -> Option<&'static str> {
+    let name = match command_id {
+        IDM_FILE_NEW => "New",
+        IDM_FILE_NEW_FROM_TEMPLATE => "New From Template",
+        IDM_FILE_OPEN => "Open",
+        IDM_FILE_SAVE => "Save",
+        IDM_FILE_SAVE_AS => "Save As",
+        IDM_FILE_LOCAL_HISTORY => "Local History",
+        IDM_VIEW_ALIGN_COLUMNS => "Aligned Columns",
+        IDM_VIEW_FREEZE_HEADER => "Freeze Header Row",
+        IDM_VIEW_ZOOM_IN => "Zoom In",
+        IDM_VIEW_ZOOM_OUT => "Zoom Out",
+        IDM_VIEW_ZOOM_RESET => "Restore Default Zoom",
+        IDM_VIEW_ZOOM_SYNC => "Same Zoom for All Documents",
+        IDM_VIEW_AUTO_COPY => "Copy on Select",
+        IDM_VIEW_KEYS_STANDARD => "Standard Key Bindings",
+        IDM_VIEW_KEYS_VI => "Vi Key Bindings",
+        IDM_VIEW_KEYS_EMACS => "Emacs Key Bindings",
+        IDM_TOOLS_FORMAT => "Format Document",
+        IDM_TOOLS_MINIFY => "Minify Document",
+        IDM_TOOLS_FILE_HASHES => "File Hashes",
+        IDM_TOOLS_USAGE_STATS => "Usage Statistics",
+        IDM_HELP_ABOUT => "About",
+        IDM_HELP_CHECK_FOR_UPDATES => "Check for Updates Automatically",
+        IDM_HELP_KEYBOARD_SHORTCUTS => "Keyboard Shortcuts",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Adds the usage of this session to the stored statistics, reporting failures on stderr.
+fn save_usage_stats() {
+    if let Err(e) = usage_stats::save() {
+        eprintln!("Failed to save usage statistics: {}", e);
+    }
+}
+
+/// Shows how Jedit has been used, from the statistics kept on this computer.
+fn show_usage_stats(hwnd: HWND) {
+    let stats = usage_stats::current();
+    let minutes = stats.seconds_in_editor / 60;
+    let mut message = format!(
+        "Files opened: {}\nEdits made: {}\nTime in Jedit: {} h {} min\n\nMost used commands:\n",
+        stats.files_opened, stats.edits, minutes / 60, minutes % 60
+    );
+    let commands = stats.most_used_commands(10);
+    if commands.is_empty() {
+        message.push_str("    (none yet)\n");
+    }
+    for (name, uses) in commands {
+        message.push_str(&format!("    {}: {}\n", name, uses));
+    }
+    if let Some(path) = usage_stats::stats_path() {
+        message.push_str(&format!(
+            "\nThese statistics are only kept on this computer, in {}. They are never sent anywhere.",
+            path.display()
+        ));
+    }
+    let message_wide: Vec<u16> = message.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe { MessageBoxW(Some(hwnd), PCWSTR(message_wide.as_ptr()), w!("Usage Statistics"), MB_OK | MB_ICONINFORMATION) };
 }
 
 /// Displays a simple "About" message box.
@@ -119,18 +1372,50 @@ fn show_about_dialog(hwnd: HWND) {
 fn create_menu_bar() -> Result<HMENU> {
     let hmenu = unsafe { CreateMenu()? };
     let hsubmenu = unsafe { CreatePopupMenu()? };
+    let hviewmenu = unsafe { CreatePopupMenu()? };
+    let hkeysmenu = unsafe { CreatePopupMenu()? };
+    let htoolsmenu = unsafe { CreatePopupMenu()? };
+    let hhelpmenu = unsafe { CreatePopupMenu()? };
 
     let result = unsafe {
-        AppendMenuW(hsubmenu, MF_STRING, IDM_FILE_NEW as usize, w!("New"))?;
-        AppendMenuW(hsubmenu, MF_STRING, IDM_FILE_OPEN as usize, w!("Open"))?;
+        AppendMenuW(hsubmenu, MF_STRING, IDM_FILE_NEW as usize, w!("&New"))?;
+        AppendMenuW(hsubmenu, MF_STRING, IDM_FILE_NEW_FROM_TEMPLATE as usize, w!("New From &Template..."))?;
+        AppendMenuW(hsubmenu, MF_STRING, IDM_FILE_OPEN as usize, w!("&Open..."))?;
+        AppendMenuW(hsubmenu, MF_STRING, IDM_FILE_SAVE as usize, w!("&Save"))?;
+        AppendMenuW(hsubmenu, MF_STRING, IDM_FILE_SAVE_AS as usize, w!("Save &As..."))?;
         AppendMenuW(hsubmenu, MF_SEPARATOR, 0, None)?;
-        AppendMenuW(hsubmenu, MF_STRING, IDM_HELP_ABOUT as usize, w!("About"))?;
-        AppendMenuW(hmenu, MF_POPUP, hsubmenu.0 as usize, w!("File"))?;
+        AppendMenuW(hsubmenu, MF_STRING, IDM_FILE_LOCAL_HISTORY as usize, w!("Local &History"))?;
+        AppendMenuW(hmenu, MF_POPUP, hsubmenu.0 as usize, w!("&File"))?;
+        AppendMenuW(hviewmenu, MF_STRING, IDM_VIEW_ALIGN_COLUMNS as usize, w!("&Aligned Columns"))?;
+        AppendMenuW(hviewmenu, MF_STRING, IDM_VIEW_FREEZE_HEADER as usize, w!("Freeze &Header Row"))?;
+        AppendMenuW(hviewmenu, MF_SEPARATOR, 0, None)?;
+        AppendMenuW(hviewmenu, MF_STRING, IDM_VIEW_ZOOM_IN as usize, w!("Zoom &In\tCtrl++"))?;
+        AppendMenuW(hviewmenu, MF_STRING, IDM_VIEW_ZOOM_OUT as usize, w!("Zoom &Out\tCtrl+-"))?;
+        AppendMenuW(hviewmenu, MF_STRING, IDM_VIEW_ZOOM_RESET as usize, w!("&Restore Default Zoom\tCtrl+0"))?;
+        AppendMenuW(hviewmenu, MF_STRING, IDM_VIEW_ZOOM_SYNC as usize, w!("&Same Zoom for All Documents"))?;
+        AppendMenuW(hviewmenu, MF_SEPARATOR, 0, None)?;
+        AppendMenuW(hviewmenu, MF_STRING, IDM_VIEW_AUTO_COPY as usize, w!("&Copy on Select, Paste on Middle-Click"))?;
+        AppendMenuW(hkeysmenu, MF_STRING, IDM_VIEW_KEYS_STANDARD as usize, w!("&Standard"))?;
+        AppendMenuW(hkeysmenu, MF_STRING, IDM_VIEW_KEYS_VI as usize, w!("&Vi"))?;
+        AppendMenuW(hkeysmenu, MF_STRING, IDM_VIEW_KEYS_EMACS as usize, w!("&Emacs"))?;
+        AppendMenuW(hviewmenu, MF_POPUP, hkeysmenu.0 as usize, w!("&Key Bindings"))?;
+        AppendMenuW(hmenu, MF_POPUP, hviewmenu.0 as usize, w!("&View"))?;
+        AppendMenuW(htoolsmenu, MF_STRING, IDM_TOOLS_FORMAT as usize, w!("&Format Document"))?;
+        AppendMenuW(htoolsmenu, MF_STRING, IDM_TOOLS_MINIFY as usize, w!("&Minify Document"))?;
+        AppendMenuW(htoolsmenu, MF_SEPARATOR, 0, None)?;
+        AppendMenuW(htoolsmenu, MF_STRING, IDM_TOOLS_FILE_HASHES as usize, w!("File &Hashes"))?;
+        AppendMenuW(htoolsmenu, MF_STRING, IDM_TOOLS_USAGE_STATS as usize, w!("&Usage Statistics"))?;
+        AppendMenuW(hmenu, MF_POPUP, htoolsmenu.0 as usize, w!("&Tools"))?;
+        AppendMenuW(hhelpmenu, MF_STRING, IDM_HELP_KEYBOARD_SHORTCUTS as usize, w!("&Keyboard Shortcuts"))?;
+        AppendMenuW(hhelpmenu, MF_SEPARATOR, 0, None)?;
+        AppendMenuW(hhelpmenu, MF_STRING, IDM_HELP_CHECK_FOR_UPDATES as usize, w!("Check for &Updates Automatically"))?;
+        AppendMenuW(hhelpmenu, MF_STRING, IDM_HELP_ABOUT as usize, w!("&About Jedit"))?;
+        AppendMenuW(hmenu, MF_POPUP, hhelpmenu.0 as usize, w!("&Help"))?;
         Ok(())
     };
 
     if let Err(e) = result {
-        unsafe { DestroyMenu(hmenu); DestroyMenu(hsubmenu); }
+        unsafe { DestroyMenu(hmenu); DestroyMenu(hsubmenu); DestroyMenu(hviewmenu); DestroyMenu(hkeysmenu); DestroyMenu(htoolsmenu); DestroyMenu(hhelpmenu); }
         return Err(e);
     }
 
@@ -218,34 +1503,204 @@ extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM)
                 return LRESULT(-1);
             }
 
+            // Get notified when the session is locked so background timers can pause
+            if let Err(e) = unsafe { WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION) } {
+                eprintln!("WTSRegisterSessionNotification failed: {}", e);
+            }
+
+            // Let external tools drive the editor when started with --remote-control
+            if std::env::args().skip(1).any(|arg| arg == "--remote-control") {
+                if let Err(e) = remote_control::start(hwnd, WM_APP_REMOTECOMMAND) {
+                    eprintln!("Failed to start the remote control server: {}", e);
+                }
+            }
+
+            // Accept files dragged from Explorer and attachments dragged from mail clients.
+            // The drag source waits for the drop, so the item is opened once it has returned.
+            let drop_result = drop_target::register(hwnd, move |item| {
+                let item_ptr = Box::into_raw(Box::new(item));
+                if unsafe { PostMessageW(Some(hwnd), WM_APP_DROPPED, WPARAM(0), LPARAM(item_ptr as isize)) }.is_err() {
+                    drop(unsafe { Box::from_raw(item_ptr) });
+                }
+            });
+            if let Err(e) = drop_result {
+                eprintln!("RegisterDragDrop failed: {}", e);
+            }
+
+            // Selections are copied as they are made if the user opted in
+            let auto_copy = settings::flag(settings::AUTO_COPY_SELECTION);
+            unsafe { SendMessageW(hwnd_editor, EVM_SETAUTOCOPY, Some(WPARAM(auto_copy as usize)), Some(LPARAM(0))) };
+            let freeze_header = settings::flag(settings::FREEZE_HEADER_ROW);
+            unsafe { SendMessageW(hwnd_editor, EVM_SETFREEZEHEADER, Some(WPARAM(freeze_header as usize)), Some(LPARAM(0))) };
+            if let Some(key_bindings) = settings::number(settings::KEY_BINDINGS) {
+                unsafe { SendMessageW(hwnd_editor, EVM_SETKEYBINDINGS, Some(WPARAM(key_bindings as usize)), Some(LPARAM(0))) };
+            }
+            if let Some(lines) = settings::number(settings::SCROLL_MARGIN) {
+                unsafe { SendMessageW(hwnd_editor, EVM_SETSCROLLMARGIN, Some(WPARAM(lines as usize)), Some(LPARAM(0))) };
+            }
+
+            usage_stats::start_session();
+            unsafe { SetTimer(Some(hwnd), USAGE_STATS_TIMER_ID, USAGE_STATS_SAVE_INTERVAL_MS, None) };
+
+            // Look for a newer release in the background, if the user opted in
+            if update_check::enabled() {
+                update_check::start(hwnd, WM_APP_UPDATEAVAILABLE);
+            }
+
             // Menu creation successful
             LRESULT(0)
         }
         WM_SIZE => {
             let hwnd_editor_ptr = unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) }; // Add unsafe block
             let hwnd_editor = HWND(hwnd_editor_ptr as *mut _); // Cast isize to *mut c_void
-            if !hwnd_editor.0.is_null() { // Compare pointer with is_null()
-                let mut rect = RECT::default();
-                unsafe { GetClientRect(hwnd, &mut rect) }; // Add unsafe block
-                unsafe { SetWindowPos( // Add unsafe block
-                    hwnd_editor,
-                    None,
-                    0, 0,
-                    rect.right - rect.left,
-                    rect.bottom - rect.top,
-                    SWP_NOZORDER | SWP_NOMOVE
-                ) };
+            update_background_timers(hwnd, hwnd_editor);
+            layout_children(hwnd);
+            LRESULT(0)
+        }
+        WM_APP_HASHPROGRESS => {
+            let title = format!("Computing file hashes... {}% - Jedit", wparam.0);
+            let title_wide: Vec<u16> = title.encode_utf16().chain(std::iter::once(0)).collect();
+            let _ = unsafe { SetWindowTextW(hwnd, PCWSTR(title_wide.as_ptr())) };
+            LRESULT(0)
+        }
+        WM_APP_HASHESDONE => {
+            // Take back ownership of the result boxed by the hashing thread
+            let result = unsafe { Box::from_raw(lparam.0 as *mut HashResult) };
+            HASHING.store(false, Ordering::SeqCst);
+            let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+            update_window_title(hwnd, hwnd_editor);
+            show_file_hashes(hwnd, &result);
+            LRESULT(0)
+        }
+        WM_COPYDATA => {
+            let copy_data = unsafe { &*(lparam.0 as *const COPYDATASTRUCT) };
+            if copy_data.dwData != COPYDATA_OPEN_FILE || copy_data.lpData.is_null() {
+                return LRESULT(0);
+            }
+            let path_wide = unsafe {
+                std::slice::from_raw_parts(copy_data.lpData as *const u16, copy_data.cbData as usize / std::mem::size_of::<u16>())
+            };
+            let path_len = path_wide.iter().position(|&unit| unit == 0).unwrap_or(path_wide.len());
+            let file_path = PathBuf::from(OsString::from_wide(&path_wide[..path_len]));
+            if unsafe { IsIconic(hwnd) }.as_bool() {
+                let _ = unsafe { ShowWindow(hwnd, SW_RESTORE) };
+            }
+            open_file(hwnd, &file_path).to_copydata_result()
+        }
+        WM_APP_REMOTECOMMAND => {
+            // The server thread waits in SendMessageW, so the request outlives this call
+            let request = unsafe { &mut *(lparam.0 as *mut RemoteRequest) };
+            let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+            request.result = run_remote_command(hwnd, hwnd_editor, &request.command);
+            LRESULT(0)
+        }
+        WM_APP_DROPPED => {
+            // Take back ownership of the item boxed by the drop target
+            let item = unsafe { Box::from_raw(lparam.0 as *mut DroppedItem) };
+            let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+            let _ = unsafe { SetForegroundWindow(hwnd) };
+            match *item {
+                DroppedItem::File(file_path) => {
+                    open_file(hwnd, &file_path);
+                }
+                DroppedItem::VirtualFile(file) => {
+                    if confirm_discard_changes(hwnd, hwnd_editor) {
+                        open_dropped_content(hwnd, hwnd_editor, file);
+                    }
+                }
+            }
+            LRESULT(0)
+        }
+        WM_APP_DOWNLOADED => {
+            // Take back ownership of the result boxed by the download thread
+            let result = unsafe { Box::from_raw(lparam.0 as *mut DownloadResult) };
+            // Results of cancelled downloads are dropped
+            let current = DOWNLOAD.lock().unwrap().as_ref().is_some_and(|(number, _)| *number == wparam.0);
+            if current {
+                *DOWNLOAD.lock().unwrap() = None;
+                hide_info_bar(hwnd, IDC_DOWNLOAD_BAR, IDC_DOWNLOAD_BAR_CANCEL);
+                let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+                finish_download(hwnd, hwnd_editor, *result);
+            }
+            LRESULT(0)
+        }
+        WM_APP_UPDATEAVAILABLE => {
+            // Take back ownership of the release boxed by the update check
+            let release = unsafe { Box::from_raw(lparam.0 as *mut Release) };
+            show_update_bar(hwnd, *release);
+            LRESULT(0)
+        }
+        WM_CTLCOLORSTATIC => {
+            // Draw the info bars in the tooltip colors, like other info bars
+            let is_info_bar = [IDC_UPDATE_BAR, IDC_DOWNLOAD_BAR].into_iter().any(|bar_id| {
+                unsafe { GetDlgItem(Some(hwnd), bar_id as i32) }.is_ok_and(|hwnd_bar| hwnd_bar.0 == lparam.0 as *mut _)
+            });
+            if is_info_bar {
+                let hdc = HDC(wparam.0 as *mut _);
+                unsafe {
+                    SetTextColor(hdc, COLORREF(GetSysColor(COLOR_INFOTEXT)));
+                    SetBkColor(hdc, COLORREF(GetSysColor(COLOR_INFOBK)));
+                    return LRESULT(GetSysColorBrush(COLOR_INFOBK).0 as isize);
+                }
+            }
+            unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) }
+        }
+        WM_INITMENUPOPUP => {
+            // Reflect the editor's view state in the View menu before it opens
+            let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+            let aligned = unsafe { SendMessageW(hwnd_editor, EVM_GETALIGNEDVIEW, Some(WPARAM(0)), Some(LPARAM(0))) }.0 != 0;
+            let freeze_header = unsafe { SendMessageW(hwnd_editor, EVM_GETFREEZEHEADER, Some(WPARAM(0)), Some(LPARAM(0))) }.0 != 0;
+            let zoom_sync = unsafe { SendMessageW(hwnd_editor, EVM_GETZOOMSYNC, Some(WPARAM(0)), Some(LPARAM(0))) }.0 != 0;
+            let auto_copy = unsafe { SendMessageW(hwnd_editor, EVM_GETAUTOCOPY, Some(WPARAM(0)), Some(LPARAM(0))) }.0 != 0;
+            let key_bindings = unsafe { SendMessageW(hwnd_editor, EVM_GETKEYBINDINGS, Some(WPARAM(0)), Some(LPARAM(0))) }.0 as usize;
+            let hmenu = HMENU(wparam.0 as *mut _);
+            let check_updates = update_check::enabled();
+            let shortcuts_shown = unsafe { GetDlgItem(Some(hwnd), IDC_SHORTCUTS_LIST as i32) }.is_ok();
+            let history_shown = unsafe { GetDlgItem(Some(hwnd), IDC_HISTORY_LIST as i32) }.is_ok();
+            for (item, checked) in [
+                (IDM_VIEW_ALIGN_COLUMNS, aligned),
+                (IDM_VIEW_FREEZE_HEADER, freeze_header),
+                (IDM_VIEW_ZOOM_SYNC, zoom_sync),
+                (IDM_VIEW_AUTO_COPY, auto_copy),
+                (IDM_HELP_CHECK_FOR_UPDATES, check_updates),
+                (IDM_HELP_KEYBOARD_SHORTCUTS, shortcuts_shown),
+                (IDM_FILE_LOCAL_HISTORY, history_shown),
+            ] {
+                let check = if checked { MF_CHECKED } else { MF_UNCHECKED };
+                unsafe { CheckMenuItem(hmenu, item as u32, (MF_BYCOMMAND | check).0) };
             }
+            let checked_keys = match KeyBindings::from_index(key_bindings) {
+                KeyBindings::Standard => IDM_VIEW_KEYS_STANDARD,
+                KeyBindings::Vi => IDM_VIEW_KEYS_VI,
+                KeyBindings::Emacs => IDM_VIEW_KEYS_EMACS,
+            };
+            let _ = unsafe {
+                CheckMenuRadioItem(hmenu, IDM_VIEW_KEYS_STANDARD as u32, IDM_VIEW_KEYS_EMACS as u32, checked_keys as u32, MF_BYCOMMAND.0)
+            };
             LRESULT(0)
         }
         WM_COMMAND => {
             let command_id = loword(wparam.0); // Use helper function
+            if let Some(name) = command_name(command_id) {
+                usage_stats::record_command(name);
+            }
             let hwnd_editor_ptr = unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) }; // Add unsafe block
             let hwnd_editor = HWND(hwnd_editor_ptr as *mut _); // Cast isize to *mut c_void
 
+            // Notifications from the editor view, which has no control ID
+            if lparam.0 != 0 && lparam.0 == hwnd_editor.0 as isize {
+                if (wparam.0 >> 16) as u16 == EVN_MODIFIEDCHANGE {
+                    update_window_title(hwnd, hwnd_editor);
+                }
+                return LRESULT(0);
+            }
+
             match command_id {
                 IDM_FILE_NEW => {
                     // println!("WM_COMMAND: IDM_FILE_NEW"); // Keep commented for debugging
+                    if !confirm_discard_changes(hwnd, hwnd_editor) {
+                        return LRESULT(0);
+                    }
                     if let Err(e) = set_window_file_name(hwnd, w!("Untitled")) { // Removed underscore from _e
                         eprintln!("Failed to set window title for New File: {}", e); // Keep commented for debugging
                     }
@@ -257,31 +1712,12 @@ extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM)
                 }
                 IDM_FILE_OPEN => {
                     // println!("WM_COMMAND: IDM_FILE_OPEN"); // Keep commented for debugging
-                    if let Some((file_path, file_title)) = show_open_file_dialog(hwnd) {
+                    if !confirm_discard_changes(hwnd, hwnd_editor) {
+                        return LRESULT(0);
+                    }
+                    if let Some((file_path, file_title)) = show_open_file_dialog(hwnd, None, None) {
                         println!("  -> File selected: {}", file_path.display()); // Keep commented for debugging
-
-                        let file_path_wide: Vec<u16> = file_path
-                            .as_os_str()
-                            .encode_wide()
-                            .chain(std::iter::once(0))
-                            .collect();
-                        let file_ptr = file_path_wide.as_ptr();
-
-                        // Send message to editor view to open the file
-                        // EVM_OPENFILE returns LRESULT(1) on success, LRESULT(0) on failure
-                        let open_result = unsafe { SendMessageW(hwnd_editor, EVM_OPENFILE, Some(WPARAM(0)), Some(LPARAM(file_ptr as isize))) }; // Add unsafe block
-                        let open_success = open_result == LRESULT(1);
-
-                        if open_success {
-                            // Update the main window title
-                            let file_title_pcwstr = OsString::from(file_title)
-                                .encode_wide()
-                                .chain(std::iter::once(0))
-                                .collect::<Vec<_>>();
-                            if let Err(e) = set_window_file_name(hwnd, PCWSTR(file_title_pcwstr.as_ptr())) {
-                                eprintln!("Failed to set window title after Open File: {}", e); // Keep commented for debugging
-                            }
-                        } else {
+                        if !open_document(hwnd, hwnd_editor, &file_path, &file_title) {
                             // Show error message if opening failed
                             let error_text = w!("Error opening file.");
                             unsafe { MessageBoxW(Some(hwnd), error_text, APP_TITLE, MB_OK | MB_ICONEXCLAMATION) }; // Add unsafe block
@@ -291,12 +1727,125 @@ extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM)
                     }
                     LRESULT(0)
                 }
+                IDM_FILE_SAVE => {
+                    save_document(hwnd, hwnd_editor, false);
+                    LRESULT(0)
+                }
+                IDM_FILE_SAVE_AS => {
+                    save_document(hwnd, hwnd_editor, true);
+                    LRESULT(0)
+                }
+                IDM_FILE_NEW_FROM_TEMPLATE => {
+                    new_from_template(hwnd, hwnd_editor);
+                    LRESULT(0)
+                }
+                IDM_FILE_LOCAL_HISTORY => {
+                    toggle_history_pane(hwnd, hwnd_editor);
+                    LRESULT(0)
+                }
+                IDM_VIEW_ALIGN_COLUMNS => {
+                    toggle_aligned_view(hwnd, hwnd_editor);
+                    LRESULT(0)
+                }
+                IDM_VIEW_ZOOM_IN | IDM_VIEW_ZOOM_OUT | IDM_VIEW_ZOOM_RESET => {
+                    let steps: isize = match command_id {
+                        IDM_VIEW_ZOOM_IN => 1,
+                        IDM_VIEW_ZOOM_OUT => -1,
+                        _ => 0,
+                    };
+                    unsafe { SendMessageW(hwnd_editor, EVM_ZOOM, Some(WPARAM(steps as usize)), Some(LPARAM(0))) };
+                    LRESULT(0)
+                }
+                IDM_VIEW_ZOOM_SYNC => {
+                    let sync_all = unsafe { SendMessageW(hwnd_editor, EVM_GETZOOMSYNC, Some(WPARAM(0)), Some(LPARAM(0))) }.0 != 0;
+                    unsafe { SendMessageW(hwnd_editor, EVM_SETZOOMSYNC, Some(WPARAM(!sync_all as usize)), Some(LPARAM(0))) };
+                    LRESULT(0)
+                }
+                IDM_VIEW_AUTO_COPY => {
+                    toggle_auto_copy(hwnd_editor);
+                    LRESULT(0)
+                }
+                IDM_VIEW_FREEZE_HEADER => {
+                    toggle_freeze_header(hwnd_editor);
+                    LRESULT(0)
+                }
+                IDM_VIEW_KEYS_STANDARD | IDM_VIEW_KEYS_VI | IDM_VIEW_KEYS_EMACS => {
+                    let key_bindings = match command_id {
+                        IDM_VIEW_KEYS_VI => KeyBindings::Vi,
+                        IDM_VIEW_KEYS_EMACS => KeyBindings::Emacs,
+                        _ => KeyBindings::Standard,
+                    };
+                    set_key_bindings(hwnd, hwnd_editor, key_bindings);
+                    LRESULT(0)
+                }
+                IDM_TOOLS_FORMAT => {
+                    format_document(hwnd, hwnd_editor, false);
+                    LRESULT(0)
+                }
+                IDM_TOOLS_MINIFY => {
+                    format_document(hwnd, hwnd_editor, true);
+                    LRESULT(0)
+                }
+                IDM_TOOLS_FILE_HASHES => {
+                    start_file_hashes(hwnd, hwnd_editor);
+                    LRESULT(0)
+                }
+                IDM_TOOLS_USAGE_STATS => {
+                    show_usage_stats(hwnd);
+                    LRESULT(0)
+                }
 
                 IDM_HELP_ABOUT => {
                     println!("WM_COMMAND: IDM_HELP_ABOUT"); // Keep commented for debugging
                     show_about_dialog(hwnd);
                     LRESULT(0)
                 }
+                IDM_HELP_CHECK_FOR_UPDATES => {
+                    toggle_update_checks(hwnd);
+                    LRESULT(0)
+                }
+                IDC_UPDATE_BAR => {
+                    open_release_page(hwnd);
+                    LRESULT(0)
+                }
+                IDC_UPDATE_BAR_DISMISS => {
+                    hide_update_bar(hwnd);
+                    LRESULT(0)
+                }
+                IDC_DOWNLOAD_BAR_CANCEL => {
+                    cancel_download(hwnd);
+                    LRESULT(0)
+                }
+                IDM_HELP_KEYBOARD_SHORTCUTS => {
+                    toggle_shortcuts_pane(hwnd);
+                    LRESULT(0)
+                }
+                IDC_SHORTCUTS_SEARCH => {
+                    if (wparam.0 >> 16) as u32 == EN_CHANGE {
+                        fill_shortcuts_list(hwnd);
+                    }
+                    LRESULT(0)
+                }
+                IDC_SHORTCUTS_CLOSE => {
+                    hide_shortcuts_pane(hwnd);
+                    LRESULT(0)
+                }
+                IDC_HISTORY_LIST => {
+                    match (wparam.0 >> 16) as u32 {
+                        LBN_SELCHANGE => show_snapshot_diff(hwnd),
+                        LBN_DBLCLK => restore_selected_snapshot(hwnd, hwnd_editor),
+                        _ => {}
+                    }
+                    LRESULT(0)
+                }
+                IDC_HISTORY_RESTORE => {
+                    restore_selected_snapshot(hwnd, hwnd_editor);
+                    LRESULT(0)
+                }
+                IDC_HISTORY_CLOSE => {
+                    hide_history_pane(hwnd);
+                    LRESULT(0)
+                }
 
                 _ => {
                     println!("WM_COMMAND: Unhandled ID {}", command_id); // Keep commented for debugging
@@ -304,13 +1853,79 @@ extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM)
                 }
             }
         }
+        WM_SETFOCUS => {
+            // Keyboard input belongs to the editor view
+            let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+            if !hwnd_editor.0.is_null() {
+                let _ = unsafe { SetFocus(Some(hwnd_editor)) };
+            }
+            LRESULT(0)
+        }
+        WM_SETTINGCHANGE | WM_SYSCOLORCHANGE | WM_THEMECHANGED | WM_FONTCHANGE => {
+            // Only top-level windows receive these; pass them on to the editor view
+            let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+            if !hwnd_editor.0.is_null() {
+                unsafe { SendMessageW(hwnd_editor, msg, Some(wparam), Some(lparam)) };
+            }
+            unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) }
+        }
+        WM_TIMER => {
+            if wparam.0 == USAGE_STATS_TIMER_ID {
+                save_usage_stats();
+            }
+            LRESULT(0)
+        }
+        WM_QUERYENDSESSION => {
+            // Logging off or shutting down may be held up to save changes, unless it is forced
+            if lparam.0 as u32 & ENDSESSION_CRITICAL != 0 {
+                return LRESULT(1);
+            }
+            let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+            LRESULT(confirm_discard_changes(hwnd, hwnd_editor) as isize)
+        }
+        WM_ENDSESSION => {
+            // The process ends without WM_DESTROY when the user logs off
+            if wparam.0 != 0 {
+                save_usage_stats();
+            }
+            LRESULT(0)
+        }
+        WM_WTSSESSION_CHANGE => {
+            let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+            match wparam.0 as u32 {
+                WTS_SESSION_LOCK | WTS_SESSION_UNLOCK => {
+                    SESSION_LOCKED.store(wparam.0 as u32 == WTS_SESSION_LOCK, Ordering::SeqCst);
+                    update_background_timers(hwnd, hwnd_editor);
+                }
+                _ => {}
+            }
+            LRESULT(0)
+        }
+        WM_POWERBROADCAST => {
+            let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+            match wparam.0 as u32 {
+                PBT_APMSUSPEND | PBT_APMRESUMEAUTOMATIC => {
+                    SYSTEM_SUSPENDED.store(wparam.0 as u32 == PBT_APMSUSPEND, Ordering::SeqCst);
+                    update_background_timers(hwnd, hwnd_editor);
+                }
+                _ => {}
+            }
+            LRESULT(1) // TRUE: allow the power event
+        }
         WM_CLOSE => {
-            unsafe { DestroyWindow(hwnd) };
+            let hwnd_editor = HWND(unsafe { GetWindowLongPtrW(hwnd, GWLP_USERDATA) } as *mut _);
+            if confirm_discard_changes(hwnd, hwnd_editor) {
+                unsafe { DestroyWindow(hwnd) };
+            }
             LRESULT(0)
         }
         WM_DESTROY => {
             // Clean up user data when the main window is destroyed
             unsafe { SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0) };
+            let _ = unsafe { WTSUnRegisterSessionNotification(hwnd) };
+            drop_target::revoke(hwnd);
+            let _ = unsafe { KillTimer(Some(hwnd), USAGE_STATS_TIMER_ID) };
+            save_usage_stats();
             // Terminate the application's message loop
             unsafe { PostQuitMessage(0) }; 
             LRESULT(0)
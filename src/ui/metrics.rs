@@ -0,0 +1,54 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Weight of the newest sample in a timing's moving average.
+const AVERAGE_WEIGHT: f64 = 0.1;
+
+enum Metric {
+    Value(u64),
+    Timing { last: Duration, average: Duration },
+}
+
+/// A small registry of named measurements (paint times, counters, operation
+/// timings) shown by the debug overlay. Names are sorted for a stable display.
+pub struct Metrics {
+    entries: BTreeMap<&'static str, Metric>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Records how long an operation took, keeping the last sample and a moving average.
+    pub fn record_timing(&mut self, name: &'static str, elapsed: Duration) {
+        let entry = self.entries.entry(name).or_insert(Metric::Timing { last: elapsed, average: elapsed });
+        if let Metric::Timing { last, average } = entry {
+            *last = elapsed;
+            *average = average.mul_f64(1.0 - AVERAGE_WEIGHT) + elapsed.mul_f64(AVERAGE_WEIGHT);
+        }
+    }
+
+    /// Sets a plain numeric value, such as a count or a percentage.
+    pub fn set_value(&mut self, name: &'static str, value: u64) {
+        self.entries.insert(name, Metric::Value(value));
+    }
+
+    /// Formats every metric as one display line.
+    pub fn display_lines(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .map(|(name, metric)| match metric {
+                Metric::Value(value) => format!("{}: {}", name, value),
+                Metric::Timing { last, average } => format!(
+                    "{}: {:.2} ms (avg {:.2} ms)",
+                    name,
+                    last.as_secs_f64() * 1000.0,
+                    average.as_secs_f64() * 1000.0
+                ),
+            })
+            .collect()
+    }
+}
@@ -1,2 +1,25 @@
 pub mod editor_view;
 pub mod main_window;
+
+pub mod line_layout;
+pub mod render_cache;
+pub mod idle_scheduler;
+pub mod frame_pacer;
+pub mod metrics;
+pub mod csv_layout;
+pub mod decorations;
+pub mod zoom;
+pub mod control;
+pub mod remote_control;
+pub mod drop_target;
+pub mod update_check;
+pub mod selection;
+pub mod clipboard;
+pub mod announce;
+pub mod settings;
+pub mod vi_mode;
+pub mod keymap;
+pub mod emacs_keys;
+pub mod standard_keys;
+pub mod shortcuts;
+pub mod usage_stats;
\ No newline at end of file
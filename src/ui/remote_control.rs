@@ -0,0 +1,638 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::iter::Peekable;
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::AsRawHandle;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+use windows::{
+    core::{PCWSTR, PWSTR},
+    Win32::{
+        Foundation::{CloseHandle, LocalFree, ERROR_BROKEN_PIPE, ERROR_PIPE_CONNECTED, HANDLE, HLOCAL, HWND, LPARAM, WPARAM},
+        Security::{
+            Authorization::{ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1},
+            GetTokenInformation, TokenUser, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, TOKEN_QUERY, TOKEN_USER,
+        },
+        Storage::FileSystem::{
+            ReadFile, WriteFile, FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX, SECURITY_IDENTIFICATION,
+        },
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, GetNamedPipeServerProcessId, PeekNamedPipe,
+            PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_WAIT,
+        },
+        System::RemoteDesktop::ProcessIdToSessionId,
+        System::Threading::{
+            GetCurrentProcess, GetCurrentProcessId, OpenProcess, OpenProcessToken, PROCESS_QUERY_LIMITED_INFORMATION,
+        },
+        UI::WindowsAndMessaging::SendMessageW,
+    },
+};
+
+/// Size of the pipe's in and out buffers.
+const PIPE_BUFFER_SIZE: u32 = 4096;
+
+/// Most clients served at a time; further clients wait until one disconnects.
+const MAX_CLIENTS: usize = 16;
+
+/// Longest request line accepted, in bytes; a longer one ends the connection.
+const MAX_REQUEST_LEN: usize = 1 << 20;
+
+/// Pause before creating the pipe again after that failed. It doubles with
+/// every failure in a row, up to MAX_RETRY_DELAY.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// How often a client subscribed to save events is checked for having
+/// disconnected while no events come, so its place is given back.
+const SUBSCRIBER_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Connections waiting for save events.
+static SAVE_SUBSCRIBERS: Mutex<Vec<Sender<String>>> = Mutex::new(Vec::new());
+
+/// Number of clients being served, and the signal that one disconnected.
+static CLIENTS: Mutex<usize> = Mutex::new(0);
+static CLIENT_LEFT: Condvar = Condvar::new();
+
+/// A command received over the remote control pipe, run on the UI thread.
+pub enum RemoteCommand {
+    OpenFile(PathBuf),
+    /// Moves the caret to the start of a 1-based line.
+    GotoLine(usize),
+    GetSelection,
+    /// Replaces the selection with the text, or inserts it at the caret.
+    InsertText(String),
+}
+
+/// Result of a remote command: named numbers for the response, or an error message.
+pub type RemoteResult = Result<Vec<(&'static str, usize)>, String>;
+
+/// Passed by pointer in the lparam of the message given to `start`. The window
+/// runs `command` and stores its outcome in `result`.
+pub struct RemoteRequest {
+    pub command: RemoteCommand,
+    pub result: RemoteResult,
+}
+
+/// Returns the name of the pipe. It holds the user's SID and the session, so
+/// instances of other users and of the user's other sessions don't collide.
+pub fn pipe_name() -> windows::core::Result<String> {
+    let sid = process_user_sid(unsafe { GetCurrentProcess() })?;
+    let mut session = 0;
+    unsafe { ProcessIdToSessionId(GetCurrentProcessId(), &mut session) }?;
+    Ok(format!(r"\\.\pipe\jedit-{}-{}", sid, session))
+}
+
+/// Returns the SID of the user running `process` in string form, e.g. "S-1-5-21-...".
+fn process_user_sid(process: HANDLE) -> windows::core::Result<String> {
+    let mut token = HANDLE::default();
+    unsafe { OpenProcessToken(process, TOKEN_QUERY, &mut token) }?;
+    let sid = token_user_sid(token);
+    let _ = unsafe { CloseHandle(token) };
+    sid
+}
+
+fn token_user_sid(token: HANDLE) -> windows::core::Result<String> {
+    // The first call fails and reports the size of the TOKEN_USER and the SID after it
+    let mut len = 0;
+    let _ = unsafe { GetTokenInformation(token, TokenUser, None, 0, &mut len) };
+    // u64 elements keep the TOKEN_USER aligned
+    let mut buffer = vec![0u64; (len as usize).div_ceil(8)];
+    unsafe { GetTokenInformation(token, TokenUser, Some(buffer.as_mut_ptr() as *mut _), len, &mut len) }?;
+    let token_user = unsafe { &*(buffer.as_ptr() as *const TOKEN_USER) };
+
+    let mut sid_string = PWSTR::null();
+    unsafe { ConvertSidToStringSidW(token_user.User.Sid, &mut sid_string) }?;
+    let sid = unsafe { sid_string.to_string() };
+    let _ = unsafe { LocalFree(Some(HLOCAL(sid_string.0 as *mut _))) };
+    sid.map_err(|_| windows::core::Error::from_win32())
+}
+
+/// A security descriptor allocated by the system; freed when dropped.
+struct SecurityDescriptor(PSECURITY_DESCRIPTOR);
+
+impl SecurityDescriptor {
+    /// Returns a descriptor whose DACL grants access to the user with the SID `sid` only.
+    fn user_only(sid: &str) -> windows::core::Result<Self> {
+        // "P" keeps inheritable entries out, "GA" is full access
+        let sddl: Vec<u16> = format!("D:P(A;;GA;;;{})", sid).encode_utf16().chain(std::iter::once(0)).collect();
+        let mut descriptor = PSECURITY_DESCRIPTOR::default();
+        unsafe { ConvertStringSecurityDescriptorToSecurityDescriptorW(PCWSTR(sddl.as_ptr()), SDDL_REVISION_1, &mut descriptor, None) }?;
+        Ok(SecurityDescriptor(descriptor))
+    }
+}
+
+impl Drop for SecurityDescriptor {
+    fn drop(&mut self) {
+        let _ = unsafe { LocalFree(Some(HLOCAL(self.0.0))) };
+    }
+}
+
+/// Connects to the remote control pipe of the jedit instance in the current
+/// session, for clients written in Rust. Fails unless the server runs as the
+/// current user, so a process that created the pipe first can't pose as jedit.
+pub fn connect() -> Result<File, Box<dyn Error>> {
+    let pipe = OpenOptions::new()
+        .read(true)
+        .write(true)
+        // The server may identify the client, but not act as it
+        .security_qos_flags(SECURITY_IDENTIFICATION.0)
+        .open(pipe_name()?)?;
+
+    let mut server_process_id = 0;
+    unsafe { GetNamedPipeServerProcessId(HANDLE(pipe.as_raw_handle()), &mut server_process_id) }?;
+    let server_process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, server_process_id) }?;
+    let server_sid = process_user_sid(server_process);
+    let _ = unsafe { CloseHandle(server_process) };
+    if server_sid? != process_user_sid(unsafe { GetCurrentProcess() })? {
+        return Err("The remote control pipe is served by another user".into());
+    }
+    Ok(pipe)
+}
+
+/// Starts serving the remote control pipe on a background thread. Commands are
+/// sent to `hwnd` as `message`, so they run on the UI thread.
+///
+/// Clients write one JSON object per line and get one back per request:
+///
+/// - `{"command": "open-file", "path": "C:\\notes.txt"}`
+/// - `{"command": "goto-line", "line": 42}`
+/// - `{"command": "get-selection"}`, answered with `start` and `end` character indices
+/// - `{"command": "insert-text", "text": "Hello"}`
+/// - `{"command": "subscribe-save"}`: from then on the connection receives
+///   `{"event": "saved", "path": ...}` lines and accepts no more commands
+///
+/// Responses are `{"ok": true, ...}` or `{"ok": false, "error": "..."}`.
+/// Only the current user may connect.
+pub fn start(hwnd: HWND, message: u32) -> Result<(), Box<dyn Error>> {
+    let name = pipe_name()?;
+    let sid = process_user_sid(unsafe { GetCurrentProcess() })?;
+    // Window handles aren't Send; the server only hands it back to SendMessageW
+    let hwnd_value = hwnd.0 as isize;
+    thread::Builder::new()
+        .name("remote-control".into())
+        .spawn(move || serve(&name, &sid, hwnd_value, message))?;
+    Ok(())
+}
+
+/// Accepts connections for as long as the process runs, one thread per client
+/// and at most MAX_CLIENTS at a time.
+fn serve(name: &str, sid: &str, hwnd_value: isize, message: u32) {
+    let name: Vec<u16> = name.encode_utf16().chain(std::iter::once(0)).collect();
+    let descriptor = match SecurityDescriptor::user_only(sid) {
+        Ok(descriptor) => descriptor,
+        Err(e) => {
+            eprintln!("Failed to create the remote control pipe's security descriptor: {}", e);
+            return;
+        }
+    };
+    let attributes = SECURITY_ATTRIBUTES {
+        nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+        lpSecurityDescriptor: descriptor.0.0,
+        bInheritHandle: false.into(),
+    };
+    // Fail if another process already serves the pipe instead of sharing it
+    let mut open_mode = PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE;
+    let mut retry_delay = RETRY_DELAY;
+    loop {
+        let slot = ClientSlot::acquire();
+        let pipe = unsafe {
+            CreateNamedPipeW(
+                PCWSTR(name.as_ptr()),
+                open_mode,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                // The instances of the clients being served and the one waiting for the next
+                MAX_CLIENTS as u32 + 1,
+                PIPE_BUFFER_SIZE,
+                PIPE_BUFFER_SIZE,
+                0,
+                Some(&attributes),
+            )
+        };
+        if pipe.is_invalid() {
+            // E.g. another jedit instance serves the pipe; it may go away
+            eprintln!("Failed to create the remote control pipe: {}", windows::core::Error::from_win32());
+            drop(slot);
+            thread::sleep(retry_delay);
+            retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+            continue;
+        }
+        open_mode = PIPE_ACCESS_DUPLEX;
+        retry_delay = RETRY_DELAY;
+        let connection = PipeConnection(pipe);
+
+        // A client connecting between the two calls is reported as an error
+        if let Err(e) = unsafe { ConnectNamedPipe(pipe, None) } {
+            if e.code() != ERROR_PIPE_CONNECTED.to_hresult() {
+                eprintln!("Remote control connection failed: {}", e);
+                continue;
+            }
+        }
+        let spawned = thread::Builder::new()
+            .name("remote-control-client".into())
+            .spawn(move || {
+                handle_connection(connection, hwnd_value, message);
+                drop(slot);
+            });
+        if let Err(e) = spawned {
+            eprintln!("Failed to start a remote control client thread: {}", e);
+        }
+    }
+}
+
+/// One of the MAX_CLIENTS places for clients being served, given back when dropped.
+struct ClientSlot;
+
+impl ClientSlot {
+    /// Waits until fewer than MAX_CLIENTS clients are served and takes a place.
+    fn acquire() -> ClientSlot {
+        let mut clients = CLIENTS.lock().unwrap_or_else(PoisonError::into_inner);
+        while *clients >= MAX_CLIENTS {
+            clients = CLIENT_LEFT.wait(clients).unwrap_or_else(PoisonError::into_inner);
+        }
+        *clients += 1;
+        ClientSlot
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        *CLIENTS.lock().unwrap_or_else(PoisonError::into_inner) -= 1;
+        CLIENT_LEFT.notify_one();
+    }
+}
+
+/// Answers the requests of one client until it disconnects.
+fn handle_connection(connection: PipeConnection, hwnd_value: isize, message: u32) {
+    let mut reader = BufReader::new(connection);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        // Reading one byte past the limit tells a line of exactly the limit from a longer one
+        match reader.by_ref().take(MAX_REQUEST_LEN as u64 + 1).read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+        if line.len() > MAX_REQUEST_LEN {
+            let error = format!("Requests may be at most {} bytes long", MAX_REQUEST_LEN);
+            let _ = writeln!(reader.get_mut(), "{}", response_json(&Err(error)));
+            return;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match parse_request(&line) {
+            Ok(None) => {
+                if writeln!(reader.get_mut(), "{}", response_json(&Ok(Vec::new()))).is_ok() {
+                    stream_save_events(reader.get_mut());
+                }
+                return;
+            }
+            Ok(Some(command)) => response_json(&run_on_ui_thread(hwnd_value, message, command)),
+            Err(e) => response_json(&Err(e)),
+        };
+        if writeln!(reader.get_mut(), "{}", response).is_err() {
+            return;
+        }
+    }
+}
+
+/// Sends a command to the window and waits until it has run.
+fn run_on_ui_thread(hwnd_value: isize, message: u32, command: RemoteCommand) -> RemoteResult {
+    let mut request = RemoteRequest {
+        command,
+        // Left in place if the window is gone and the message isn't handled
+        result: Err("The editor window is not available".into()),
+    };
+    unsafe {
+        SendMessageW(
+            HWND(hwnd_value as *mut _),
+            message,
+            Some(WPARAM(0)),
+            Some(LPARAM(&mut request as *mut RemoteRequest as isize)),
+        )
+    };
+    request.result
+}
+
+/// Writes save events to a subscribed client until it disconnects.
+fn stream_save_events(connection: &mut PipeConnection) {
+    let (sender, receiver) = mpsc::channel();
+    SAVE_SUBSCRIBERS.lock().unwrap_or_else(PoisonError::into_inner).push(sender);
+    loop {
+        match receiver.recv_timeout(SUBSCRIBER_CHECK_INTERVAL) {
+            Ok(event) => {
+                if writeln!(connection, "{}", event).is_err() {
+                    return;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if !connection.is_connected() {
+                    return;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Tells subscribed clients that the document was saved to `path`.
+pub fn notify_saved(path: &Path) {
+    let event = format!("{{\"event\": \"saved\", \"path\": {}}}", json_string(&path.to_string_lossy()));
+    let mut subscribers = SAVE_SUBSCRIBERS.lock().unwrap_or_else(PoisonError::into_inner);
+    // Sending fails once a client's thread has ended
+    subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+}
+
+/// Parses a request line. Returns None for a save subscription.
+fn parse_request(line: &str) -> Result<Option<RemoteCommand>, String> {
+    let mut fields = parse_object(line)?;
+    let mut string_field = |name: &str| match fields.remove(name) {
+        Some(JsonValue::String(value)) => Ok(value),
+        _ => Err(format!("Expected a string \"{}\"", name)),
+    };
+    let command = match string_field("command")?.as_str() {
+        "open-file" => RemoteCommand::OpenFile(PathBuf::from(string_field("path")?)),
+        "insert-text" => RemoteCommand::InsertText(string_field("text")?),
+        "get-selection" => RemoteCommand::GetSelection,
+        "subscribe-save" => return Ok(None),
+        "goto-line" => match fields.remove("line") {
+            Some(JsonValue::Number(line)) if line >= 1 => RemoteCommand::GotoLine(line as usize),
+            _ => return Err("Expected a line number \"line\" of at least 1".into()),
+        },
+        command => return Err(format!("Unknown command \"{}\"", command)),
+    };
+    Ok(Some(command))
+}
+
+/// Formats the response to a request as a JSON object.
+fn response_json(result: &RemoteResult) -> String {
+    match result {
+        Ok(values) => {
+            let mut response = String::from("{\"ok\": true");
+            for (name, value) in values {
+                response.push_str(&format!(", \"{}\": {}", name, value));
+            }
+            response.push('}');
+            response
+        }
+        Err(e) => format!("{{\"ok\": false, \"error\": {}}}", json_string(e)),
+    }
+}
+
+/// Quotes and escapes `text` as a JSON string.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The values requests are made of; nested objects and arrays aren't needed.
+enum JsonValue {
+    String(String),
+    Number(i64),
+    /// true, false or null, which no command takes.
+    Literal,
+}
+
+/// Parses a flat JSON object into its fields.
+fn parse_object(text: &str) -> Result<HashMap<String, JsonValue>, String> {
+    let mut chars = text.chars().peekable();
+    let mut fields = HashMap::new();
+    skip_whitespace(&mut chars);
+    expect(&mut chars, '{')?;
+    skip_whitespace(&mut chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+    } else {
+        loop {
+            skip_whitespace(&mut chars);
+            let name = parse_string(&mut chars)?;
+            skip_whitespace(&mut chars);
+            expect(&mut chars, ':')?;
+            skip_whitespace(&mut chars);
+            fields.insert(name, parse_value(&mut chars)?);
+            skip_whitespace(&mut chars);
+            match chars.next() {
+                Some(',') => continue,
+                Some('}') => break,
+                _ => return Err("Expected ',' or '}'".into()),
+            }
+        }
+    }
+    skip_whitespace(&mut chars);
+    if chars.next().is_some() {
+        return Err("Unexpected text after the object".into());
+    }
+    Ok(fields)
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|ch| ch.is_whitespace()).is_some() {}
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), String> {
+    match chars.next() {
+        Some(ch) if ch == expected => Ok(()),
+        _ => Err(format!("Expected '{}'", expected)),
+    }
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<JsonValue, String> {
+    match chars.peek() {
+        Some('"') => Ok(JsonValue::String(parse_string(chars)?)),
+        Some('-' | '0'..='9') => {
+            let mut number = String::new();
+            while let Some(ch) = chars.next_if(|ch| *ch == '-' || ch.is_ascii_digit()) {
+                number.push(ch);
+            }
+            number.parse().map(JsonValue::Number).map_err(|_| format!("Invalid number {}", number))
+        }
+        Some(_) => {
+            let mut word = String::new();
+            while let Some(ch) = chars.next_if(|ch| ch.is_ascii_alphabetic()) {
+                word.push(ch);
+            }
+            match word.as_str() {
+                "true" | "false" | "null" => Ok(JsonValue::Literal),
+                _ => Err("Only strings, integers, booleans and null are supported".into()),
+            }
+        }
+        None => Err("Expected a value".into()),
+    }
+}
+
+/// Parses a quoted string. Escapes are collected as UTF-16 so `\u` surrogate pairs decode.
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    expect(chars, '"')?;
+    let mut units: Vec<u16> = Vec::new();
+    loop {
+        let ch = chars.next().ok_or("Unterminated string")?;
+        let escaped = match ch {
+            '"' => break,
+            '\\' => chars.next().ok_or("Unterminated string")?,
+            ch => {
+                units.extend(ch.encode_utf16(&mut [0; 2]).iter());
+                continue;
+            }
+        };
+        let unit = match escaped {
+            '"' | '\\' | '/' => escaped as u16,
+            'b' => 0x08,
+            'f' => 0x0C,
+            'n' => 0x0A,
+            'r' => 0x0D,
+            't' => 0x09,
+            'u' => {
+                let hex: String = chars.by_ref().take(4).collect();
+                // from_str_radix also takes a sign
+                if hex.len() != 4 || !hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+                    return Err(format!("Invalid escape \\u{}", hex));
+                }
+                u16::from_str_radix(&hex, 16).map_err(|_| format!("Invalid escape \\u{}", hex))?
+            }
+            other => return Err(format!("Invalid escape \\{}", other)),
+        };
+        units.push(unit);
+    }
+    String::from_utf16(&units).map_err(|_| "Invalid surrogate in string".into())
+}
+
+/// Server end of a connected pipe instance; disconnects and closes it when dropped.
+struct PipeConnection(HANDLE);
+
+// The handle is only used by the thread that owns the connection
+unsafe impl Send for PipeConnection {}
+
+impl PipeConnection {
+    /// Whether the client still has its end of the pipe open. Unlike a read,
+    /// this doesn't wait for data, and a read pending on the synchronous handle
+    /// would hold up writes.
+    fn is_connected(&self) -> bool {
+        unsafe { PeekNamedPipe(self.0, None, 0, None, None, None) }.is_ok()
+    }
+}
+
+impl Read for PipeConnection {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        match unsafe { ReadFile(self.0, Some(buffer), Some(&mut read), None) } {
+            Ok(()) => Ok(read as usize),
+            // The client closed its end
+            Err(e) if e.code() == ERROR_BROKEN_PIPE.to_hresult() => Ok(0),
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+}
+
+impl Write for PipeConnection {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+        unsafe { WriteFile(self.0, Some(buffer), Some(&mut written), None) }.map_err(io::Error::other)?;
+        Ok(written as usize)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PipeConnection {
+    fn drop(&mut self) {
+        let _ = unsafe { DisconnectNamedPipe(self.0) };
+        let _ = unsafe { CloseHandle(self.0) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_flat_objects() {
+        let fields = parse_object(" { \"a\" : \"x\", \"n\": -12, \"t\": true, \"z\": null } \n").unwrap();
+        assert_eq!(fields.len(), 4);
+        assert!(matches!(fields.get("a"), Some(JsonValue::String(value)) if value == "x"));
+        assert!(matches!(fields.get("n"), Some(JsonValue::Number(-12))));
+        assert!(matches!(fields.get("t"), Some(JsonValue::Literal)));
+        assert!(parse_object("{}").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_objects() {
+        for text in ["", "[]", "{", "{\"a\"}", "{\"a\": 1,}", "{\"a\": 1} x", "{\"a\": [1]}", "{\"a\": 1-2}", "{a: 1}"] {
+            assert!(parse_object(text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn decodes_string_escapes() {
+        let string = |text: &str| parse_string(&mut text.chars().peekable());
+        assert_eq!(string(r#""a\"b\\c\/d\n\t""#).unwrap(), "a\"b\\c/d\n\t");
+        assert_eq!(string(r#""\u00e9\u00E9""#).unwrap(), "\u{e9}\u{e9}");
+        assert_eq!(string(r#""\ud83d\ude00""#).unwrap(), "\u{1f600}");
+        assert_eq!(string("\"caf\u{e9}\"").unwrap(), "caf\u{e9}");
+    }
+
+    #[test]
+    fn rejects_invalid_string_escapes() {
+        let string = |text: &str| parse_string(&mut text.chars().peekable());
+        for text in [r#""\u+041""#, r#""\u-041""#, r#""\u12""#, r#""\u12g4""#, r#""\x""#, r#""\ud83d""#, r#""open"#] {
+            assert!(string(text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn parses_requests() {
+        assert!(matches!(
+            parse_request(r#"{"command": "open-file", "path": "C:\\notes.txt"}"#),
+            Ok(Some(RemoteCommand::OpenFile(path))) if path == Path::new(r"C:\notes.txt")
+        ));
+        assert!(matches!(parse_request(r#"{"line": 42, "command": "goto-line"}"#), Ok(Some(RemoteCommand::GotoLine(42)))));
+        assert!(matches!(parse_request(r#"{"command": "get-selection"}"#), Ok(Some(RemoteCommand::GetSelection))));
+        assert!(matches!(
+            parse_request(r#"{"command": "insert-text", "text": "Hi\n"}"#),
+            Ok(Some(RemoteCommand::InsertText(text))) if text == "Hi\n"
+        ));
+        assert!(matches!(parse_request(r#"{"command": "subscribe-save"}"#), Ok(None)));
+    }
+
+    #[test]
+    fn rejects_invalid_requests() {
+        for line in [
+            r#"{}"#,
+            r#"{"command": 1}"#,
+            r#"{"command": "close"}"#,
+            r#"{"command": "open-file"}"#,
+            r#"{"command": "goto-line", "line": 0}"#,
+            r#"{"command": "goto-line", "line": "3"}"#,
+        ] {
+            assert!(parse_request(line).is_err(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn quotes_json_strings() {
+        assert_eq!(json_string("a\"b\\c\n\u{1}"), r#""a\"b\\c\n\u0001""#);
+        assert_eq!(response_json(&Ok(vec![("start", 1), ("end", 2)])), r#"{"ok": true, "start": 1, "end": 2}"#);
+        assert_eq!(response_json(&Err("No \"x\"".into())), r#"{"ok": false, "error": "No \"x\""}"#);
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::ui::line_layout::{self, LineRun};
+
+/// Upper bound on cached lines; the cache is simply emptied when it is exceeded.
+const MAX_CACHED_LINES: usize = 4096;
+
+/// A laid-out run ready to be handed to TextOutW.
+pub enum CachedRun {
+    Text(Vec<u16>),
+    Control(&'static str),
+}
+
+impl CachedRun {
+    /// Number of character cells the run occupies on screen.
+    pub fn columns(&self) -> usize {
+        match self {
+            CachedRun::Text(text_wide) => text_wide.len(),
+            CachedRun::Control(mnemonic) => mnemonic.len(),
+        }
+    }
+}
+
+/// The laid-out visible part of a single line.
+pub struct CachedLine {
+    pub runs: Vec<CachedRun>,
+    /// Number of bytes of the line covered by `runs`.
+    pub visible_len: usize,
+}
+
+impl CachedLine {
+    fn layout(line_text: &str, max_columns: usize) -> Self {
+        let mut visible_len = 0;
+        let runs = line_layout::line_runs(line_text, max_columns)
+            .into_iter()
+            .map(|run| match run {
+                LineRun::Text(text) => {
+                    visible_len += text.len();
+                    CachedRun::Text(text.encode_utf16().collect())
+                }
+                LineRun::Control(mnemonic) => {
+                    visible_len += 1; // Control characters are single-byte
+                    CachedRun::Control(mnemonic)
+                }
+            })
+            .collect();
+        CachedLine { runs, visible_len }
+    }
+}
+
+/// Caches laid-out lines between paints so scrolling and repainting don't
+/// re-layout every visible line. Entries are keyed by line number and are only
+/// valid for the document version they were built from.
+pub struct RenderCache {
+    version: u64,
+    max_columns: usize,
+    lines: HashMap<usize, Rc<CachedLine>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl RenderCache {
+    pub fn new() -> Self {
+        RenderCache {
+            version: 0,
+            max_columns: 0,
+            lines: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Returns the layout of `lineno`, building it from `line_text` on a miss.
+    /// A change of document version or of the laid-out width drops all entries.
+    pub fn get_or_layout(&mut self, version: u64, lineno: usize, line_text: &str, max_columns: usize) -> Rc<CachedLine> {
+        if version != self.version || max_columns > self.max_columns {
+            self.lines.clear();
+            self.version = version;
+            self.max_columns = max_columns;
+        }
+        if let Some(line) = self.lines.get(&lineno) {
+            self.hits += 1;
+            return Rc::clone(line);
+        }
+        self.misses += 1;
+
+        if self.lines.len() >= MAX_CACHED_LINES {
+            self.lines.clear();
+        }
+        // Lay out to the widest width seen so narrower paints can reuse the entry
+        let line = Rc::new(CachedLine::layout(line_text, self.max_columns));
+        self.lines.insert(lineno, Rc::clone(&line));
+        line
+    }
+
+    /// Drops all cached layouts, e.g. after a change of system settings.
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    /// Percentage of lookups served from the cache since it was created.
+    pub fn hit_rate_percent(&self) -> u64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 { 0 } else { self.hits * 100 / lookups }
+    }
+}
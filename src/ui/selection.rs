@@ -0,0 +1,49 @@
+use std::ops::Range;
+
+/// A selection in the document, as byte offsets. `anchor` is where the
+/// selection was started and stays put while it grows or shrinks; `active` is
+/// the end that moves, where the caret is. Nothing is selected when they are equal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: usize,
+    pub active: usize,
+}
+
+impl Selection {
+    /// An empty selection with the caret at `offset`.
+    pub fn caret(offset: usize) -> Self {
+        Selection { anchor: offset, active: offset }
+    }
+
+    pub fn new(anchor: usize, active: usize) -> Self {
+        Selection { anchor, active }
+    }
+
+    /// Returns the offset where the selection starts, whichever end that is.
+    pub fn start(&self) -> usize {
+        self.anchor.min(self.active)
+    }
+
+    /// Returns the offset where the selection ends, whichever end that is.
+    pub fn end(&self) -> usize {
+        self.anchor.max(self.active)
+    }
+
+    /// Returns the selected byte range, in document order.
+    pub fn range(&self) -> Range<usize> {
+        self.start()..self.end()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.active
+    }
+
+    /// Moves the caret to `offset`. With `extend`, the anchor stays where it is
+    /// and the selection grows or shrinks; otherwise the selection collapses there.
+    pub fn move_to(&mut self, offset: usize, extend: bool) {
+        if !extend {
+            self.anchor = offset;
+        }
+        self.active = offset;
+    }
+}
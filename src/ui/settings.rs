@@ -0,0 +1,69 @@
+use std::error::Error;
+use windows::{
+    core::{w, PCWSTR},
+    Win32::{
+        Foundation::ERROR_SUCCESS,
+        System::Registry::{RegGetValueW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_DWORD, RRF_RT_REG_DWORD},
+    },
+};
+
+// User settings are DWORD values under HKEY_CURRENT_USER\Software\Jedit
+const SETTINGS_KEY: PCWSTR = w!("Software\\Jedit");
+
+/// Check for newer releases on startup.
+pub const CHECK_FOR_UPDATES: PCWSTR = w!("CheckForUpdates");
+/// Copy selections to the clipboard as they are made; middle-click pastes.
+pub const AUTO_COPY_SELECTION: PCWSTR = w!("AutoCopySelection");
+/// Keep the first line of aligned columns in view as their header row.
+pub const FREEZE_HEADER_ROW: PCWSTR = w!("FreezeHeaderRow");
+/// Lines of context kept above and below the caret when scrolling to it.
+pub const SCROLL_MARGIN: PCWSTR = w!("ScrollMargin");
+/// The key binding scheme, as a KeyBindings index.
+pub const KEY_BINDINGS: PCWSTR = w!("KeyBindings");
+/// Spaces per level Format Document indents by; 4 when not set.
+pub const FORMAT_INDENT: PCWSTR = w!("FormatIndent");
+
+/// Returns whether the on/off setting `name` is on. Settings are off until the
+/// user turns them on.
+pub fn flag(name: PCWSTR) -> bool {
+    number(name).is_some_and(|value| value != 0)
+}
+
+/// Returns the numeric setting `name`, or None if it isn't set.
+pub fn number(name: PCWSTR) -> Option<u32> {
+    let mut value: u32 = 0;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            SETTINGS_KEY,
+            name,
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut value as *mut u32 as *mut _),
+            Some(&mut size),
+        )
+    };
+    (result == ERROR_SUCCESS).then_some(value)
+}
+
+/// Turns the on/off setting `name` on or off for the current user.
+pub fn set_flag(name: PCWSTR, on: bool) -> Result<(), Box<dyn Error>> {
+    set_number(name, on as u32)
+}
+
+/// Sets the numeric setting `name` for the current user.
+pub fn set_number(name: PCWSTR, value: u32) -> Result<(), Box<dyn Error>> {
+    unsafe {
+        RegSetKeyValueW(
+            HKEY_CURRENT_USER,
+            SETTINGS_KEY,
+            name,
+            REG_DWORD.0,
+            Some(&value as *const u32 as *const _),
+            std::mem::size_of::<u32>() as u32,
+        )
+    }
+    .ok()?;
+    Ok(())
+}
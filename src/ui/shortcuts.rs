@@ -0,0 +1,114 @@
+use crate::ui::emacs_keys::{self, EmacsCommand};
+use crate::ui::keymap::KeyBindings;
+use crate::ui::standard_keys::{self, StandardCommand};
+use crate::ui::vi_mode::{self, ViCommand};
+
+/// A key binding as the Keyboard Shortcuts pane lists it.
+pub struct Shortcut {
+    pub category: &'static str,
+    pub keys: String,
+    pub description: &'static str,
+}
+
+impl Shortcut {
+    /// Whether the keys, the description or the category contain `query`, ignoring case.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        [self.keys.as_str(), self.description, self.category]
+            .iter()
+            .any(|text| text.to_lowercase().contains(&query))
+    }
+}
+
+/// Returns the category the Keyboard Shortcuts pane lists a standard command under.
+fn standard_category(command: StandardCommand) -> &'static str {
+    use StandardCommand::*;
+    match command {
+        Move(_) => "Movement",
+        Select(_) => "Selection",
+        Cut | CutOrDelete | Copy | Paste => "Clipboard",
+        DeleteBack | DeleteForward | DeleteWordBack | DeleteWordForward | Undo | Redo | RepeatLastEdit => "Editing",
+        ZoomIn | ZoomOut | ResetZoom | ToggleRenderingStats => "View",
+    }
+}
+
+/// Returns the category the Keyboard Shortcuts pane lists a vi command under.
+fn vi_category(command: ViCommand) -> &'static str {
+    use ViCommand::*;
+    match command {
+        Insert(_) | Visual | Escape => "Vi Modes",
+        Motion(_) => "Vi Motions",
+        Operator(_) => "Vi Operators",
+        DeleteChars | Put { .. } | Undo | Redo | Register => "Vi Editing",
+    }
+}
+
+/// Returns the category the Keyboard Shortcuts pane lists an Emacs command under.
+fn emacs_category(command: EmacsCommand) -> &'static str {
+    use EmacsCommand::*;
+    match command {
+        LineStart | LineEnd | ForwardChar | BackwardChar | NextLine | PreviousLine | ForwardWord
+        | BackwardWord | BufferStart | BufferEnd | PageDown | PageUp => "Emacs Movement",
+        DeleteChar | KillWord | KillLine | KillRegion | CopyRegion | Yank => "Emacs Killing and Yanking",
+        SetMark | ExchangePointAndMark | MarkWholeBuffer => "Emacs Mark and Region",
+        Undo | KeyboardQuit => "Emacs Other",
+    }
+}
+
+/// Returns the shortcuts of a key binding scheme, grouped by category: the
+/// scheme's own keys first, then the standard keys it leaves alone.
+pub fn shortcuts(key_bindings: KeyBindings) -> Vec<Shortcut> {
+    let mut shortcuts = Vec::new();
+    let emacs_keymap = (key_bindings == KeyBindings::Emacs).then(emacs_keys::keymap);
+    match key_bindings {
+        KeyBindings::Standard => {}
+        KeyBindings::Vi => {
+            for binding in vi_mode::keymap().bindings() {
+                shortcuts.push(Shortcut {
+                    category: vi_category(binding.action),
+                    keys: binding.keys_name(),
+                    description: binding.description,
+                });
+            }
+            // Counts are typed before a command rather than bound to keys
+            shortcuts.push(Shortcut {
+                category: "Vi Editing",
+                keys: "{count}".to_string(),
+                description: "Repeat the next motion or command",
+            });
+        }
+        KeyBindings::Emacs => {
+            for binding in emacs_keymap.iter().flat_map(|keymap| keymap.bindings()) {
+                shortcuts.push(Shortcut {
+                    category: emacs_category(binding.action),
+                    keys: binding.keys_name(),
+                    description: binding.description,
+                });
+            }
+        }
+    }
+    for binding in standard_keys::keymap().bindings() {
+        // Emacs key bindings take precedence, also for the first key of a sequence such as C-x
+        let taken = emacs_keymap
+            .iter()
+            .flat_map(|keymap| keymap.bindings())
+            .any(|emacs_binding| emacs_binding.keys.first() == binding.keys.first());
+        if !taken {
+            shortcuts.push(Shortcut {
+                category: standard_category(binding.action),
+                keys: binding.keys.iter().map(|chord| chord.windows_name()).collect::<Vec<_>>().join(", "),
+                description: binding.description,
+            });
+        }
+    }
+
+    // Keep the categories in the order they first appear in
+    let mut categories: Vec<&str> = Vec::new();
+    for shortcut in &shortcuts {
+        if !categories.contains(&shortcut.category) {
+            categories.push(shortcut.category);
+        }
+    }
+    shortcuts.sort_by_key(|shortcut| categories.iter().position(|&category| category == shortcut.category));
+    shortcuts
+}
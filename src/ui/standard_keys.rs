@@ -0,0 +1,126 @@
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    VIRTUAL_KEY, VK_0, VK_ADD, VK_BACK, VK_C, VK_DELETE, VK_DOWN, VK_END, VK_F12, VK_HOME, VK_INSERT, VK_LEFT,
+    VK_NEXT, VK_NUMPAD0, VK_OEM_MINUS, VK_OEM_PERIOD, VK_OEM_PLUS, VK_PRIOR, VK_RIGHT, VK_SUBTRACT, VK_UP, VK_V,
+    VK_X, VK_Y, VK_Z,
+};
+use crate::ui::keymap::{Binding, Chord, Keymap};
+
+/// Caret movements of the navigation keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Movement {
+    CharLeft,
+    CharRight,
+    /// To the start of the word, or of the previous one.
+    WordLeft,
+    /// To the end of the word, or of the next one.
+    WordRight,
+    LineUp,
+    LineDown,
+    LineStart,
+    LineEnd,
+    PageUp,
+    PageDown,
+    DocumentStart,
+    DocumentEnd,
+}
+
+impl Movement {
+    /// Returns the navigation key and whether Ctrl goes with it, for the
+    /// movements that aren't made of words.
+    pub fn navigation_key(self) -> Option<(VIRTUAL_KEY, bool)> {
+        match self {
+            Movement::CharLeft => Some((VK_LEFT, false)),
+            Movement::CharRight => Some((VK_RIGHT, false)),
+            Movement::WordLeft | Movement::WordRight => None,
+            Movement::LineUp => Some((VK_UP, false)),
+            Movement::LineDown => Some((VK_DOWN, false)),
+            Movement::LineStart => Some((VK_HOME, false)),
+            Movement::LineEnd => Some((VK_END, false)),
+            Movement::PageUp => Some((VK_PRIOR, false)),
+            Movement::PageDown => Some((VK_NEXT, false)),
+            Movement::DocumentStart => Some((VK_HOME, true)),
+            Movement::DocumentEnd => Some((VK_END, true)),
+        }
+    }
+}
+
+/// Commands of the keys that work in every scheme unless Emacs key bindings
+/// take their chord over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StandardCommand {
+    /// Moves the caret, collapsing the selection.
+    Move(Movement),
+    /// Moves the caret and extends the selection to it.
+    Select(Movement),
+    Cut,
+    /// Shift+Delete: cuts the selection, or else deletes the next character.
+    CutOrDelete,
+    Copy,
+    Paste,
+    DeleteBack,
+    DeleteForward,
+    DeleteWordBack,
+    DeleteWordForward,
+    Undo,
+    Redo,
+    /// Makes the last edit again at the caret.
+    RepeatLastEdit,
+    ZoomIn,
+    ZoomOut,
+    ResetZoom,
+    ToggleRenderingStats,
+}
+
+/// The navigation keys: the chord, its movement, and what the key does
+/// without and with Shift.
+const MOVEMENT_KEYS: &[(Chord, Movement, &str, &str)] = &[
+    (Chord::plain(VK_LEFT), Movement::CharLeft, "Move a character left", "Extend the selection a character left"),
+    (Chord::plain(VK_RIGHT), Movement::CharRight, "Move a character right", "Extend the selection a character right"),
+    (Chord::ctrl(VK_LEFT), Movement::WordLeft, "Move to the start of the word", "Extend the selection to the start of the word"),
+    (Chord::ctrl(VK_RIGHT), Movement::WordRight, "Move to the end of the word", "Extend the selection to the end of the word"),
+    (Chord::plain(VK_UP), Movement::LineUp, "Move a line up", "Extend the selection a line up"),
+    (Chord::plain(VK_DOWN), Movement::LineDown, "Move a line down", "Extend the selection a line down"),
+    (Chord::plain(VK_HOME), Movement::LineStart, "Move to the start of the line", "Extend the selection to the start of the line"),
+    (Chord::plain(VK_END), Movement::LineEnd, "Move to the end of the line", "Extend the selection to the end of the line"),
+    (Chord::plain(VK_PRIOR), Movement::PageUp, "Move a page up", "Extend the selection a page up"),
+    (Chord::plain(VK_NEXT), Movement::PageDown, "Move a page down", "Extend the selection a page down"),
+    (Chord::ctrl(VK_HOME), Movement::DocumentStart, "Move to the start of the document", "Extend the selection to the start of the document"),
+    (Chord::ctrl(VK_END), Movement::DocumentEnd, "Move to the end of the document", "Extend the selection to the end of the document"),
+];
+
+/// Returns the standard key bindings.
+pub fn keymap() -> Keymap<StandardCommand> {
+    use StandardCommand::*;
+    let mut bindings = Vec::new();
+    for &(chord, movement, move_description, select_description) in MOVEMENT_KEYS {
+        bindings.push(Binding::new(&[chord], Move(movement), move_description));
+        bindings.push(Binding::new(&[chord.with_shift()], Select(movement), select_description));
+    }
+    bindings.extend([
+        Binding::new(&[Chord::ctrl(VK_X)], Cut, "Cut"),
+        Binding::new(&[Chord::plain(VK_DELETE).with_shift()], CutOrDelete, "Cut the selection"),
+        Binding::new(&[Chord::ctrl(VK_C)], Copy, "Copy"),
+        Binding::new(&[Chord::ctrl(VK_INSERT)], Copy, "Copy"),
+        Binding::new(&[Chord::ctrl(VK_V)], Paste, "Paste"),
+        Binding::new(&[Chord::plain(VK_INSERT).with_shift()], Paste, "Paste"),
+        Binding::new(&[Chord::plain(VK_BACK)], DeleteBack, "Delete the previous character"),
+        Binding::new(&[Chord::plain(VK_BACK).with_shift()], DeleteBack, "Delete the previous character"),
+        Binding::new(&[Chord::plain(VK_DELETE)], DeleteForward, "Delete the next character"),
+        Binding::new(&[Chord::ctrl(VK_BACK)], DeleteWordBack, "Delete to the start of the word"),
+        Binding::new(&[Chord::ctrl(VK_DELETE)], DeleteWordForward, "Delete to the end of the word"),
+        Binding::new(&[Chord::ctrl(VK_Z)], Undo, "Undo"),
+        Binding::new(&[Chord::ctrl(VK_Y)], Redo, "Redo"),
+        Binding::new(&[Chord::ctrl(VK_Z).with_shift()], Redo, "Redo"),
+        Binding::new(&[Chord::ctrl(VK_OEM_PERIOD).with_shift()], RepeatLastEdit, "Repeat the last edit at the caret"),
+        Binding::new(&[Chord::ctrl(VK_OEM_PLUS)], ZoomIn, "Zoom in"),
+        // Layouts such as US English type + with Shift
+        Binding::new(&[Chord::ctrl(VK_OEM_PLUS).with_shift()], ZoomIn, "Zoom in"),
+        Binding::new(&[Chord::ctrl(VK_ADD)], ZoomIn, "Zoom in"),
+        Binding::new(&[Chord::ctrl(VK_OEM_MINUS)], ZoomOut, "Zoom out"),
+        Binding::new(&[Chord::ctrl(VK_SUBTRACT)], ZoomOut, "Zoom out"),
+        Binding::new(&[Chord::ctrl(VK_0)], ResetZoom, "Restore the default zoom"),
+        Binding::new(&[Chord::ctrl(VK_NUMPAD0)], ResetZoom, "Restore the default zoom"),
+        Binding::new(&[Chord::ctrl(VK_F12).with_shift()], ToggleRenderingStats, "Show or hide rendering statistics"),
+    ]);
+    Keymap::new(bindings)
+}
@@ -0,0 +1,201 @@
+use std::error::Error;
+use std::ptr;
+use crate::ui::settings;
+use windows::{
+    core::{w, PCWSTR},
+    Win32::{
+        Foundation::{HWND, LPARAM, WPARAM},
+        Networking::WinHttp::{
+            WinHttpCloseHandle, WinHttpConnect, WinHttpOpen, WinHttpOpenRequest, WinHttpQueryHeaders,
+            WinHttpReadData, WinHttpReceiveResponse, WinHttpSendRequest, INTERNET_DEFAULT_HTTPS_PORT,
+            WINHTTP_ACCESS_TYPE_AUTOMATIC_PROXY, WINHTTP_FLAG_SECURE, WINHTTP_QUERY_FLAG_NUMBER,
+            WINHTTP_QUERY_STATUS_CODE,
+        },
+        UI::WindowsAndMessaging::PostMessageW,
+    },
+};
+
+// Endpoint describing the latest published release
+const RELEASES_HOST: PCWSTR = w!("api.github.com");
+const LATEST_RELEASE_PATH: PCWSTR = w!("/repos/jcg517/jedit/releases/latest");
+/// Download page of a release, followed by its tag.
+const RELEASE_PAGE_URL: &str = "https://github.com/jcg517/jedit/releases/tag/";
+
+/// Largest response read; release descriptions are far smaller.
+const MAX_RESPONSE_LEN: usize = 1024 * 1024;
+
+/// A release newer than the running version.
+pub struct Release {
+    pub version: String,
+    /// Page the release can be downloaded from.
+    pub url: String,
+}
+
+/// Returns whether the user turned automatic update checks on. They are off
+/// until turned on, so nothing is sent anywhere without consent.
+pub fn enabled() -> bool {
+    settings::flag(settings::CHECK_FOR_UPDATES)
+}
+
+/// Turns automatic update checks on or off for the current user.
+pub fn set_enabled(enabled: bool) -> Result<(), Box<dyn Error>> {
+    settings::set_flag(settings::CHECK_FOR_UPDATES, enabled)
+}
+
+/// Checks for a newer release on a background thread. If there is one, it is
+/// posted to `hwnd` as `message` with a `Box<Release>` in lparam. Failures are
+/// only logged: being offline shouldn't interrupt the user. Updates are never
+/// downloaded or installed, the user is only pointed to the download page.
+pub fn start(hwnd: HWND, message: u32) {
+    // HWND isn't Send, so the handle crosses to the thread as an integer
+    let hwnd_raw = hwnd.0 as isize;
+    std::thread::spawn(move || {
+        let hwnd = HWND(hwnd_raw as *mut _);
+        let tag = match fetch_latest_tag() {
+            Ok(tag) => tag,
+            Err(e) => {
+                eprintln!("Update check failed: {}", e);
+                return;
+            }
+        };
+        if !is_newer(&tag, env!("CARGO_PKG_VERSION")) {
+            return;
+        }
+        let release = Box::new(Release {
+            version: tag.trim_start_matches('v').to_string(),
+            url: format!("{}{}", RELEASE_PAGE_URL, tag),
+        });
+        let release_ptr = Box::into_raw(release);
+        if unsafe { PostMessageW(Some(hwnd), message, WPARAM(0), LPARAM(release_ptr as isize)) }.is_err() {
+            // The window is gone, so nobody will take ownership of the release
+            drop(unsafe { Box::from_raw(release_ptr) });
+        }
+    });
+}
+
+/// A WinHTTP session, connection or request handle, closed when dropped.
+struct InternetHandle(*mut std::ffi::c_void);
+
+impl InternetHandle {
+    fn new(handle: *mut std::ffi::c_void) -> Result<Self, Box<dyn Error>> {
+        if handle.is_null() {
+            return Err(windows::core::Error::from_win32().into());
+        }
+        Ok(InternetHandle(handle))
+    }
+}
+
+impl Drop for InternetHandle {
+    fn drop(&mut self) {
+        let _ = unsafe { WinHttpCloseHandle(self.0) };
+    }
+}
+
+/// Asks the releases endpoint for the tag of the latest release.
+fn fetch_latest_tag() -> Result<String, Box<dyn Error>> {
+    let user_agent: Vec<u16> = format!("Jedit/{}", env!("CARGO_PKG_VERSION"))
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect();
+    // The automatic proxy setting follows the system proxy configuration,
+    // including auto-detection and PAC scripts
+    let session = InternetHandle::new(unsafe {
+        WinHttpOpen(PCWSTR(user_agent.as_ptr()), WINHTTP_ACCESS_TYPE_AUTOMATIC_PROXY, PCWSTR::null(), PCWSTR::null(), 0)
+    })?;
+    let connection = InternetHandle::new(unsafe { WinHttpConnect(session.0, RELEASES_HOST, INTERNET_DEFAULT_HTTPS_PORT, 0) })?;
+    let request = InternetHandle::new(unsafe {
+        WinHttpOpenRequest(connection.0, w!("GET"), LATEST_RELEASE_PATH, PCWSTR::null(), PCWSTR::null(), ptr::null(), WINHTTP_FLAG_SECURE)
+    })?;
+
+    let headers: Vec<u16> = "Accept: application/vnd.github+json\r\n".encode_utf16().collect();
+    unsafe {
+        WinHttpSendRequest(request.0, Some(&headers), None, 0, 0, 0)?;
+        WinHttpReceiveResponse(request.0, ptr::null_mut())?;
+    }
+    let mut status: u32 = 0;
+    let mut status_size = std::mem::size_of::<u32>() as u32;
+    unsafe {
+        WinHttpQueryHeaders(
+            request.0,
+            WINHTTP_QUERY_STATUS_CODE | WINHTTP_QUERY_FLAG_NUMBER,
+            PCWSTR::null(),
+            Some(&mut status as *mut u32 as *mut _),
+            &mut status_size,
+            ptr::null_mut(),
+        )?;
+    }
+    if status != 200 {
+        return Err(format!("The releases endpoint answered with status {}", status).into());
+    }
+
+    let mut body = Vec::new();
+    let mut buffer = [0u8; 8192];
+    loop {
+        let mut read = 0u32;
+        unsafe { WinHttpReadData(request.0, buffer.as_mut_ptr() as *mut _, buffer.len() as u32, &mut read)? };
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&buffer[..read as usize]);
+        if body.len() > MAX_RESPONSE_LEN {
+            return Err("The release description is too large".into());
+        }
+    }
+    string_field(&String::from_utf8_lossy(&body), "tag_name").ok_or_else(|| "The release has no tag".into())
+}
+
+/// Returns the value of the first string field called `name` in a JSON
+/// document, without unescaping it. Good enough for release tags, which are
+/// plain ASCII and only appear once in a release description.
+fn string_field(json: &str, name: &str) -> Option<String> {
+    let key = format!("\"{}\"", name);
+    let rest = json[json.find(&key)? + key.len()..].trim_start();
+    let rest = rest.strip_prefix(':')?.trim_start().strip_prefix('"')?;
+    Some(rest[..rest.find('"')?].to_string())
+}
+
+/// Returns whether the release tagged `tag` (e.g. "v1.10.0") is newer than
+/// the version `current`. Pre-release and build suffixes are ignored.
+fn is_newer(tag: &str, current: &str) -> bool {
+    version_numbers(tag) > version_numbers(current)
+}
+
+fn version_numbers(version: &str) -> Vec<u64> {
+    let version = version.trim_start_matches('v');
+    let version = version.split(['-', '+']).next().unwrap_or(version);
+    let mut numbers: Vec<u64> = version.split('.').map(|part| part.parse().unwrap_or(0)).collect();
+    // 1.2 and 1.2.0 are the same version
+    while numbers.last() == Some(&0) {
+        numbers.pop();
+    }
+    numbers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_version_parts_as_numbers() {
+        assert!(is_newer("v1.10", "1.9"));
+        assert!(is_newer("v1.10.0", "1.9.9"));
+        assert!(is_newer("v2.0", "1.99"));
+        assert!(!is_newer("v1.9", "1.10"));
+    }
+
+    #[test]
+    fn equal_versions_are_not_newer() {
+        assert!(!is_newer("v1.2.0", "1.2.0"));
+        assert!(!is_newer("v1.2", "1.2.0"));
+        assert!(!is_newer("1.2.0", "v1.2"));
+        assert_eq!(version_numbers("v1.2.0"), version_numbers("1.2"));
+    }
+
+    #[test]
+    fn ignores_pre_release_and_build_suffixes() {
+        assert_eq!(version_numbers("v1.3.0-beta.2"), vec![1, 3]);
+        assert_eq!(version_numbers("1.3.0+build.7"), vec![1, 3]);
+        assert!(!is_newer("v1.2.0-rc1", "1.2.0"));
+        assert!(is_newer("v1.3.0-rc1", "1.2.9"));
+    }
+}
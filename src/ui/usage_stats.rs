@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Usage in this session that isn't stored yet, and since when time is counted.
+static SESSION: Mutex<(UsageStats, Option<Instant>)> = Mutex::new((UsageStats::new(), None));
+
+/// How Jedit has been used. The statistics stay on this computer; they are
+/// only shown to the user and never sent anywhere.
+#[derive(Clone, Default)]
+pub struct UsageStats {
+    pub files_opened: u64,
+    pub edits: u64,
+    pub seconds_in_editor: u64,
+    /// How often each command was used, by its menu name.
+    pub commands: BTreeMap<String, u64>,
+}
+
+impl UsageStats {
+    const fn new() -> Self {
+        UsageStats {
+            files_opened: 0,
+            edits: 0,
+            seconds_in_editor: 0,
+            commands: BTreeMap::new(),
+        }
+    }
+
+    fn add(&mut self, other: &UsageStats) {
+        self.files_opened += other.files_opened;
+        self.edits += other.edits;
+        self.seconds_in_editor += other.seconds_in_editor;
+        for (name, count) in &other.commands {
+            *self.commands.entry(name.clone()).or_default() += count;
+        }
+    }
+
+    /// Returns up to `count` commands, most used first.
+    pub fn most_used_commands(&self, count: usize) -> Vec<(&str, u64)> {
+        let mut commands: Vec<(&str, u64)> = self.commands.iter().map(|(name, &uses)| (name.as_str(), uses)).collect();
+        commands.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        commands.truncate(count);
+        commands
+    }
+
+    /// Reads statistics stored as `name=value` lines; unknown lines are skipped.
+    fn parse(text: &str) -> Self {
+        let mut stats = UsageStats::default();
+        for (name, value) in text.lines().filter_map(|line| line.split_once('=')) {
+            let Ok(value) = value.trim().parse::<u64>() else {
+                continue;
+            };
+            match name {
+                "files_opened" => stats.files_opened = value,
+                "edits" => stats.edits = value,
+                "seconds_in_editor" => stats.seconds_in_editor = value,
+                _ => {
+                    if let Some(command) = name.strip_prefix("command.") {
+                        stats.commands.insert(command.to_string(), value);
+                    }
+                }
+            }
+        }
+        stats
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = format!(
+            "files_opened={}\nedits={}\nseconds_in_editor={}\n",
+            self.files_opened, self.edits, self.seconds_in_editor
+        );
+        for (name, count) in &self.commands {
+            text.push_str(&format!("command.{}={}\n", name, count));
+        }
+        text
+    }
+}
+
+/// Returns the file the statistics are stored in (`%APPDATA%\Jedit\usage-stats.txt`).
+pub fn stats_path() -> Option<PathBuf> {
+    std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("Jedit").join("usage-stats.txt"))
+}
+
+/// Reads the stored statistics. They start out empty when none are stored
+/// yet, but a file that can't be read is an error, so it isn't overwritten.
+fn load() -> Result<UsageStats, Box<dyn Error>> {
+    let path = stats_path().ok_or("APPDATA is not set")?;
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(UsageStats::parse(&text)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(UsageStats::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Starts counting the time spent in the editor, unless it is counted already.
+pub fn start_session() {
+    let mut session = SESSION.lock().unwrap();
+    if session.1.is_none() {
+        session.1 = Some(Instant::now());
+    }
+}
+
+/// Stops counting the time spent in the editor, e.g. while it is minimized,
+/// until `start_session` is called again.
+pub fn pause_session() {
+    let mut session = SESSION.lock().unwrap();
+    session_usage(&mut session);
+    session.1 = None;
+}
+
+pub fn record_file_opened() {
+    SESSION.lock().unwrap().0.files_opened += 1;
+}
+
+pub fn record_edit() {
+    SESSION.lock().unwrap().0.edits += 1;
+}
+
+/// Counts a use of the command called `name`, e.g. "Save".
+pub fn record_command(name: &str) {
+    *SESSION.lock().unwrap().0.commands.entry(name.to_string()).or_default() += 1;
+}
+
+/// Returns the usage of this session so far, moving the time counted up to
+/// now into it.
+fn session_usage(session: &mut (UsageStats, Option<Instant>)) -> &UsageStats {
+    if let Some(started) = session.1.as_mut() {
+        let seconds = started.elapsed().as_secs();
+        session.0.seconds_in_editor += seconds;
+        // Keep the fraction of a second that wasn't counted
+        *started += Duration::from_secs(seconds);
+    }
+    &session.0
+}
+
+/// Returns the stored statistics including this session.
+pub fn current() -> UsageStats {
+    let mut stats = load().unwrap_or_else(|e| {
+        eprintln!("Failed to read the usage statistics: {}", e);
+        UsageStats::default()
+    });
+    stats.add(session_usage(&mut SESSION.lock().unwrap()));
+    stats
+}
+
+/// Adds the usage of this session to the stored statistics. They are written
+/// to a temporary file that then replaces the old one, so a crash or power
+/// loss while saving doesn't lose the statistics.
+pub fn save() -> Result<(), Box<dyn Error>> {
+    let path = stats_path().ok_or("APPDATA is not set")?;
+    let mut session = SESSION.lock().unwrap();
+    let mut stats = load()?;
+    stats.add(session_usage(&mut session));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp_path = path.with_extension("txt.tmp");
+    fs::write(&temp_path, stats.to_text())?;
+    fs::rename(&temp_path, &path)?;
+    session.0 = UsageStats::default();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> UsageStats {
+        let mut stats = UsageStats::new();
+        stats.files_opened = 3;
+        stats.edits = 120;
+        stats.seconds_in_editor = 3600;
+        stats.commands.insert("Save".to_string(), 7);
+        stats.commands.insert("Open".to_string(), 2);
+        stats
+    }
+
+    #[test]
+    fn text_round_trips() {
+        let text = sample().to_text();
+        assert_eq!(
+            text,
+            "files_opened=3\nedits=120\nseconds_in_editor=3600\ncommand.Open=2\ncommand.Save=7\n"
+        );
+        assert_eq!(UsageStats::parse(&text).to_text(), text);
+    }
+
+    #[test]
+    fn parse_skips_unknown_and_malformed_lines() {
+        let stats = UsageStats::parse(
+            "files_opened=4\r\nedits=many\nunknown=5\nno equals sign\ncommand.Format=-1\ncommand.Zoom In= 6\n",
+        );
+        assert_eq!(stats.files_opened, 4);
+        assert_eq!(stats.edits, 0);
+        assert_eq!(stats.seconds_in_editor, 0);
+        assert_eq!(stats.commands.len(), 1);
+        assert_eq!(stats.commands.get("Zoom In"), Some(&6));
+    }
+
+    #[test]
+    fn parse_of_empty_text_is_empty() {
+        assert_eq!(UsageStats::parse("").to_text(), UsageStats::new().to_text());
+    }
+
+    #[test]
+    fn add_sums_counts_and_commands() {
+        let mut stats = sample();
+        stats.add(&sample());
+        assert_eq!(stats.files_opened, 6);
+        assert_eq!(stats.seconds_in_editor, 7200);
+        assert_eq!(stats.most_used_commands(1), vec![("Save", 14)]);
+    }
+}
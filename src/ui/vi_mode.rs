@@ -0,0 +1,636 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use crate::document::text_document::TextDocument;
+use crate::ui::keymap::{Binding, Keymap, Lookup};
+
+/// The register used when a command doesn't name one.
+pub const UNNAMED_REGISTER: char = '"';
+
+/// The largest count a command takes. Typing more digits, or an operator's
+/// count times its motion's, gives this.
+pub const MAX_COUNT: usize = 99_999;
+
+/// The modes of the vi key bindings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Keys are commands: motions, operators and mode changes.
+    Normal,
+    /// Keys type text, as without vi key bindings, until Esc.
+    Insert,
+    /// Motions extend a selection that operators then apply to.
+    Visual,
+}
+
+/// Caret movements, which also give the text an operator applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Motion {
+    /// `h`: one character left, within the line.
+    Left,
+    /// `l`: one character right, within the line.
+    Right,
+    /// `k`
+    Up,
+    /// `j`
+    Down,
+    /// `w`: to the start of the next word.
+    WordForward,
+    /// `b`: to the start of the word, or of the previous one.
+    WordBackward,
+    /// `e`: to the last character of the word, or of the next one.
+    WordEnd,
+    /// `0`
+    LineStart,
+    /// `^`: to the first non-blank character of the line.
+    FirstNonBlank,
+    /// `$`: to the last character of the line.
+    LineEnd,
+    /// `gg`, or `G` with a count: to the given 1-based line.
+    Line(usize),
+    /// `G` without a count.
+    LastLine,
+}
+
+impl Motion {
+    /// Whether an operator with this motion includes the character it ends on.
+    fn inclusive(self) -> bool {
+        matches!(self, Motion::WordEnd | Motion::LineEnd)
+    }
+
+    /// Whether an operator with this motion applies to whole lines.
+    fn linewise(self) -> bool {
+        matches!(self, Motion::Up | Motion::Down | Motion::Line(_) | Motion::LastLine)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    /// `d`
+    Delete,
+    /// `c`: deletes, then starts insert mode.
+    Change,
+    /// `y`: copies into a register.
+    Yank,
+}
+
+/// Where insert mode starts typing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InsertAt {
+    /// `i`
+    BeforeCaret,
+    /// `a`
+    AfterCaret,
+    /// `I`: before the first non-blank character of the line.
+    LineStart,
+    /// `A`
+    LineEnd,
+    /// `o`: on a new line below.
+    LineBelow,
+    /// `O`: on a new line above.
+    LineAbove,
+}
+
+/// A complete command typed in normal or visual mode, for the view to carry out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Moves the caret; in visual mode, extends the selection.
+    Move { motion: Motion, count: usize },
+    /// Applies an operator to the text between the caret and where the motion goes.
+    Operate { operator: Operator, motion: Motion, count: usize },
+    /// Applies an operator to `count` whole lines from the caret's (`dd`, `cc`, `yy`).
+    OperateLines { operator: Operator, count: usize },
+    /// Applies an operator to the visual selection.
+    OperateSelection(Operator),
+    /// `x`: deletes characters from the caret, within the line.
+    DeleteChars(usize),
+    /// `p`, or `P` to put the register's text before the caret.
+    Put { before: bool, count: usize },
+    /// Starts insert mode.
+    Insert(InsertAt),
+    /// Esc in insert mode.
+    EndInsert,
+    /// `v`
+    StartVisual,
+    /// `v` or Esc in visual mode.
+    EndVisual,
+    /// `u`
+    Undo(usize),
+    /// Ctrl+R
+    Redo(usize),
+}
+
+/// Text held in a register.
+#[derive(Clone, Debug)]
+pub struct Register {
+    pub text: String,
+    /// Set for whole lines, which are put above or below the caret's line
+    /// rather than at the caret. The text then ends with a line break.
+    pub linewise: bool,
+}
+
+/// What a key means to the vi key bindings, before the count and the
+/// operator typed with it make it an action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViCommand {
+    /// `G` is bound to `LastLine` and `gg` to `Line(1)`; a count gives the line.
+    Motion(Motion),
+    Operator(Operator),
+    /// `x`
+    DeleteChars,
+    /// `p`, or `P` to put before the caret.
+    Put { before: bool },
+    Insert(InsertAt),
+    /// `v`: starts visual mode, or leaves it.
+    Visual,
+    Undo,
+    Redo,
+    /// `"`: the next key names the register for the command.
+    Register,
+    /// Esc: back to normal mode, or cancels a partly typed command.
+    Escape,
+}
+
+/// Returns the vi key bindings. Digits typed before a command are its count.
+pub fn keymap() -> Keymap<ViCommand, char> {
+    Keymap::new(vec![
+        Binding::new(&['i'], ViCommand::Insert(InsertAt::BeforeCaret), "Insert before the caret"),
+        Binding::new(&['a'], ViCommand::Insert(InsertAt::AfterCaret), "Insert after the caret"),
+        Binding::new(&['I'], ViCommand::Insert(InsertAt::LineStart), "Insert at the start of the line"),
+        Binding::new(&['A'], ViCommand::Insert(InsertAt::LineEnd), "Insert at the end of the line"),
+        Binding::new(&['o'], ViCommand::Insert(InsertAt::LineBelow), "Open a line below"),
+        Binding::new(&['O'], ViCommand::Insert(InsertAt::LineAbove), "Open a line above"),
+        Binding::new(&['v'], ViCommand::Visual, "Start or leave visual mode"),
+        Binding::new(&['\x1b'], ViCommand::Escape, "Back to normal mode, or cancel a command"),
+        Binding::new(&['h'], ViCommand::Motion(Motion::Left), "Move a character left"),
+        Binding::new(&['l'], ViCommand::Motion(Motion::Right), "Move a character right"),
+        Binding::new(&[' '], ViCommand::Motion(Motion::Right), "Move a character right"),
+        Binding::new(&['k'], ViCommand::Motion(Motion::Up), "Move a line up"),
+        Binding::new(&['j'], ViCommand::Motion(Motion::Down), "Move a line down"),
+        Binding::new(&['\r'], ViCommand::Motion(Motion::Down), "Move a line down"),
+        Binding::new(&['w'], ViCommand::Motion(Motion::WordForward), "Move to the start of the next word"),
+        Binding::new(&['b'], ViCommand::Motion(Motion::WordBackward), "Move to the start of the word"),
+        Binding::new(&['e'], ViCommand::Motion(Motion::WordEnd), "Move to the end of the word"),
+        Binding::new(&['0'], ViCommand::Motion(Motion::LineStart), "Move to the start of the line"),
+        Binding::new(&['^'], ViCommand::Motion(Motion::FirstNonBlank), "Move to the first non-blank character"),
+        Binding::new(&['$'], ViCommand::Motion(Motion::LineEnd), "Move to the end of the line"),
+        Binding::new(&['g', 'g'], ViCommand::Motion(Motion::Line(1)), "Move to the first line, or the line of the count"),
+        Binding::new(&['G'], ViCommand::Motion(Motion::LastLine), "Move to the last line, or the line of the count"),
+        Binding::new(&['d'], ViCommand::Operator(Operator::Delete), "Delete to the motion, whole lines (dd) or the selection"),
+        Binding::new(&['c'], ViCommand::Operator(Operator::Change), "Change to the motion, whole lines (cc) or the selection"),
+        Binding::new(&['y'], ViCommand::Operator(Operator::Yank), "Yank to the motion, whole lines (yy) or the selection"),
+        Binding::new(&['x'], ViCommand::DeleteChars, "Delete the character under the caret, or the selection"),
+        Binding::new(&['p'], ViCommand::Put { before: false }, "Put after the caret"),
+        Binding::new(&['P'], ViCommand::Put { before: true }, "Put before the caret"),
+        Binding::new(&['u'], ViCommand::Undo, "Undo"),
+        Binding::new(&['\x12'], ViCommand::Redo, "Redo"), // Ctrl+R
+        Binding::new(&['"'], ViCommand::Register, "Use a register for the next command; + and * are the clipboard"),
+    ])
+}
+
+/// The part of a command typed so far.
+#[derive(Default)]
+struct Pending {
+    count: Option<usize>,
+    /// The operator waiting for its motion, with the count typed before it.
+    operator: Option<(Operator, Option<usize>)>,
+    /// Set after `"`, waiting for the register name.
+    awaiting_register: bool,
+    register: Option<char>,
+}
+
+/// Turns keys typed in normal and visual mode into actions, and holds the
+/// mode and the registers.
+pub struct ViState {
+    mode: Mode,
+    keymap: Keymap<ViCommand, char>,
+    pending: Pending,
+    /// The register named for the last action returned.
+    register: char,
+    registers: HashMap<char, Register>,
+}
+
+impl ViState {
+    /// Creates the state for a view that just turned vi key bindings on, in normal mode.
+    pub fn new() -> Self {
+        ViState {
+            mode: Mode::Normal,
+            keymap: keymap(),
+            pending: Pending::default(),
+            register: UNNAMED_REGISTER,
+            registers: HashMap::new(),
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Returns the register the last action applies to, e.g. `+` for the clipboard.
+    pub fn register_name(&self) -> char {
+        self.register
+    }
+
+    /// Handles a character typed in normal or visual mode, or Esc in insert
+    /// mode. Returns the action once a command is complete, or None while it is
+    /// still being typed or when the key means nothing.
+    pub fn handle_char(&mut self, ch: char) -> Option<Action> {
+        if self.pending.awaiting_register {
+            self.pending.awaiting_register = false;
+            if ch == '\x1b' {
+                return self.cancel();
+            }
+            self.pending.register = Some(ch);
+            return None;
+        }
+        // 0 is a motion unless it continues a count; digits don't continue `g`
+        let continues_count = self.pending.count.is_some();
+        let digit = ch.to_digit(10).filter(|&digit| digit > 0 || continues_count);
+        if let Some(digit) = digit.filter(|_| !self.keymap.is_pending()) {
+            let count = self.pending.count.unwrap_or(0);
+            self.pending.count = Some(count.saturating_mul(10).saturating_add(digit as usize).min(MAX_COUNT));
+            return None;
+        }
+        let command = match self.keymap.press(ch) {
+            Lookup::Found(command) => command,
+            Lookup::Pending => return None,
+            Lookup::Undefined | Lookup::Unbound => return self.cancel(),
+        };
+        if command == ViCommand::Escape {
+            return self.escape();
+        }
+        if self.mode == Mode::Insert {
+            return None;
+        }
+
+        let count = self.pending.count.unwrap_or(1);
+        let operator = match command {
+            ViCommand::Motion(motion) => {
+                // `G` and `gg` go to the line of the count rather than repeating
+                let motion = match motion {
+                    Motion::LastLine | Motion::Line(_) => self.pending.count.take().map_or(motion, Motion::Line),
+                    motion => motion,
+                };
+                return self.motion(motion);
+            }
+            ViCommand::Operator(operator) => operator,
+            ViCommand::Register if self.pending.operator.is_none() => {
+                self.pending.awaiting_register = true;
+                return None;
+            }
+            _ if self.pending.operator.is_some() => return self.cancel(),
+            command => {
+                let action = match (self.mode, command) {
+                    (Mode::Visual, ViCommand::DeleteChars) => Action::OperateSelection(Operator::Delete),
+                    (Mode::Visual, ViCommand::Visual) => Action::EndVisual,
+                    (Mode::Visual, _) => return self.cancel(),
+                    (_, ViCommand::DeleteChars) => Action::DeleteChars(count),
+                    (_, ViCommand::Put { before }) => Action::Put { before, count },
+                    (_, ViCommand::Insert(at)) => Action::Insert(at),
+                    (_, ViCommand::Visual) => Action::StartVisual,
+                    (_, ViCommand::Undo) => Action::Undo(count),
+                    (_, ViCommand::Redo) => Action::Redo(count),
+                    _ => return self.cancel(),
+                };
+                return self.finish(action);
+            }
+        };
+
+        if self.mode == Mode::Visual {
+            return self.finish(Action::OperateSelection(operator));
+        }
+        match self.pending.operator {
+            // Doubled, as in `dd`, the operator applies to whole lines
+            Some((pending, operator_count)) if pending == operator => {
+                let count = count.saturating_mul(operator_count.unwrap_or(1)).min(MAX_COUNT);
+                self.finish(Action::OperateLines { operator, count })
+            }
+            Some(_) => self.cancel(),
+            None => {
+                self.pending.operator = Some((operator, self.pending.count.take()));
+                None
+            }
+        }
+    }
+
+    /// Handles Esc: leaves insert or visual mode, or abandons a partly typed command.
+    fn escape(&mut self) -> Option<Action> {
+        match self.mode {
+            Mode::Insert => self.finish(Action::EndInsert),
+            Mode::Visual => self.finish(Action::EndVisual),
+            Mode::Normal => self.cancel(),
+        }
+    }
+
+    /// Completes a command with a motion: an operator's target, or a caret movement.
+    fn motion(&mut self, motion: Motion) -> Option<Action> {
+        let count = self.pending.count.unwrap_or(1);
+        let action = match self.pending.operator {
+            Some((operator, operator_count)) => {
+                let count = count.saturating_mul(operator_count.unwrap_or(1)).min(MAX_COUNT);
+                Action::Operate { operator, motion, count }
+            }
+            None => Action::Move { motion, count },
+        };
+        self.finish(action)
+    }
+
+    /// Switches to the mode the action leaves the view in and starts a new command.
+    fn finish(&mut self, action: Action) -> Option<Action> {
+        self.mode = match action {
+            Action::Insert(_)
+            | Action::Operate { operator: Operator::Change, .. }
+            | Action::OperateLines { operator: Operator::Change, .. }
+            | Action::OperateSelection(Operator::Change) => Mode::Insert,
+            Action::StartVisual => Mode::Visual,
+            Action::EndInsert | Action::EndVisual | Action::OperateSelection(_) => Mode::Normal,
+            _ => self.mode,
+        };
+        self.register = self.pending.register.unwrap_or(UNNAMED_REGISTER);
+        self.pending = Pending::default();
+        Some(action)
+    }
+
+    fn cancel(&mut self) -> Option<Action> {
+        self.pending = Pending::default();
+        self.keymap.reset();
+        None
+    }
+
+    /// Stores deleted or yanked text in the register named for the last action.
+    /// An uppercase name appends to the lowercase register. The unnamed register
+    /// always gets a copy.
+    pub fn store(&mut self, text: String, linewise: bool) {
+        let name = self.register.to_ascii_lowercase();
+        let register = if self.register.is_ascii_uppercase() {
+            match self.registers.remove(&name) {
+                Some(Register { text: previous, linewise: previous_linewise }) => Register {
+                    text: previous + &text,
+                    linewise: linewise || previous_linewise,
+                },
+                None => Register { text, linewise },
+            }
+        } else {
+            Register { text, linewise }
+        };
+        if name != UNNAMED_REGISTER {
+            self.registers.insert(UNNAMED_REGISTER, register.clone());
+        }
+        self.registers.insert(name, register);
+    }
+
+    /// Returns the register named for the last action.
+    pub fn fetch(&self) -> Option<&Register> {
+        self.registers.get(&self.register.to_ascii_lowercase())
+    }
+}
+
+/// Returns where `motion` moves the caret at `offset`, repeated `count` times.
+/// Left and Right stay within the line; Up and Down keep the byte column.
+pub fn motion_target(document: &TextDocument, offset: usize, motion: Motion, count: usize) -> usize {
+    let content = document.get_content();
+    let (line, column) = document.offset_to_position(offset);
+    let line_text = document.getline(line).unwrap_or("");
+    let line_start = offset - column;
+    let last_line = document.line_count() - 1;
+    match motion {
+        Motion::Left => {
+            let column = line_text[..column.min(line_text.len())]
+                .char_indices()
+                .rev()
+                .take(count)
+                .last()
+                .map_or(column, |(i, _)| i);
+            line_start + column
+        }
+        Motion::Right => {
+            let rest = &line_text[column.min(line_text.len())..];
+            let moved = rest.char_indices().nth(count).map_or(rest.len(), |(i, _)| i);
+            offset + moved
+        }
+        Motion::Up => document.position_to_offset(line.saturating_sub(count), column),
+        Motion::Down => document.position_to_offset(line.saturating_add(count).min(last_line), column),
+        Motion::WordForward => (0..count).fold(offset, |offset, _| next_word_start(content, offset)),
+        Motion::WordBackward => (0..count).fold(offset, |offset, _| previous_word_start(content, offset)),
+        Motion::WordEnd => (0..count).fold(offset, |offset, _| word_end(content, offset)),
+        Motion::LineStart => line_start,
+        Motion::FirstNonBlank => first_non_blank(document, line),
+        Motion::LineEnd => {
+            let line = line.saturating_add(count.saturating_sub(1)).min(last_line);
+            let text = document.getline(line).unwrap_or("");
+            let last_char = text.chars().next_back().map_or(0, char::len_utf8);
+            document.position_to_offset(line, text.len() - last_char)
+        }
+        Motion::Line(number) => first_non_blank(document, number.clamp(1, last_line + 1) - 1),
+        Motion::LastLine => first_non_blank(document, last_line),
+    }
+}
+
+/// The text an operator applies to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// A byte range of the document.
+    Chars(Range<usize>),
+    /// Whole lines, the first and the last.
+    Lines(usize, usize),
+}
+
+/// Returns the text an operator with `motion` applies to from `offset`.
+pub fn operator_target(document: &TextDocument, offset: usize, operator: Operator, motion: Motion, count: usize) -> Target {
+    let content = document.get_content();
+    // As in vi, `cw` on a word changes only to the end of the word, not the blanks after it
+    let on_blank = content[offset..].chars().next().is_none_or(char::is_whitespace);
+    let motion = if operator == Operator::Change && motion == Motion::WordForward && !on_blank {
+        Motion::WordEnd
+    } else {
+        motion
+    };
+    let target = motion_target(document, offset, motion, count);
+    if motion.linewise() {
+        let first = document.line_from_offset(offset.min(target));
+        let last = document.line_from_offset(offset.max(target));
+        return Target::Lines(first, last);
+    }
+
+    let start = offset.min(target);
+    let mut end = offset.max(target);
+    if motion.inclusive() {
+        end += content[end..].chars().next().filter(|&ch| ch != '\r' && ch != '\n').map_or(0, char::len_utf8);
+    } else {
+        // An exclusive motion ending at the start of a later line stops at the end
+        // of the line before, and `w` stops at the end of the line of the last word,
+        // so `dw` on the last word of a line keeps the line break
+        let (end_line, end_column) = document.offset_to_position(end);
+        if (end_column == 0 || motion == Motion::WordForward) && end_line > document.line_from_offset(start) {
+            end = document.position_to_offset(end_line - 1, usize::MAX).max(start);
+        }
+    }
+    Target::Chars(start..end)
+}
+
+/// Returns the range of whole lines `first` to `last`, with their line breaks.
+/// If the last line of the document is included, which has no line break, the
+/// one before the lines is taken instead, so deleting them leaves no empty line.
+pub fn line_range(document: &TextDocument, first: usize, last: usize) -> Range<usize> {
+    let start = document.line_start(first).unwrap_or(document.len());
+    match document.line_start(last + 1) {
+        Some(next) => start..next,
+        None if first > 0 => document.position_to_offset(first - 1, usize::MAX)..document.len(),
+        None => start..document.len(),
+    }
+}
+
+/// Returns the offset of the first non-blank character of `line`, or of its end.
+pub fn first_non_blank(document: &TextDocument, line: usize) -> usize {
+    let text = document.getline(line).unwrap_or("");
+    let indent = text.len() - text.trim_start_matches([' ', '\t']).len();
+    document.position_to_offset(line, indent)
+}
+
+/// Characters of one class next to each other form a word.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn char_class(ch: char) -> CharClass {
+    if ch.is_whitespace() {
+        CharClass::Blank
+    } else if ch.is_alphanumeric() || ch == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+/// Returns the start of the word after the one at `offset`, or the end of the text.
+/// An empty line counts as a word.
+fn next_word_start(text: &str, offset: usize) -> usize {
+    let mut chars = text[offset..].char_indices().map(|(i, ch)| (offset + i, ch)).peekable();
+    let Some(&(_, first)) = chars.peek() else {
+        return text.len();
+    };
+    let class = char_class(first);
+    if class != CharClass::Blank {
+        while chars.next_if(|&(_, ch)| char_class(ch) == class).is_some() {}
+    }
+    while let Some((_, ch)) = chars.next_if(|&(_, ch)| char_class(ch) == CharClass::Blank) {
+        if ch == '\n' && chars.peek().is_some_and(|&(_, next)| next == '\r' || next == '\n') {
+            break;
+        }
+    }
+    chars.peek().map_or(text.len(), |&(i, _)| i)
+}
+
+/// Returns the start of the word before `offset`, which may be the one `offset` is in.
+fn previous_word_start(text: &str, offset: usize) -> usize {
+    let mut chars = text[..offset].char_indices().rev().map(|(i, ch)| (i, char_class(ch))).peekable();
+    while chars.next_if(|&(_, class)| class == CharClass::Blank).is_some() {}
+    let Some(&(mut start, class)) = chars.peek() else {
+        return 0;
+    };
+    while let Some((i, _)) = chars.next_if(|&(_, next)| next == class) {
+        start = i;
+    }
+    start
+}
+
+/// Returns the last character of the word after `offset`, which may be the one
+/// `offset` is in, or `offset` if there is none.
+fn word_end(text: &str, offset: usize) -> usize {
+    let mut chars = text[offset..].char_indices().skip(1).map(|(i, ch)| (offset + i, char_class(ch))).peekable();
+    while chars.next_if(|&(_, class)| class == CharClass::Blank).is_some() {}
+    let Some(&(mut end, class)) = chars.peek() else {
+        return offset;
+    };
+    while let Some((i, _)) = chars.next_if(|&(_, next)| next == class) {
+        end = i;
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Types `keys` in normal mode and returns the last action they complete.
+    fn type_keys(vi: &mut ViState, keys: &str) -> Option<Action> {
+        keys.chars().fold(None, |last, ch| vi.handle_char(ch).or(last))
+    }
+
+    #[test]
+    fn delete_word_at_end_of_line_keeps_line_break() {
+        let document = TextDocument::from_text("one two\r\nthree");
+        assert_eq!(operator_target(&document, 4, Operator::Delete, Motion::WordForward, 1), Target::Chars(4..7));
+        assert_eq!(operator_target(&document, 0, Operator::Delete, Motion::WordForward, 1), Target::Chars(0..4));
+    }
+
+    #[test]
+    fn change_word_stops_at_end_of_word() {
+        let document = TextDocument::from_text("one  two");
+        assert_eq!(operator_target(&document, 0, Operator::Change, Motion::WordForward, 1), Target::Chars(0..3));
+        assert_eq!(operator_target(&document, 1, Operator::Change, Motion::WordForward, 2), Target::Chars(1..8));
+        // On blanks, `cw` changes the blanks like `dw`
+        assert_eq!(operator_target(&document, 3, Operator::Change, Motion::WordForward, 1), Target::Chars(3..5));
+    }
+
+    #[test]
+    fn counts_multiply_and_are_capped() {
+        let mut vi = ViState::new();
+        assert_eq!(type_keys(&mut vi, "2d3w"), Some(Action::Operate { operator: Operator::Delete, motion: Motion::WordForward, count: 6 }));
+        assert_eq!(type_keys(&mut vi, "3dd"), Some(Action::OperateLines { operator: Operator::Delete, count: 3 }));
+        assert_eq!(type_keys(&mut vi, "10x"), Some(Action::DeleteChars(10)));
+        assert_eq!(type_keys(&mut vi, "99999999999999999999999u"), Some(Action::Undo(MAX_COUNT)));
+        assert_eq!(type_keys(&mut vi, "99999d99999w"), Some(Action::Operate { operator: Operator::Delete, motion: Motion::WordForward, count: MAX_COUNT }));
+        assert_eq!(type_keys(&mut vi, "5G"), Some(Action::Move { motion: Motion::Line(5), count: 1 }));
+    }
+
+    #[test]
+    fn key_sequences_and_escape() {
+        let mut vi = ViState::new();
+        assert_eq!(type_keys(&mut vi, "gg"), Some(Action::Move { motion: Motion::Line(1), count: 1 }));
+        assert_eq!(type_keys(&mut vi, "G"), Some(Action::Move { motion: Motion::LastLine, count: 1 }));
+        // Esc drops the operator, so the motion only moves
+        assert_eq!(type_keys(&mut vi, "d\x1bw"), Some(Action::Move { motion: Motion::WordForward, count: 1 }));
+        // A key that completes no sequence drops the prefix
+        assert_eq!(type_keys(&mut vi, "gqj"), Some(Action::Move { motion: Motion::Down, count: 1 }));
+        assert_eq!(type_keys(&mut vi, "i"), Some(Action::Insert(InsertAt::BeforeCaret)));
+        assert_eq!(vi.handle_char('\x1b'), Some(Action::EndInsert));
+        assert_eq!(vi.mode(), Mode::Normal);
+    }
+
+    #[test]
+    fn large_counts_stop_at_the_document_end() {
+        let document = TextDocument::from_text("one\ntwo\nthree");
+        assert_eq!(motion_target(&document, 0, Motion::Down, usize::MAX), 8);
+        assert_eq!(motion_target(&document, 0, Motion::LineEnd, usize::MAX), 12);
+        assert_eq!(motion_target(&document, 0, Motion::WordForward, MAX_COUNT), 13);
+        assert_eq!(motion_target(&document, 0, Motion::Right, usize::MAX), 3);
+    }
+
+    #[test]
+    fn uppercase_register_appends() {
+        let mut vi = ViState::new();
+        type_keys(&mut vi, "\"ayy");
+        vi.store("one\n".to_string(), true);
+        type_keys(&mut vi, "\"Ayy");
+        vi.store("two\n".to_string(), true);
+        assert_eq!(vi.register_name(), 'A');
+        let register = vi.fetch().unwrap();
+        assert_eq!(register.text, "one\ntwo\n");
+        assert!(register.linewise);
+
+        // The unnamed register gets a copy of the whole register
+        type_keys(&mut vi, "p");
+        assert_eq!(vi.fetch().unwrap().text, "one\ntwo\n");
+    }
+
+    #[test]
+    fn delete_last_line_takes_line_break_before_it() {
+        let three_lines = TextDocument::from_text("one\ntwo\nthree");
+        assert_eq!(line_range(&three_lines, 2, 2), 7..13);
+        assert_eq!(line_range(&three_lines, 1, 2), 3..13);
+        assert_eq!(line_range(&three_lines, 0, 0), 0..4);
+        assert_eq!(line_range(&TextDocument::from_text("only"), 0, 0), 0..4);
+    }
+}
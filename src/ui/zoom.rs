@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Zoom level of a document without an explicit zoom, in percent.
+pub const DEFAULT_ZOOM_PERCENT: u32 = 100;
+const MIN_ZOOM_PERCENT: u32 = 50;
+const MAX_ZOOM_PERCENT: u32 = 400;
+const ZOOM_STEP_PERCENT: u32 = 10;
+
+/// Zoom levels of an editor window. Each document can have its own zoom,
+/// falling back to the window default; with `sync_all` set, one zoom level
+/// applies to every document shown in the window.
+pub struct ZoomSettings {
+    window_default: u32,
+    per_document: HashMap<PathBuf, u32>,
+    sync_all: bool,
+}
+
+impl ZoomSettings {
+    pub fn new() -> Self {
+        ZoomSettings {
+            window_default: DEFAULT_ZOOM_PERCENT,
+            per_document: HashMap::new(),
+            sync_all: false,
+        }
+    }
+
+    /// Returns the zoom level of the document at `path` (None for untitled documents).
+    pub fn zoom_for(&self, path: Option<&Path>) -> u32 {
+        if self.sync_all {
+            return self.window_default;
+        }
+        path.and_then(|path| self.per_document.get(path))
+            .copied()
+            .unwrap_or(self.window_default)
+    }
+
+    /// Zooms the document at `path` in (positive `steps`) or out, or back to
+    /// the default for `steps == 0`. Returns the new zoom level.
+    pub fn zoom(&mut self, path: Option<&Path>, steps: i32) -> u32 {
+        let percent = if steps == 0 {
+            DEFAULT_ZOOM_PERCENT
+        } else {
+            let current = self.zoom_for(path) as i32;
+            // Steps come from window messages and can be arbitrarily large
+            let change = steps.saturating_mul(ZOOM_STEP_PERCENT as i32);
+            current.saturating_add(change).clamp(MIN_ZOOM_PERCENT as i32, MAX_ZOOM_PERCENT as i32) as u32
+        };
+        match path {
+            // Untitled documents have nothing to remember their zoom by
+            Some(path) if !self.sync_all => {
+                self.per_document.insert(path.to_path_buf(), percent);
+            }
+            _ => self.window_default = percent,
+        }
+        percent
+    }
+
+    /// Returns whether one zoom level applies to all documents.
+    pub fn sync_all(&self) -> bool {
+        self.sync_all
+    }
+
+    /// Turns synchronized zoom on or off. Turning it on keeps the zoom of the
+    /// document at `path` as the zoom of every document.
+    pub fn set_sync_all(&mut self, sync_all: bool, path: Option<&Path>) {
+        if sync_all && !self.sync_all {
+            self.window_default = self.zoom_for(path);
+            self.per_document.clear();
+        }
+        self.sync_all = sync_all;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_beyond_the_range_stop_at_its_ends() {
+        let mut zoom = ZoomSettings::new();
+        let path = Path::new("a.txt");
+        assert_eq!(zoom.zoom(Some(path), 2), 120);
+        assert_eq!(zoom.zoom(Some(path), i32::MAX), MAX_ZOOM_PERCENT);
+        assert_eq!(zoom.zoom(Some(path), i32::MIN), MIN_ZOOM_PERCENT);
+        assert_eq!(zoom.zoom_for(None), DEFAULT_ZOOM_PERCENT);
+    }
+}